@@ -0,0 +1,1050 @@
+use crate::error::MusicalDataError;
+use crate::key_signature::{fifths_from_key_name, key_name_from_fifths};
+use crate::melody;
+use crate::prelude::{Chord, Meter, NoteMod, NoteName, Positionable, Song, SongChord, SongNote};
+use crate::repeats::{unroll, RepeatMarks};
+
+/// Letters in the order sharps are added to a key signature.
+const SHARP_ORDER : &str = "FCGDAEB";
+/// The most slashes in a note length, "A///////" is a 256th note.
+const MAX_LENGTH_SLASHES : usize = 8;
+
+/// The abbreviations ABC uses for modes.
+const MODES : [(&str, &str); 10] = [
+    ("maj", "major"),
+    ("ion", "ionian"),
+    ("min", "minor"),
+    ("m", "minor"),
+    ("aeo", "aeolian"),
+    ("dor", "dorian"),
+    ("phr", "phrygian"),
+    ("lyd", "lydian"),
+    ("mix", "mixolydian"),
+    ("loc", "locrian"),
+];
+
+impl Song {
+    /// Read all tunes of an ABC file. Every tune starts with an `X:` field. Repeats and
+    /// endings are unrolled, so the returned songs contain the chords and notes in
+    /// playback order. Only the first voice of multi voice tunes is read.
+    pub fn from_abc(input : &str) -> Result<Vec<Song>, MusicalDataError> {
+        let mut songs = Vec::new();
+        let mut tune : Option<TuneReader> = None;
+
+        for (number, line) in input.lines().enumerate() {
+            let line_number = number + 1;
+            if line.starts_with("X:") {
+                if let Some(t) = tune.take() {
+                    songs.push(t.finish());
+                }
+                tune = Some(TuneReader::new());
+                continue;
+            }
+
+            // A blank line ends the tune.
+            if line.trim().is_empty() {
+                if let Some(t) = tune.take() {
+                    songs.push(t.finish());
+                }
+                continue;
+            }
+
+            if let Some(t) = tune.as_mut() {
+                t.read_line(line).map_err(|msg| MusicalDataError::InvalidAbc(format!("{} (line {})", msg, line_number)))?;
+            }
+        }
+        if let Some(t) = tune.take() {
+            songs.push(t.finish());
+        }
+
+        Ok(songs)
+    }
+
+    /// Write this song as an ABC tune with the given reference number. The unit note
+    /// length is an eighth note, the body contains the chord symbols and the melody.
+    pub fn to_abc(&self, reference_number : u64) -> String {
+        let meta = self.get_song_meta();
+        let settings = self.get_song_settings();
+
+        let mut out = format!("X:{}\n", reference_number);
+        out.push_str(&format!("T:{}\n", meta.get_title().map(|t| t.as_str()).unwrap_or("Untitled")));
+        let fields = [("C", meta.get_composer()), ("B", meta.get_album()), ("R", meta.get_genre()), ("N", meta.get_comment())];
+        for (field, value) in fields {
+            if let Some(value) = value {
+                out.push_str(&format!("{}:{}\n", field, value));
+            }
+        }
+        out.push_str(&format!("M:{}\n", settings.get_meter_at(0)));
+        out.push_str("L:1/8\n");
+        out.push_str(&format!("Q:1/4={}\n", settings.get_tempo().round()));
+
+        let key = fifths_from_key_name(settings.get_key_signature());
+        let tonic = settings.get_key_signature().split_whitespace().next().unwrap_or("C");
+        let mode = match key.as_ref().map(|(_, m)| m.as_str()) {
+            None | Some("major") => String::new(),
+            Some("minor") => String::from("m"),
+            Some(m) => {
+                let mut chars = m.chars();
+                chars.next().map(|c| c.to_ascii_uppercase()).into_iter().chain(chars.take(2)).collect()
+            },
+        };
+        out.push_str(&format!("K:{}{}\n", tonic, mode));
+
+        let fifths = key.map(|(f, _)| f).unwrap_or(0);
+        out.push_str(&BodyWriter::new(self, fifths).write());
+        out
+    }
+}
+
+/// A fraction of the unit note length.
+type Length = (u64, u64);
+
+fn gcd(a : u64, b : u64) -> u64 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+fn multiply(a : Length, b : Length) -> Length {
+    let (n, d) = (a.0 * b.0, a.1 * b.1);
+    let g = gcd(n, d).max(1);
+    (n / g, d / g)
+}
+
+/// The sharps (positive) or flats (negative) every letter carries in a key signature.
+fn key_alters(fifths : i64) -> [i64; 7] {
+    let mut alters = [0; 7];
+    for (i, letter) in SHARP_ORDER.chars().enumerate() {
+        let index = letter_index(letter);
+        if (i as i64) < fifths {
+            alters[index] = 1;
+        }
+        if (6 - i as i64) < -fifths {
+            alters[index] = -1;
+        }
+    }
+
+    alters
+}
+
+fn letter_index(letter : char) -> usize {
+    "CDEFGAB".find(letter.to_ascii_uppercase()).unwrap_or(0)
+}
+
+/// A note, chord or rest of the tune body with the chord symbols written in front of it.
+#[derive(Default, Clone)]
+struct Item {
+    pitches : Vec<u8>,
+    length : Length,
+    tie : bool,
+    chords : Vec<Chord>,
+}
+
+#[derive(Default, Clone)]
+struct Bar {
+    items : Vec<Item>,
+    repeat : RepeatMarks,
+    /// A meter change in the tune body, starting with this bar.
+    meter : Option<Meter>,
+    /// Tempo changes in the tune body in quarter notes per minute, before the item at
+    /// the index.
+    tempos : Vec<(usize, f64)>,
+}
+
+struct TuneReader {
+    song : Song,
+    in_header : bool,
+    title_read : bool,
+    meter : Length,
+    unit : Option<Length>,
+    key_alters : [i64; 7],
+    bar_accidentals : Vec<((char, i64), i64)>,
+    bars : Vec<Bar>,
+    current : Bar,
+    pending_chords : Vec<Chord>,
+    /// Factor and remaining notes of the current tuplet.
+    tuplet : Option<(Length, u64)>,
+    /// Factor applied to the next note by broken rhythm.
+    broken : Option<Length>,
+    first_voice : Option<String>,
+    skip_voice : bool,
+}
+
+impl TuneReader {
+    fn new() -> Self {
+        TuneReader {
+            song : Song::new(),
+            in_header : true,
+            title_read : false,
+            meter : (4, 4),
+            unit : None,
+            key_alters : [0; 7],
+            bar_accidentals : Vec::new(),
+            bars : Vec::new(),
+            current : Bar::default(),
+            pending_chords : Vec::new(),
+            tuplet : None,
+            broken : None,
+            first_voice : None,
+            skip_voice : false,
+        }
+    }
+
+    fn unit(&self) -> Length {
+        // The default unit note length depends on the meter.
+        self.unit.unwrap_or(if self.meter.0 * 4 < self.meter.1 * 3 { (1, 16) } else { (1, 8) })
+    }
+
+    fn read_line(&mut self, line : &str) -> Result<(), String> {
+        if line.starts_with("%%") {
+            return Ok(());
+        }
+        let line = line.split('%').next().unwrap_or_default();
+        let bytes = line.as_bytes();
+        if bytes.len() >= 2 && bytes[1] == b':' && bytes[0].is_ascii_alphabetic() {
+            return self.read_field(bytes[0] as char, line[2..].trim());
+        }
+
+        self.in_header = false;
+        if self.skip_voice {
+            return Ok(());
+        }
+        self.read_body(line)
+    }
+
+    fn read_field(&mut self, field : char, value : &str) -> Result<(), String> {
+        let meta = self.song.get_song_meta_mut();
+        match field {
+            'T' if !self.title_read => {
+                meta.set_title(Some(String::from(value)));
+                self.title_read = true;
+            },
+            'C' if self.in_header => meta.set_composer(Some(String::from(value))),
+            'B' if self.in_header => meta.set_album(Some(String::from(value))),
+            'R' if self.in_header => meta.set_genre(Some(String::from(value))),
+            'N' if self.in_header => meta.set_comment(Some(String::from(value))),
+            'M' => self.read_meter(value)?,
+            'L' => {
+                let (n, d) = value.split_once('/').ok_or(format!("invalid unit note length '{}'", value))?;
+                let n = read_length_number(n.trim()).map_err(|_| format!("invalid unit note length '{}'", value))?;
+                let d = read_length_number(d.trim()).map_err(|_| format!("invalid unit note length '{}'", value))?;
+                self.unit = Some((n, d));
+            },
+            'Q' => self.read_tempo(value)?,
+            'K' => {
+                self.read_key(value)?;
+                self.in_header = false;
+            },
+            'V' => {
+                let id = String::from(value.split_whitespace().next().unwrap_or_default());
+                match &self.first_voice {
+                    Some(first) => self.skip_voice = *first != id,
+                    None => self.first_voice = Some(id),
+                }
+            },
+            _ => {},
+        }
+
+        Ok(())
+    }
+
+    fn read_meter(&mut self, value : &str) -> Result<(), String> {
+        let meter = match value {
+            "C" => Meter::new(4, 4),
+            "C|" => Meter::new(2, 2),
+            "none" | "" => return Ok(()),
+            // Additive meters like (2+2+3)/8 keep their beat grouping.
+            _ => value.parse::<Meter>().map_err(|_| format!("invalid meter '{}'", value))?,
+        };
+
+        self.meter = (meter.get_numerator(), meter.get_denominator());
+        match self.in_header {
+            true => self.song.get_song_settings_mut().set_meter(meter),
+            false => self.current.meter = Some(meter),
+        }
+
+        Ok(())
+    }
+
+    fn read_tempo(&mut self, value : &str) -> Result<(), String> {
+        // Skip text like "Allegro" in quotes.
+        let value = match value.rfind('"') {
+            Some(i) => value[i + 1..].trim(),
+            None => value,
+        };
+        if value.is_empty() {
+            return Ok(());
+        }
+
+        let invalid = || format!("invalid tempo '{}'", value);
+        let (beat, bpm) = match value.split_once('=') {
+            Some((beat, bpm)) => {
+                let mut length = (0, 1);
+                for unit in beat.split_whitespace() {
+                    let (n, d) = unit.split_once('/').ok_or_else(invalid)?;
+                    let n = n.parse::<u16>().map_err(|_| invalid())? as u64;
+                    let d = d.parse::<u16>().map_err(|_| invalid())? as u64;
+                    length = (length.0 * d + n * length.1, length.1 * d);
+                }
+                (length, bpm)
+            },
+            None => (self.unit(), value),
+        };
+        let bpm : f64 = bpm.trim().parse().map_err(|_| invalid())?;
+
+        // The tempo of the song is given in quarter notes per minute.
+        let tempo = bpm * 4.0 * beat.0 as f64 / beat.1 as f64;
+        if !(tempo > 0.0 && tempo.is_finite()) {
+            return Err(invalid());
+        }
+        match self.in_header {
+            true => self.song.get_song_settings_mut().set_tempo(tempo),
+            false => self.current.tempos.push((self.current.items.len(), tempo)),
+        }
+        Ok(())
+    }
+
+    fn read_key(&mut self, value : &str) -> Result<(), String> {
+        let mut tokens = value.split_whitespace();
+        let first = tokens.next().unwrap_or_default();
+        if first.is_empty() || first == "none" || first.eq_ignore_ascii_case("HP") {
+            self.key_alters = [0; 7];
+            return Ok(());
+        }
+
+        let tonic_length = 1 + first.chars().skip(1).take_while(|c| *c == '#' || *c == 'b').count();
+        let tonic = &first[..tonic_length.min(first.len())];
+        let mut mode = first[tonic.len()..].to_lowercase();
+        if mode.is_empty() {
+            if let Some(next) = tokens.next() {
+                mode = next.to_lowercase();
+            }
+        }
+        let mode = if mode.is_empty() {
+            "major"
+        } else {
+            MODES.iter()
+                .find(|(abbreviation, _)| mode.starts_with(abbreviation))
+                .map(|(_, m)| *m)
+                .ok_or(format!("unknown mode '{}'", mode))?
+        };
+
+        let (fifths, mode) = fifths_from_key_name(&format!("{} {}", tonic, mode)).ok_or(format!("invalid key '{}'", value))?;
+        self.key_alters = key_alters(fifths);
+        if self.in_header {
+            if let Some(name) = key_name_from_fifths(fifths, &mode) {
+                self.song.get_song_settings_mut().set_key_signature(name);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn read_body(&mut self, line : &str) -> Result<(), String> {
+        let chars : Vec<char> = line.chars().collect();
+        let mut i = 0;
+        while i < chars.len() {
+            let c = chars[i];
+            match c {
+                '"' => {
+                    let end = chars[i + 1..].iter().position(|c| *c == '"').ok_or("unterminated chord symbol")?;
+                    let text : String = chars[i + 1..i + 1 + end].iter().collect();
+                    // Annotations start with a placement character, other text that is not
+                    // a chord symbol like "N.C." or "(Am)" is taken as annotation as well.
+                    if !text.starts_with(['^', '_', '<', '>', '@']) {
+                        self.pending_chords.extend(text.parse::<Chord>().ok());
+                    }
+                    i += end + 2;
+                },
+                '!' | '+' => {
+                    // A lone '!' is the old line break marker and is skipped.
+                    match chars[i + 1..].iter().position(|e| *e == c) {
+                        Some(end) => i += end + 2,
+                        None if c == '!' => i += 1,
+                        None => return Err(String::from("unterminated decoration")),
+                    }
+                },
+                '{' => {
+                    let end = chars[i..].iter().position(|c| *c == '}').ok_or("unterminated grace notes")?;
+                    i += end + 1;
+                },
+                '(' if chars.get(i + 1).is_some_and(|c| c.is_ascii_digit()) => {
+                    i = self.read_tuplet(&chars, i + 1);
+                },
+                '-' => {
+                    if let Some(item) = self.current.items.last_mut() {
+                        item.tie = true;
+                    }
+                    i += 1;
+                },
+                '>' | '<' => {
+                    let count = chars[i..].iter().take_while(|e| **e == c).count() as u32;
+                    if count > 3 {
+                        return Err(format!("invalid broken rhythm '{}'", c.to_string().repeat(count as usize)));
+                    }
+                    let short = (1, 2u64.pow(count));
+                    let long = (2u64.pow(count + 1) - 1, 2u64.pow(count));
+                    let (previous, next) = if c == '>' { (long, short) } else { (short, long) };
+                    if let Some(item) = self.current.items.last_mut() {
+                        item.length = multiply(item.length, previous);
+                    }
+                    self.broken = Some(next);
+                    i += count as usize;
+                },
+                '[' if chars.get(i + 2) == Some(&':') && chars.get(i + 1).is_some_and(|c| c.is_ascii_alphabetic()) => {
+                    let end = chars[i..].iter().position(|c| *c == ']').ok_or("unterminated inline field")?;
+                    let field : String = chars[i + 3..i + end].iter().collect();
+                    self.read_field(chars[i + 1], field.trim())?;
+                    i += end + 1;
+                },
+                '[' if chars.get(i + 1).is_some_and(|c| *c == '|' || c.is_ascii_digit()) => {
+                    i = self.read_bar_line(&chars, i);
+                },
+                '|' | ':' => {
+                    i = self.read_bar_line(&chars, i);
+                },
+                '[' => {
+                    i = self.read_chord(&chars, i + 1)?;
+                },
+                'z' | 'x' => {
+                    let (length, next) = read_length(&chars, i + 1)?;
+                    self.push_item(Vec::new(), length);
+                    i = next;
+                },
+                'Z' | 'X' => {
+                    // Multi measure rests.
+                    let digits : String = chars[i + 1..].iter().take_while(|c| c.is_ascii_digit()).collect();
+                    let count = digits.parse::<u64>().unwrap_or(1);
+                    let bar = multiply(self.meter, (self.unit().1, self.unit().0));
+                    for n in 0..count {
+                        self.push_item(Vec::new(), bar);
+                        if n + 1 < count {
+                            self.end_bar(false, false);
+                        }
+                    }
+                    i += 1 + digits.len();
+                },
+                '^' | '_' | '=' | 'A'..='G' | 'a'..='g' => {
+                    let (pitch, next) = self.read_pitch(&chars, i)?;
+                    let (length, next) = read_length(&chars, next)?;
+                    self.push_item(vec![pitch], length);
+                    i = next;
+                },
+                _ => i += 1,
+            }
+        }
+
+        Ok(())
+    }
+
+    fn read_tuplet(&mut self, chars : &[char], start : usize) -> usize {
+        let mut numbers : Vec<Option<u64>> = Vec::new();
+        let mut i = start;
+        loop {
+            let digits : String = chars[i..].iter().take_while(|c| c.is_ascii_digit()).collect();
+            numbers.push(digits.parse::<u8>().ok().filter(|n| *n > 0).map(u64::from));
+            i += digits.len();
+            if chars.get(i) == Some(&':') && numbers.len() < 3 {
+                i += 1;
+            } else {
+                break;
+            }
+        }
+
+        let p = numbers[0].unwrap_or(3);
+        let q = numbers.get(1).copied().flatten().unwrap_or(match p {
+            2 | 4 | 8 => 3,
+            3 | 6 => 2,
+            // Depends on the meter, compound meters use three.
+            _ => if self.meter.1 == 8 && self.meter.0.is_multiple_of(3) { 3 } else { 2 },
+        });
+        let r = numbers.get(2).copied().flatten().unwrap_or(p);
+        self.tuplet = Some(((q, p), r));
+
+        i
+    }
+
+    /// Read a note name with accidentals and octave marks and return its midi pitch.
+    fn read_pitch(&mut self, chars : &[char], start : usize) -> Result<(u8, usize), String> {
+        let mut i = start;
+        let mut accidental : Option<i64> = None;
+        while let Some(c) = chars.get(i) {
+            match c {
+                '^' => accidental = Some(accidental.unwrap_or(0) + 1),
+                '_' => accidental = Some(accidental.unwrap_or(0) - 1),
+                '=' => accidental = Some(0),
+                _ => break,
+            }
+            i += 1;
+        }
+
+        let letter = *chars.get(i).filter(|c| c.is_ascii_alphabetic()).ok_or("expected a note")?;
+        let mut octave : i64 = if letter.is_ascii_uppercase() { 4 } else { 5 };
+        i += 1;
+        while let Some(c) = chars.get(i) {
+            match c {
+                '\'' => octave += 1,
+                ',' => octave -= 1,
+                _ => break,
+            }
+            i += 1;
+        }
+
+        let key = (letter.to_ascii_uppercase(), octave);
+        let alter = match accidental {
+            Some(a) => {
+                self.bar_accidentals.retain(|(k, _)| *k != key);
+                self.bar_accidentals.push((key, a));
+                a
+            },
+            None => self.bar_accidentals.iter()
+                .find(|(k, _)| *k == key)
+                .map(|(_, a)| *a)
+                .unwrap_or(self.key_alters[letter_index(letter)]),
+        };
+
+        let natural = NoteName::from_step(letter, NoteMod::Normal)
+            .and_then(|n| n.to_midi_pitch(octave))
+            .ok_or(format!("note '{}' out of range", letter))?;
+        let pitch = u8::try_from(natural as i64 + alter).map_err(|_| format!("note '{}' out of range", letter))?;
+
+        Ok((pitch, i))
+    }
+
+    fn read_chord(&mut self, chars : &[char], start : usize) -> Result<usize, String> {
+        let mut i = start;
+        let mut pitches = Vec::new();
+        let mut length = None;
+        while chars.get(i).is_some_and(|c| *c != ']') {
+            if chars[i].is_whitespace() || chars[i] == '-' {
+                i += 1;
+                continue;
+            }
+            let (pitch, next) = self.read_pitch(chars, i)?;
+            let (note_length, next) = read_length(chars, next)?;
+            pitches.push(pitch);
+            // The length of the chord is the length of its first note.
+            length.get_or_insert(note_length);
+            i = next;
+        }
+        if chars.get(i).is_none() {
+            return Err(String::from("unterminated chord"));
+        }
+
+        let (outer, next) = read_length(chars, i + 1)?;
+        self.push_item(pitches, multiply(length.unwrap_or((1, 1)), outer));
+
+        Ok(next)
+    }
+
+    fn push_item(&mut self, pitches : Vec<u8>, length : Length) {
+        let mut length = multiply(length, self.unit());
+        if let Some(factor) = self.broken.take() {
+            length = multiply(length, factor);
+        }
+        if let Some((factor, remaining)) = self.tuplet {
+            length = multiply(length, factor);
+            self.tuplet = if remaining > 1 { Some((factor, remaining - 1)) } else { None };
+        }
+
+        self.current.items.push(Item {
+            pitches,
+            length,
+            tie : false,
+            chords : std::mem::take(&mut self.pending_chords),
+        });
+    }
+
+    fn read_bar_line(&mut self, chars : &[char], start : usize) -> usize {
+        let mut i = start;
+        let mut symbol = String::new();
+        while let Some(c) = chars.get(i) {
+            let is_bar_char = matches!(c, '|' | ':' | ']') || (*c == '[' && chars.get(i + 1) == Some(&'|'));
+            if !is_bar_char {
+                break;
+            }
+            symbol.push(*c);
+            i += 1;
+        }
+
+        // Endings like "|1", ":|2" or "[1".
+        if chars.get(i) == Some(&'[') && chars.get(i + 1).is_some_and(|c| c.is_ascii_digit()) {
+            i += 1;
+        }
+        let mut ending = String::new();
+        while let Some(c) = chars.get(i).filter(|c| c.is_ascii_digit() || **c == ',' || **c == '-') {
+            ending.push(*c);
+            i += 1;
+        }
+
+        let end_repeat = symbol.starts_with(':');
+        let start_repeat = symbol.len() > 1 && symbol.ends_with(':');
+        let section_end = symbol.contains("||") || symbol.contains(']') || symbol.contains("[|");
+        self.end_bar(end_repeat, section_end);
+        self.current.repeat.start_repeat |= start_repeat;
+        if !ending.is_empty() {
+            self.current.repeat.ending = Some(parse_ending(&ending));
+        }
+
+        i
+    }
+
+    fn end_bar(&mut self, end_repeat : bool, section_end : bool) {
+        self.bar_accidentals.clear();
+        if self.current.items.is_empty() {
+            // Bar lines without content in between, i.e. a bar line at the start of a line.
+            if let Some(last) = self.bars.last_mut() {
+                last.repeat.end_repeat |= end_repeat;
+                last.repeat.section_end |= section_end;
+            }
+            return;
+        }
+
+        self.current.repeat.end_repeat |= end_repeat;
+        self.current.repeat.section_end |= section_end;
+        self.bars.push(std::mem::take(&mut self.current));
+    }
+
+    fn finish(mut self) -> Song {
+        self.end_bar(false, false);
+
+        let ppq = self.song.get_song_settings().get_pulses_per_quarter();
+        let whole = ppq * 4;
+        let mut notes : Vec<(u64, u64, u8)> = Vec::new();
+        let mut chords = Vec::new();
+        let mut open_ties : Vec<usize> = Vec::new();
+        let mut cursor = 0;
+
+        let marks : Vec<RepeatMarks> = self.bars.iter().map(|b| b.repeat.clone()).collect();
+        for index in unroll(&marks) {
+            if let Some(meter) = &self.bars[index].meter {
+                let settings = self.song.get_song_settings_mut();
+                if settings.get_meter_at(cursor) != *meter {
+                    settings.get_time_signature_map_mut().add_meter_change(cursor, meter.clone());
+                }
+            }
+            let bar = &self.bars[index];
+            for (item_index, item) in bar.items.iter().enumerate() {
+                for (_, tempo) in bar.tempos.iter().filter(|(at, _)| *at == item_index) {
+                    self.song.get_song_settings_mut().get_tempo_map_mut().add_change(cursor, *tempo);
+                }
+                let length = whole * item.length.0 / item.length.1;
+                chords.extend(item.chords.iter().map(|c| SongChord::new(cursor, *c)));
+
+                let mut tied = Vec::new();
+                for pitch in &item.pitches {
+                    let continued = open_ties.iter().copied()
+                        .find(|n| notes[*n].2 == *pitch && notes[*n].1 == cursor);
+                    let index = match continued {
+                        Some(n) => {
+                            notes[n].1 = cursor + length;
+                            n
+                        },
+                        None => {
+                            notes.push((cursor, cursor + length, *pitch));
+                            notes.len() - 1
+                        },
+                    };
+                    if item.tie {
+                        tied.push(index);
+                    }
+                }
+                open_ties = tied;
+                cursor += length;
+            }
+            // Tempo changes after the last item of the bar.
+            for (_, tempo) in bar.tempos.iter().filter(|(at, _)| *at >= bar.items.len()) {
+                self.song.get_song_settings_mut().get_tempo_map_mut().add_change(cursor, *tempo);
+            }
+        }
+
+        // Chord symbols at the very end of the tune.
+        chords.extend(self.pending_chords.iter().map(|c| SongChord::new(cursor, *c)));
+        self.song.get_chord_timeline_mut().extend(chords);
+        self.song.get_note_timeline_mut().extend(notes.into_iter().map(|(on, off, pitch)| SongNote::new(on, off, pitch)));
+
+        self.song
+    }
+}
+
+fn parse_ending(s : &str) -> Vec<u64> {
+    let mut numbers = Vec::new();
+    for part in s.split(',') {
+        match part.split_once('-') {
+            Some((from, to)) => {
+                if let (Ok(from), Ok(to)) = (from.parse::<u64>(), to.parse::<u64>()) {
+                    numbers.extend(from..=to);
+                }
+            },
+            None => numbers.extend(part.parse::<u64>().ok()),
+        }
+    }
+
+    numbers
+}
+
+/// Read a length suffix like "2", "/2", "//", "3/2" or "/" and return it as a fraction.
+fn read_length(chars : &[char], start : usize) -> Result<(Length, usize), String> {
+    let mut i = start;
+    let numerator : String = chars[i..].iter().take_while(|c| c.is_ascii_digit()).collect();
+    i += numerator.len();
+    let numerator = if numerator.is_empty() { 1 } else { read_length_number(&numerator)? };
+
+    let mut denominator = 1;
+    let slashes = chars[i..].iter().take_while(|c| **c == '/').count();
+    if slashes > 0 {
+        i += slashes;
+        let digits : String = chars[i..].iter().take_while(|c| c.is_ascii_digit()).collect();
+        i += digits.len();
+        denominator = if slashes == 1 && !digits.is_empty() {
+            read_length_number(&digits)?
+        } else if slashes <= MAX_LENGTH_SLASHES {
+            2u64.pow(slashes as u32)
+        } else {
+            return Err(format!("too many slashes in note length '{}'", "/".repeat(slashes)));
+        };
+    }
+
+    Ok((multiply((numerator, denominator), (1, 1)), i))
+}
+
+/// Read one number of a length, which has to be a positive 16 bit value so
+/// that multiplying lengths can't overflow.
+fn read_length_number(digits : &str) -> Result<u64, String> {
+    match digits.parse::<u16>() {
+        Ok(n) if n > 0 => Ok(n as u64),
+        _ => Err(format!("invalid note length '{}'", digits)),
+    }
+}
+
+/// Writes the tune body, four bars per line.
+struct BodyWriter<'a> {
+    song : &'a Song,
+    key_alters : [i64; 7],
+    prefer_flats : bool,
+}
+
+impl<'a> BodyWriter<'a> {
+    fn new(song : &'a Song, fifths : i64) -> Self {
+        BodyWriter {
+            song,
+            key_alters : key_alters(fifths),
+            prefer_flats : fifths < 0,
+        }
+    }
+
+    fn write(&self) -> String {
+        let barlines = melody::barlines(self.song);
+        let mut cuts : Vec<u64> = barlines[1..barlines.len() - 1].to_vec();
+        cuts.extend(self.song.get_chords().iter().map(|c| c.get_position().get_ticks_on()));
+        let settings = self.song.get_song_settings();
+        let tempo_map = settings.get_tempo_map().to_steps(settings.get_pulses_per_quarter());
+        cuts.extend(tempo_map.get_changes().iter().map(|c| c.get_tick()));
+
+        let events = melody::melody_events(self.song);
+        let events = melody::split_at(melody::fill_rests(events, 0, barlines[barlines.len() - 1]), &cuts);
+
+        let mut out = String::new();
+        let mut bar_accidentals : Vec<((char, i64), i64)> = Vec::new();
+        let mut bar = 0;
+        for event in events {
+            let event_bar = barlines.partition_point(|b| *b <= event.start) - 1;
+            if event_bar != bar {
+                bar = event_bar;
+                bar_accidentals.clear();
+                out.push_str(if bar % 4 == 0 { " |\n" } else { " | " });
+                let meter = settings.get_meter_at(event.start);
+                if meter != settings.get_meter_at(barlines[bar - 1]) {
+                    out.push_str(&format!("[M:{}] ", meter));
+                }
+            } else if !out.is_empty() {
+                out.push(' ');
+            }
+
+            for change in tempo_map.get_changes().iter().filter(|c| c.get_tick() == event.start) {
+                out.push_str(&format!("[Q:1/4={}]", change.get_tempo().round()));
+            }
+            for chord in self.song.get_chords() {
+                if chord.get_position().get_ticks_on() == event.start {
+                    out.push_str(&format!("\"{}\"", chord.get_chord()));
+                }
+            }
+
+            let mut pitches = String::new();
+            for pitch in &event.pitches {
+                pitches.push_str(&self.write_pitch(*pitch, &mut bar_accidentals));
+            }
+            match event.pitches.len() {
+                0 => out.push('z'),
+                1 => out.push_str(&pitches),
+                _ => out.push_str(&format!("[{}]", pitches)),
+            }
+
+            out.push_str(&self.write_length(event.get_length()));
+            if event.tie_start {
+                out.push('-');
+            }
+        }
+        out.push_str(" |]\n");
+
+        out
+    }
+
+    fn write_pitch(&self, pitch : u8, bar_accidentals : &mut Vec<((char, i64), i64)>) -> String {
+        let (name, octave) = NoteName::from_midi_pitch(pitch, self.prefer_flats);
+        let letter = name.get_step();
+        let alter = name.get_mod().get_alter();
+        let key = (letter, octave);
+        let current = bar_accidentals.iter()
+            .find(|(k, _)| *k == key)
+            .map(|(_, a)| *a)
+            .unwrap_or(self.key_alters[letter_index(letter)]);
+
+        let mut out = String::new();
+        if alter != current {
+            out.push_str(match alter {
+                -2 => "__",
+                -1 => "_",
+                1 => "^",
+                2 => "^^",
+                _ => "=",
+            });
+            bar_accidentals.retain(|(k, _)| *k != key);
+            bar_accidentals.push((key, alter));
+        }
+
+        if octave >= 5 {
+            out.push(letter.to_ascii_lowercase());
+            out.push_str(&"'".repeat((octave - 5) as usize));
+        } else {
+            out.push(letter);
+            out.push_str(&",".repeat((4 - octave).max(0) as usize));
+        }
+
+        out
+    }
+
+    /// The length in eighth notes.
+    fn write_length(&self, ticks : u64) -> String {
+        let eighth = self.song.get_song_settings().get_pulses_per_quarter();
+        let (n, d) = multiply((ticks * 2, eighth), (1, 1));
+        match (n, d) {
+            (1, 1) => String::new(),
+            (n, 1) => n.to_string(),
+            (1, 2) => String::from("/"),
+            (n, d) => format!("{}/{}", n, d),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prelude::{ChordType, TempoCurve};
+    use test_case::test_case;
+
+    const TUNES : &str = r#"X:1
+T:The Kesh
+T:The Kesh Jig
+C:Trad.
+R:jig
+M:6/8
+L:1/8
+Q:3/8=120
+K:G
+|:"G"GAG GAB|"D"ABA ABd|"G"edd gdd|"D"edB dBA:|
+
+X:2
+T:Second Tune
+M:4/4
+K:Am
+"Am"A2 ^G2 A4|"E7"B>A (3GFE E4|]
+"#;
+
+    #[test]
+    fn test_multiple_tunes() {
+        let songs = Song::from_abc(TUNES).unwrap();
+        assert_eq!(songs.len(), 2);
+        assert_eq!(songs[0].get_song_meta().get_title(), Some(&String::from("The Kesh")));
+        assert_eq!(songs[1].get_song_meta().get_title(), Some(&String::from("Second Tune")));
+    }
+
+    #[test]
+    fn test_header() {
+        let songs = Song::from_abc(TUNES).unwrap();
+        let meta = songs[0].get_song_meta();
+        assert_eq!(meta.get_composer(), Some(&String::from("Trad.")));
+        assert_eq!(meta.get_genre(), Some(&String::from("jig")));
+
+        let settings = songs[0].get_song_settings();
+        assert_eq!(settings.get_time_signature_numerator(), 6);
+        assert_eq!(settings.get_time_signature_denominator(), 8);
+        assert_eq!(settings.get_tempo(), 180.0);
+        assert_eq!(settings.get_key_signature(), "G Major");
+        assert_eq!(songs[1].get_song_settings().get_key_signature(), "A Minor");
+    }
+
+    #[test]
+    fn test_repeats_are_unrolled() {
+        let songs = Song::from_abc(TUNES).unwrap();
+        let chords = songs[0].get_chords();
+        assert_eq!(chords.len(), 8);
+        // Each bar of 6/8 is three quarters long.
+        assert_eq!(chords[4].get_position().get_ticks_on(), 4 * 2880);
+        assert_eq!(chords[4].get_chord().get_root(), NoteName::G(NoteMod::Normal));
+        assert_eq!(songs[0].get_notes().len(), 48);
+    }
+
+    #[test]
+    fn test_notes() {
+        let songs = Song::from_abc(TUNES).unwrap();
+        let notes = songs[1].get_notes();
+        let pitches : Vec<u8> = notes.iter().map(|n| n.get_pitch()).collect();
+        assert_eq!(pitches, vec![69, 68, 69, 71, 69, 67, 65, 64, 64]);
+
+        // B>A is a dotted eighth followed by a sixteenth.
+        assert_eq!(notes[3].get_position().get_length(), 720);
+        assert_eq!(notes[4].get_position().get_length(), 240);
+        // (3GFE is a triplet of eighths.
+        assert_eq!(notes[5].get_position().get_length(), 320);
+        assert_eq!(notes[8].get_position().get_ticks_on(), 3840 + 1920);
+
+        let chords = songs[1].get_chords();
+        assert_eq!(chords[1].get_chord().get_intervals().len(), 1);
+        assert_eq!(chords[1].get_position().get_ticks_on(), 3840);
+    }
+
+    #[test]
+    fn test_key_signature_and_accidentals() {
+        let songs = Song::from_abc("X:1\nK:D\nF ^G G =F F|F\n").unwrap();
+        let pitches : Vec<u8> = songs[0].get_notes().iter().map(|n| n.get_pitch()).collect();
+        assert_eq!(pitches, vec![66, 68, 68, 65, 65, 66]);
+    }
+
+    #[test]
+    fn test_endings_and_ties() {
+        let songs = Song::from_abc("X:1\nM:2/4\nL:1/4\nK:C\n|:\"C\"C D|1\"G\"E2:|2\"F\"F- F|]\n").unwrap();
+        let roots : Vec<char> = songs[0].get_chords().iter().map(|c| c.get_chord().get_root().get_step()).collect();
+        assert_eq!(roots, vec!['C', 'G', 'C', 'F']);
+
+        let notes = songs[0].get_notes();
+        assert_eq!(notes.len(), 6);
+        assert_eq!(notes[5].get_position().get_ticks_on(), 5760);
+        assert_eq!(notes[5].get_position().get_length(), 1920);
+    }
+
+    #[test]
+    fn test_voices() {
+        let songs = Song::from_abc("X:1\nK:C\nV:1\nC D|\nV:2\nE F|\nV:1\nG A|\n").unwrap();
+        let pitches : Vec<u8> = songs[0].get_notes().iter().map(|n| n.get_pitch()).collect();
+        assert_eq!(pitches, vec![60, 62, 67, 69]);
+    }
+
+    #[test]
+    fn test_export_round_trip() {
+        let songs = Song::from_abc(TUNES).unwrap();
+        let abc = songs[1].to_abc(7);
+        assert!(abc.starts_with("X:7\nT:Second Tune\n"));
+        assert!(abc.contains("K:Am\n"));
+
+        let again = Song::from_abc(&abc).unwrap();
+        assert_eq!(again.len(), 1);
+        let pitches = |s : &Song| s.get_notes().iter().map(|n| (n.get_pitch(), n.get_position().get_ticks_on(), n.get_position().get_length())).collect::<Vec<_>>();
+        assert_eq!(pitches(&again[0]), pitches(&songs[1]));
+        let chords = |s : &Song| s.get_chords().iter().map(|c| (c.get_chord().to_string(), c.get_position().get_ticks_on())).collect::<Vec<_>>();
+        assert_eq!(chords(&again[0]), chords(&songs[1]));
+    }
+
+    #[test]
+    fn test_export_tempo_changes() {
+        let mut song = Song::new();
+        song.add_note(SongNote::new(0, 3840 * 2, 60));
+        let tempo_map = song.get_song_settings_mut().get_tempo_map_mut();
+        tempo_map.add_change(960, 90.0);
+        tempo_map.add_ramp(3840, 90.0, 3840 + 1920, 60.0, TempoCurve::Linear);
+        let abc = song.to_abc(1);
+        assert!(abc.contains("Q:1/4=120\n"));
+        assert!(abc.contains("C2- [Q:1/4=90]C6- | [Q:1/4=82]C2- [Q:1/4=67]C2- [Q:1/4=60]C4 |]"), "{}", abc);
+    }
+
+    #[test]
+    fn test_meter_changes() {
+        let songs = Song::from_abc("X:1\nM:4/4\nL:1/4\nK:C\nCDEF|[M:3/4]GAB|\nM:6/8\nc3c3|]\n").unwrap();
+        let settings = songs[0].get_song_settings();
+        let changes : Vec<(u64, String)> = settings.get_time_signature_map().get_changes().iter()
+            .map(|c| (c.get_tick(), c.get_meter().to_string()))
+            .collect();
+        assert_eq!(changes, vec![(3840, String::from("3/4")), (3840 + 2880, String::from("6/8"))]);
+        assert_eq!(settings.get_meter(), Meter::new(4, 4));
+
+        let abc = songs[0].to_abc(1);
+        assert!(abc.contains("| [M:3/4] G2 A2 B2 | [M:6/8] c6 | c6 |]"), "{}", abc);
+        let again = Song::from_abc(&abc).unwrap();
+        assert_eq!(again[0].get_song_settings().get_time_signature_map(), settings.get_time_signature_map());
+        assert_eq!(again[0].get_notes(), songs[0].get_notes());
+    }
+
+    #[test]
+    fn test_annotations() {
+        let songs = Song::from_abc("X:1\nL:1/4\nK:C\n\"N.C.\"C \"(Am)\"D \"Am\"E \"^text\"F|\n").unwrap();
+        let chords : Vec<(u64, String)> = songs[0].get_chords().iter()
+            .map(|c| (c.get_position().get_ticks_on(), c.get_chord().to_string()))
+            .collect();
+        assert_eq!(chords, vec![(1920, String::from("Am"))]);
+        assert_eq!(songs[0].get_notes().len(), 4);
+    }
+
+    #[test]
+    fn test_tempo_changes() {
+        let songs = Song::from_abc("X:1\nL:1/4\nQ:1/4=100\nK:C\nCD[Q:1/2=40]EF|\nQ:3/8=60\nGABc|]\n").unwrap();
+        let settings = songs[0].get_song_settings();
+        assert_eq!(settings.get_tempo(), 100.0);
+        let changes : Vec<(u64, f64)> = settings.get_tempo_map().get_changes().iter().map(|c| (c.get_tick(), c.get_tempo())).collect();
+        assert_eq!(changes, vec![(1920, 80.0), (3840, 90.0)]);
+
+        let again = Song::from_abc(&songs[0].to_abc(1)).unwrap();
+        assert_eq!(again[0].get_song_settings().get_tempo_map(), settings.get_tempo_map());
+    }
+
+    #[test_case("X:1\nL:1/0\nK:C\nCDEF|\n" ; "zero unit length")]
+    #[test_case("X:1\nL:1/4\nK:C\nA/0 B|\n" ; "zero note length")]
+    #[test_case("X:1\nL:1/4\nK:C\nA////////////////////////////////////////////////////////////////// B|\n" ; "long slash run")]
+    #[test_case("X:1\nL:1/4\nK:C\nA99999999999999999999 B|\n" ; "huge note length")]
+    #[test_case("X:1\nL:1/4\nK:C\nA>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>B|\n" ; "long broken rhythm")]
+    #[test_case("X:1\nL:1/4\nQ:1/4=0\nK:C\nCDEF|\n" ; "zero tempo")]
+    #[test_case("X:1\nL:1/4\nK:C\nCD[Q:1/4=-40]EF|\n" ; "negative tempo")]
+    #[test_case("X:1\nL:1/4\nQ:1/0=100\nK:C\nCDEF|\n" ; "zero tempo beat")]
+    fn test_invalid_lengths(abc : &str) {
+        assert!(matches!(Song::from_abc(abc), Err(MusicalDataError::InvalidAbc(_))));
+    }
+
+    #[test]
+    fn test_line_break_marker() {
+        let songs = Song::from_abc("X:1\nL:1/4\nK:C\nCDEF|!\nGA !trill!B c|]\n").unwrap();
+        assert_eq!(songs[0].get_notes().len(), 8);
+    }
+
+    #[test]
+    fn test_additive_meter() {
+        let songs = Song::from_abc("X:1\nM:(2+2+3)/8\nL:1/8\nK:C\nCDEFGAB|\n").unwrap();
+        let settings = songs[0].get_song_settings();
+        assert_eq!(settings.get_meter().get_groups(), &[2, 2, 3]);
+        assert_eq!(settings.ticks_to_bars_and_beats(480 * 4), (0, 2, 0.0));
+        assert!(songs[0].to_abc(1).contains("M:2+2+3/8\n"));
+    }
+
+    #[test]
+    fn test_export_ties_across_bars() {
+        let mut song = Song::new();
+        song.add_chord(SongChord::new(0, Chord::new(NoteName::C(NoteMod::Normal), ChordType::Major)));
+        song.add_note(SongNote::new(2880, 4800, 61));
+        let abc = song.to_abc(1);
+        assert!(abc.contains("\"C\"z6 ^C2- | ^C2 z6 |]"), "{}", abc);
+    }
+
+    #[test_case("C", [0, 0, 0, 0, 0, 0, 0])]
+    #[test_case("D", [1, 0, 0, 1, 0, 0, 0])]
+    #[test_case("Bb", [0, 0, -1, 0, 0, 0, -1])]
+    fn test_key_alters(key : &str, alters : [i64; 7]) {
+        let (fifths, _) = fifths_from_key_name(key).unwrap();
+        assert_eq!(key_alters(fifths), alters);
+    }
+}
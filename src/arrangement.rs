@@ -0,0 +1,327 @@
+use std::fmt;
+
+use crate::error::MusicalDataError;
+use crate::prelude::{Chord, Marker, MarkerCategory, PositionableMut, Song, SongChord, SongPosition, SongSettings, Timeline};
+
+/// A chord of a section at a bar and beat counted from the start of the section, so it
+/// keeps its place in the bar whatever meter the song has there.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SectionChord {
+    pub bar : u64,
+    pub beat : u64,
+    pub chord : Chord,
+}
+
+/// A reusable part of a song like intro, verse or chorus with its own chords.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Section {
+    label : String,
+    name : String,
+    bars : u64,
+    chords : Vec<SectionChord>,
+}
+
+impl Section {
+    /// Create a section with a short label like "C" used in the arrangement order and a
+    /// name like "Chorus".
+    pub fn new(label : &str, name : &str, bars : u64) -> Self {
+        Section {
+            label : String::from(label),
+            name : String::from(name),
+            bars,
+            chords : Vec::new(),
+        }
+    }
+
+    pub fn get_label(&self) -> &String {
+        &self.label
+    }
+
+    pub fn get_name(&self) -> &String {
+        &self.name
+    }
+
+    pub fn set_name(&mut self, name : &str) {
+        self.name = String::from(name);
+    }
+
+    /// The length of the section in bars.
+    pub fn get_bars(&self) -> u64 {
+        self.bars
+    }
+
+    pub fn set_bars(&mut self, bars : u64) {
+        self.bars = bars;
+    }
+
+    /// The chords of this section, ordered by bar and beat.
+    pub fn get_chords(&self) -> &[SectionChord] {
+        &self.chords
+    }
+
+    /// Insert a chord at a bar and beat of the section after all chords at the same
+    /// place.
+    pub fn add_chord(&mut self, bar : u64, beat : u64, chord : Chord) {
+        let index = self.chords.partition_point(|c| (c.bar, c.beat) <= (bar, beat));
+        self.chords.insert(index, SectionChord { bar, beat, chord });
+    }
+
+    pub fn remove_chord(&mut self, index : usize) -> SectionChord {
+        self.chords.remove(index)
+    }
+}
+
+/// A section placed in the linear timeline of a song.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArrangedSection {
+    /// The index of the section in `Arrangement::get_sections`.
+    pub section : usize,
+    /// How often the section has been played before, counted from zero.
+    pub repetition : u64,
+    pub start_bar : u64,
+    pub position : SongPosition,
+}
+
+/// The sections of a song and the order they are played in, like "I V C V C B C C O".
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Arrangement {
+    sections : Vec<Section>,
+    order : Vec<usize>,
+}
+
+impl fmt::Display for Arrangement {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        let labels : Vec<&str> = self.order.iter().map(|i| self.sections[*i].label.as_str()).collect();
+        write!(f, "{}", labels.join(" "))
+    }
+}
+
+impl Arrangement {
+    pub fn new() -> Self {
+        Arrangement::default()
+    }
+
+    pub fn get_sections(&self) -> &[Section] {
+        &self.sections
+    }
+
+    /// Add a section. A section with the same label is replaced and keeps its places in
+    /// the order.
+    pub fn add_section(&mut self, section : Section) {
+        match self.sections.iter().position(|s| s.label == section.label) {
+            Some(index) => self.sections[index] = section,
+            None => self.sections.push(section),
+        }
+    }
+
+    pub fn get_section(&self, label : &str) -> Option<&Section> {
+        self.sections.iter().find(|s| s.label == label)
+    }
+
+    /// The section to edit, which changes every repetition of it.
+    pub fn get_section_mut(&mut self, label : &str) -> Option<&mut Section> {
+        self.sections.iter_mut().find(|s| s.label == label)
+    }
+
+    /// The sections in playback order.
+    pub fn get_order(&self) -> Vec<&Section> {
+        self.order.iter().map(|i| &self.sections[*i]).collect()
+    }
+
+    /// Set the playback order from section labels separated by whitespace.
+    pub fn set_order(&mut self, order : &str) -> Result<(), MusicalDataError> {
+        self.order = order.split_whitespace()
+            .map(|label| self.index_of(label))
+            .collect::<Result<_, _>>()?;
+        Ok(())
+    }
+
+    /// Append a section to the playback order.
+    pub fn push(&mut self, label : &str) -> Result<(), MusicalDataError> {
+        let index = self.index_of(label)?;
+        self.order.push(index);
+        Ok(())
+    }
+
+    /// The length of the arrangement in bars.
+    pub fn get_bars(&self) -> u64 {
+        self.order.iter().map(|i| self.sections[*i].bars).sum()
+    }
+
+    /// Lay out the sections one after another from the first bar of the song.
+    pub fn expand(&self, settings : &SongSettings) -> Vec<ArrangedSection> {
+        let mut repetitions = vec![0; self.sections.len()];
+        let mut bar = 0;
+        let mut arranged = Vec::with_capacity(self.order.len());
+        for index in &self.order {
+            let end_bar = bar + self.sections[*index].bars;
+            let start = settings.bars_and_beats_to_ticks(bar, 0, 0.0);
+            let end = settings.bars_and_beats_to_ticks(end_bar, 0, 0.0);
+            arranged.push(ArrangedSection {
+                section : *index,
+                repetition : repetitions[*index],
+                start_bar : bar,
+                position : SongPosition::from(start, end),
+            });
+            repetitions[*index] += 1;
+            bar = end_bar;
+        }
+
+        arranged
+    }
+
+    /// The chords of all sections in playback order, placed at their bar and beat in the
+    /// meter of the song there. Every chord lasts until the next one or the end of its
+    /// section. Chords after the last bar of their section or after the last beat of
+    /// their bar are left out.
+    pub fn expand_chords(&self, settings : &SongSettings) -> Timeline<SongChord> {
+        let mut chords = Vec::new();
+        for arranged in self.expand(settings) {
+            let section = &self.sections[arranged.section];
+            let starts : Vec<(u64, Chord)> = section.chords.iter()
+                .filter(|c| c.bar < section.bars)
+                .filter(|c| {
+                    let bar_start = settings.bars_and_beats_to_ticks(arranged.start_bar + c.bar, 0, 0.0);
+                    c.beat < settings.get_meter_at(bar_start).get_beat_count()
+                })
+                .map(|c| (settings.bars_and_beats_to_ticks(arranged.start_bar + c.bar, c.beat, 0.0), c.chord))
+                .collect();
+
+            for (i, (on, chord)) in starts.iter().enumerate() {
+                let off = starts.get(i + 1).map(|(next, _)| *next).unwrap_or(arranged.position.get_ticks_end());
+                let mut chord = SongChord::new(*on, *chord);
+                chord.set_position(SongPosition::from(*on, off));
+                chords.push(chord);
+            }
+        }
+
+        Timeline::from_vec(chords)
+    }
+
+    fn index_of(&self, label : &str) -> Result<usize, MusicalDataError> {
+        self.sections.iter().position(|s| s.label == label)
+            .ok_or_else(|| MusicalDataError::InvalidArrangement(format!("unknown section '{}'", label)))
+    }
+}
+
+impl Song {
+    /// Replace the chords of the song by the expanded arrangement and mark every section
+    /// with a section region.
+    pub fn apply_arrangement(&mut self, arrangement : &Arrangement) {
+        let chords = arrangement.expand_chords(self.get_song_settings());
+        let sections = arrangement.expand(self.get_song_settings());
+        *self.get_chord_timeline_mut() = chords;
+
+        let markers = self.get_marker_timeline_mut();
+        markers.retain(|m| m.get_category() != MarkerCategory::Section);
+        markers.extend(sections.iter().map(|s| {
+            let mut marker = Marker::new(0, arrangement.sections[s.section].get_name(), MarkerCategory::Section);
+            marker.set_position(s.position);
+            marker
+        }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prelude::{ChordType, NoteName, Positionable};
+    use test_case::test_case;
+
+    fn chord(root : &str) -> Chord {
+        Chord::new(root.parse::<NoteName>().unwrap(), ChordType::Major)
+    }
+
+    fn arrangement() -> Arrangement {
+        let mut arrangement = Arrangement::new();
+        let mut verse = Section::new("V", "Verse", 4);
+        verse.add_chord(2, 0, chord("F"));
+        verse.add_chord(0, 0, chord("C"));
+        let mut chorus = Section::new("C", "Chorus", 2);
+        chorus.add_chord(0, 0, chord("G"));
+        chorus.add_chord(1, 0, chord("A"));
+        // After the last bar of the chorus.
+        chorus.add_chord(2, 0, chord("D"));
+        arrangement.add_section(Section::new("I", "Intro", 1));
+        arrangement.add_section(verse);
+        arrangement.add_section(chorus);
+        arrangement.set_order("I V C V C C").unwrap();
+        arrangement
+    }
+
+    #[test_case("I V C", Ok(7))]
+    #[test_case("  C  C ", Ok(4))]
+    #[test_case("", Ok(0))]
+    #[test_case("I X C", Err(MusicalDataError::InvalidArrangement("unknown section 'X'".to_string())))]
+    fn test_order(order : &str, bars : Result<u64, MusicalDataError>) {
+        let mut arrangement = arrangement();
+        assert_eq!(arrangement.set_order(order).map(|_| arrangement.get_bars()), bars);
+    }
+
+    #[test]
+    fn test_expand() {
+        let arrangement = arrangement();
+        assert_eq!(arrangement.to_string(), "I V C V C C");
+        let arranged = arrangement.expand(&SongSettings::default());
+        assert_eq!(arranged.len(), 6);
+        assert_eq!(arranged[3], ArrangedSection { section : 1, repetition : 1, start_bar : 7, position : SongPosition::from(26880, 42240) });
+        assert_eq!(arranged[5].repetition, 2);
+        assert_eq!(arranged[5].position, SongPosition::from(49920, 57600));
+    }
+
+    #[test]
+    fn test_expand_with_meter_change() {
+        let mut settings = SongSettings::default();
+        settings.add_time_signature_change_at_bar(2, 3, 4);
+        let arranged = arrangement().expand(&settings);
+        assert_eq!(arranged[1].position, SongPosition::from(3840, 7680 + 3 * 2880));
+    }
+
+    #[test]
+    fn test_edit_section_updates_repetitions() {
+        let mut arrangement = arrangement();
+        arrangement.get_section_mut("C").unwrap().add_chord(0, 2, chord("E"));
+        let chords = arrangement.expand_chords(&SongSettings::default());
+        let roots : Vec<String> = chords.iter().map(|c| format!("{}", c.get_chord().get_root())).collect();
+        assert_eq!(roots, vec!["C", "F", "G", "E", "A", "C", "F", "G", "E", "A", "G", "E", "A"]);
+        // Every chord lasts until the next one or the end of its section.
+        assert_eq!(chords.get_items()[2].get_position(), &SongPosition::from(19200, 19200 + 1920));
+        assert_eq!(chords.get_items()[4].get_position(), &SongPosition::from(19200 + 3840, 26880));
+    }
+
+    #[test]
+    fn test_expand_chords_with_meter_change() {
+        // The verse starts in 4/4 and continues in 3/4 from bar 2 of the song.
+        let mut settings = SongSettings::default();
+        settings.add_time_signature_change_at_bar(2, 3, 4);
+        let mut arrangement = arrangement();
+        arrangement.get_section_mut("C").unwrap().add_chord(0, 2, chord("E"));
+        arrangement.get_section_mut("C").unwrap().add_chord(1, 3, chord("B"));
+        let chords : Vec<(u64, u64, String)> = arrangement.expand_chords(&settings).iter()
+            .map(|c| (c.get_position().get_ticks_on(), c.get_position().get_ticks_end(), format!("{}", c.get_chord().get_root())))
+            .collect();
+
+        let bar = |n : u64| 7680 + (n - 2) * 2880;
+        assert_eq!(chords[..5].to_vec(), vec![
+            (3840, bar(3), String::from("C")),
+            (bar(3), bar(5), String::from("F")),
+            (bar(5), bar(5) + 1920, String::from("G")),
+            (bar(5) + 1920, bar(6), String::from("E")),
+            // Beat 3 does not exist in 3/4.
+            (bar(6), bar(7), String::from("A")),
+        ]);
+    }
+
+    #[test]
+    fn test_apply_arrangement() {
+        let mut song = Song::new();
+        song.add_marker(Marker::region(0, 100, "Old", MarkerCategory::Section).unwrap());
+        song.add_marker(Marker::new(0, "A", MarkerCategory::Rehearsal));
+        song.apply_arrangement(&arrangement());
+        assert_eq!(song.get_chords().len(), 10);
+        assert_eq!(song.get_markers_by_category(MarkerCategory::Section).len(), 6);
+        assert_eq!(song.get_section_at(30000).unwrap().get_name(), "Verse");
+        assert!(song.find_marker("Old").is_none());
+        assert!(song.find_marker("A").is_some());
+    }
+}
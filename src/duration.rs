@@ -0,0 +1,259 @@
+use crate::prelude::{Rational, SongPosition, SongSettings, WholeNotes};
+
+/// The tuplets `Duration::from_ticks` and `Duration::decompose` try, as actual notes
+/// in the time of normal notes.
+const TUPLETS : [(u64, u64); 4] = [(3, 2), (5, 4), (6, 4), (7, 4)];
+
+/// The plain note values from the whole note down to the 128th.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NoteValue {
+    Whole,
+    Half,
+    Quarter,
+    Eighth,
+    Sixteenth,
+    ThirtySecond,
+    SixtyFourth,
+    HundredTwentyEighth,
+}
+
+impl NoteValue {
+    /// All note values, longest first.
+    pub const ALL : [NoteValue; 8] = [
+        NoteValue::Whole, NoteValue::Half, NoteValue::Quarter, NoteValue::Eighth,
+        NoteValue::Sixteenth, NoteValue::ThirtySecond, NoteValue::SixtyFourth, NoteValue::HundredTwentyEighth,
+    ];
+
+    /// The denominator of the note value as a fraction of a whole note, e.g. 4 for a quarter.
+    pub fn get_denominator(&self) -> u64 {
+        match self {
+            NoteValue::Whole => 1,
+            NoteValue::Half => 2,
+            NoteValue::Quarter => 4,
+            NoteValue::Eighth => 8,
+            NoteValue::Sixteenth => 16,
+            NoteValue::ThirtySecond => 32,
+            NoteValue::SixtyFourth => 64,
+            NoteValue::HundredTwentyEighth => 128,
+        }
+    }
+
+    pub fn from_denominator(denominator : u64) -> Option<Self> {
+        NoteValue::ALL.into_iter().find(|v| v.get_denominator() == denominator)
+    }
+}
+
+/// A notated duration: a note value with dots and an optional tuplet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Duration {
+    note_value : NoteValue,
+    dots : u8,
+    /// Actual notes in the time of normal notes, e.g. (3, 2) for triplets.
+    tuplet : Option<(u64, u64)>,
+}
+
+impl Duration {
+    pub fn new(note_value : NoteValue) -> Self {
+        Duration { note_value, dots : 0, tuplet : None }
+    }
+
+    pub fn dotted(note_value : NoteValue, dots : u8) -> Self {
+        Duration { note_value, dots, tuplet : None }
+    }
+
+    /// A note value of a tuplet with `actual` notes in the time of `normal` notes.
+    pub fn tuplet(note_value : NoteValue, actual : u64, normal : u64) -> Self {
+        Duration { note_value, dots : 0, tuplet : Some((actual, normal)) }
+    }
+
+    pub fn get_note_value(&self) -> NoteValue {
+        self.note_value
+    }
+
+    pub fn get_dots(&self) -> u8 {
+        self.dots
+    }
+
+    pub fn get_tuplet(&self) -> Option<(u64, u64)> {
+        self.tuplet
+    }
+
+    pub fn set_dots(&mut self, dots : u8) {
+        self.dots = dots;
+    }
+
+    pub fn set_tuplet(&mut self, tuplet : Option<(u64, u64)>) {
+        self.tuplet = tuplet;
+    }
+
+    /// The exact length, e.g. 3/16 for a dotted eighth or 1/12 for an eighth triplet.
+    pub fn get_whole_notes(&self) -> WholeNotes {
+        let base = Rational::new(1, self.note_value.get_denominator() as i128);
+        // Every dot adds half of the previous value: 2 - 1/2^dots.
+        let dots = Rational::from_integer(2) - Rational::new(1, 1 << self.dots.min(64));
+        let tuplet = match self.tuplet {
+            Some((actual, normal)) if actual > 0 => Rational::new(normal as i128, actual as i128),
+            _ => Rational::from_integer(1),
+        };
+
+        WholeNotes::from_rational(base * dots * tuplet)
+    }
+
+    /// The length in ticks, `None` if the PPQ can not represent it exactly.
+    pub fn to_ticks(&self, ppq : u64) -> Option<u64> {
+        self.get_whole_notes().to_ticks(ppq)
+    }
+
+    /// Find a single duration with exactly this length. Plain values are preferred over
+    /// dotted values and both over tuplets.
+    pub fn from_ticks(ticks : u64, ppq : u64) -> Option<Self> {
+        candidates(ppq).into_iter()
+            .find(|(length, _)| *length == ticks)
+            .map(|(_, duration)| duration)
+    }
+
+    /// Split the length of a position into tied durations that do not cross bar lines.
+    /// A duration either stays within a beat or starts and ends on beats. Returns the
+    /// start tick of every duration and the ticks at the end that no duration the PPQ can
+    /// represent covers, e.g. 5 ticks of a note of 965 ticks at PPQ 960.
+    pub fn decompose(position : &SongPosition, settings : &SongSettings) -> (Vec<(u64, Duration)>, u64) {
+        let start = position.get_ticks_on();
+        let end = start + position.get_length();
+        let candidates = candidates(settings.get_pulses_per_quarter());
+        let grid = Duration::new(NoteValue::HundredTwentyEighth).to_ticks(settings.get_pulses_per_quarter()).unwrap_or(0);
+
+        let mut result = Vec::new();
+        let mut cursor = start;
+        while cursor < end {
+            let (bar, beat, fraction) = settings.ticks_to_bars_and_beats_exact(cursor);
+            let bar_start = settings.bars_and_beats_to_ticks(bar, 0, 0.0);
+            let bar_end = settings.bars_and_beats_to_ticks(bar + 1, 0, 0.0).min(end);
+            let next_beat = settings.bars_and_beats_to_ticks(bar, beat + 1, 0.0);
+            let on_beat = fraction == Rational::zero();
+            let is_beat = |tick : u64| tick == bar_end || settings.ticks_to_bars_and_beats_exact(tick).2 == Rational::zero();
+
+            let fits = |length : u64| {
+                let to = cursor + length;
+                to <= bar_end && (to <= next_beat || (on_beat && is_beat(to)))
+            };
+            // Tuplets are only used when the rest of the note is off the grid of the shortest
+            // plain value, or when no plain or dotted value fits.
+            let on_grid = grid == 0 || ((cursor - bar_start).is_multiple_of(grid) && (end - cursor).is_multiple_of(grid));
+            let plain = candidates.iter().find(|(length, d)| d.tuplet.is_none() && fits(*length)).filter(|_| on_grid);
+            let found = plain.or_else(|| candidates.iter().find(|(length, _)| fits(*length)));
+
+            match found {
+                Some((length, duration)) => {
+                    result.push((cursor, *duration));
+                    cursor += length;
+                },
+                None => break,
+            }
+        }
+
+        (result, end - cursor)
+    }
+}
+
+/// All durations with their length in ticks at the PPQ, longest first. Durations the PPQ
+/// can not represent are left out.
+fn candidates(ppq : u64) -> Vec<(u64, Duration)> {
+    let mut durations = Vec::new();
+    for dots in 0..=2 {
+        for value in NoteValue::ALL {
+            durations.push(Duration::dotted(value, dots));
+        }
+    }
+    for (actual, normal) in TUPLETS {
+        for value in NoteValue::ALL {
+            durations.push(Duration::tuplet(value, actual, normal));
+        }
+    }
+
+    let mut result : Vec<(u64, Duration)> = durations.into_iter()
+        .filter_map(|d| d.to_ticks(ppq).filter(|t| *t > 0).map(|t| (t, d)))
+        .collect();
+    // A stable sort keeps plain values before dotted values and tuplets of the same length.
+    result.sort_by_key(|(ticks, _)| std::cmp::Reverse(*ticks));
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    #[test_case(Duration::new(NoteValue::Quarter), 960)]
+    #[test_case(Duration::dotted(NoteValue::Eighth, 1), 720)]
+    #[test_case(Duration::dotted(NoteValue::Half, 2), 3360)]
+    #[test_case(Duration::tuplet(NoteValue::Eighth, 3, 2), 320)]
+    #[test_case(Duration::tuplet(NoteValue::Sixteenth, 5, 4), 192)]
+    #[test_case(Duration::new(NoteValue::HundredTwentyEighth), 30)]
+    fn test_to_and_from_ticks(duration : Duration, ticks : u64) {
+        assert_eq!(duration.to_ticks(960), Some(ticks));
+        assert_eq!(Duration::from_ticks(ticks, 960), Some(duration));
+    }
+
+    #[test]
+    fn test_unrepresentable() {
+        assert_eq!(Duration::tuplet(NoteValue::Eighth, 3, 2).to_ticks(100), None);
+        assert_eq!(Duration::new(NoteValue::HundredTwentyEighth).to_ticks(15), None);
+        assert_eq!(Duration::from_ticks(7, 960), None);
+        assert_eq!(Duration::dotted(NoteValue::Quarter, 1).get_whole_notes(), WholeNotes::new(3, 8));
+    }
+
+    fn decompose(on : u64, off : u64, numerator : u64, denominator : u64) -> (Vec<(u64, Duration)>, u64) {
+        let mut settings = SongSettings::default();
+        settings.set_time_signature_numerator(numerator);
+        settings.set_time_signature_denominator(denominator);
+        Duration::decompose(&SongPosition::from(on, off), &settings)
+    }
+
+    #[test]
+    fn test_decompose_across_bar_lines() {
+        // A dotted half starting on beat 3 is tied over the bar line.
+        assert_eq!(decompose(1920, 1920 + 2880, 4, 4).0, vec![
+            (1920, Duration::new(NoteValue::Half)),
+            (3840, Duration::new(NoteValue::Quarter)),
+        ]);
+    }
+
+    #[test]
+    fn test_decompose_off_beat() {
+        // A half note off the beat is split at the beat.
+        assert_eq!(decompose(480, 480 + 1920, 4, 4).0, vec![
+            (480, Duration::new(NoteValue::Eighth)),
+            (960, Duration::dotted(NoteValue::Quarter, 1)),
+        ]);
+        assert_eq!(decompose(240, 960, 4, 4).0, vec![(240, Duration::dotted(NoteValue::Eighth, 1))]);
+    }
+
+    #[test]
+    fn test_decompose_whole_bars_and_compound_meter() {
+        assert_eq!(decompose(0, 3840 * 2, 4, 4).0, vec![
+            (0, Duration::new(NoteValue::Whole)),
+            (3840, Duration::new(NoteValue::Whole)),
+        ]);
+        assert_eq!(decompose(0, 2880, 6, 8).0, vec![(0, Duration::dotted(NoteValue::Half, 1))]);
+        assert_eq!(decompose(0, 1440, 6, 8).0, vec![(0, Duration::dotted(NoteValue::Quarter, 1))]);
+        assert_eq!(decompose(2400, 3360, 6, 8).0, vec![
+            (2400, Duration::new(NoteValue::Eighth)),
+            (2880, Duration::new(NoteValue::Eighth)),
+        ]);
+    }
+
+    #[test]
+    fn test_decompose_triplets() {
+        assert_eq!(decompose(320, 960, 4, 4).0, vec![(320, Duration::tuplet(NoteValue::Quarter, 3, 2))]);
+        assert_eq!(decompose(0, 320, 4, 4).0, vec![(0, Duration::tuplet(NoteValue::Eighth, 3, 2))]);
+    }
+
+    #[test_case(0, 965, 1, 5)]
+    #[test_case(0, 7, 0, 7)]
+    #[test_case(0, 960, 1, 0)]
+    fn test_decompose_remainder(on : u64, off : u64, count : usize, remainder : u64) {
+        let (durations, rest) = decompose(on, off, 4, 4);
+        assert_eq!((durations.len(), rest), (count, remainder));
+    }
+}
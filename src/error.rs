@@ -0,0 +1,66 @@
+use std::fmt;
+
+/// Errors returned by the importers, exporters and conversions of this crate.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MusicalDataError {
+    /// The input is not well formed xml.
+    InvalidXml(String),
+    /// The input is well formed xml but not a MusicXML document we understand.
+    InvalidMusicXml(String),
+    /// A note name like "C#" or "Bb" could not be parsed.
+    InvalidNoteName(String),
+    /// A chord symbol like "Am7" or "G7/B" could not be parsed.
+    InvalidChordSymbol(String),
+    /// The input is not a valid ABC tune.
+    InvalidAbc(String),
+    /// The input is not a valid iReal Pro link.
+    InvalidIReal(String),
+    /// A time like "01:23.450" could not be parsed.
+    InvalidTime(String),
+    /// A SMPTE timecode is malformed, does not exist at the frame rate or lies before the start of the song.
+    InvalidTimecode(String),
+    /// A frame type is not one of the known frame rate codes.
+    InvalidFrameRate(i64),
+    /// A position like "12.3.480" could not be parsed or lies outside of its bar.
+    InvalidPosition(String),
+    /// A musical time in whole notes can not be represented in ticks at the PPQ.
+    InexactTicks(String),
+    /// A meter like "2+2+3/8" could not be parsed.
+    InvalidMeter(String),
+    /// A position ends before it starts, given as on and off ticks.
+    ReversedPosition(u64, u64),
+    /// A color is not written as "#rrggbb".
+    InvalidColor(String),
+    /// An arrangement refers to a section that does not exist.
+    InvalidArrangement(String),
+    /// A PPQ of zero.
+    InvalidPulsesPerQuarter(u64),
+    /// Tempo or time signature changes land on the same tick after rescaling.
+    CollidingChanges(String),
+}
+
+impl fmt::Display for MusicalDataError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MusicalDataError::InvalidXml(msg) => write!(f, "Invalid xml: {}", msg),
+            MusicalDataError::InvalidMusicXml(msg) => write!(f, "Invalid MusicXML: {}", msg),
+            MusicalDataError::InvalidNoteName(name) => write!(f, "Invalid note name: '{}'", name),
+            MusicalDataError::InvalidChordSymbol(symbol) => write!(f, "Invalid chord symbol: '{}'", symbol),
+            MusicalDataError::InvalidAbc(msg) => write!(f, "Invalid ABC: {}", msg),
+            MusicalDataError::InvalidIReal(msg) => write!(f, "Invalid iReal Pro link: {}", msg),
+            MusicalDataError::InvalidTime(time) => write!(f, "Invalid time: '{}'", time),
+            MusicalDataError::InvalidTimecode(timecode) => write!(f, "Invalid timecode: '{}'", timecode),
+            MusicalDataError::InvalidFrameRate(code) => write!(f, "Invalid frame rate code: {}", code),
+            MusicalDataError::InvalidPosition(position) => write!(f, "Invalid position: '{}'", position),
+            MusicalDataError::InexactTicks(time) => write!(f, "{} whole notes can not be represented in ticks", time),
+            MusicalDataError::InvalidMeter(meter) => write!(f, "Invalid meter: '{}'", meter),
+            MusicalDataError::ReversedPosition(on, off) => write!(f, "Position ends at tick {} before it starts at tick {}", off, on),
+            MusicalDataError::InvalidColor(color) => write!(f, "Invalid color: '{}'", color),
+            MusicalDataError::InvalidArrangement(msg) => write!(f, "Invalid arrangement: {}", msg),
+            MusicalDataError::InvalidPulsesPerQuarter(ppq) => write!(f, "Invalid PPQ: {}", ppq),
+            MusicalDataError::CollidingChanges(msg) => write!(f, "Colliding changes: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for MusicalDataError {}
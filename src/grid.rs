@@ -0,0 +1,284 @@
+use crate::prelude::{Accent, Meter, Rational, SongSettings};
+use crate::time_signature_map::MeterSegment;
+
+/// A bar of the bar grid of a song.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GridBar {
+    /// The bar counted from zero.
+    pub bar : u64,
+    pub start_tick : u64,
+    /// The exact start in ticks, fractional for e.g. 7/8 at an odd PPQ.
+    pub start : Rational,
+    /// The exact length in ticks, shorter than a full bar for a pickup or a bar cut
+    /// short by a meter change.
+    pub length : Rational,
+    pub meter : Meter,
+    /// The tempo at the start of the bar.
+    pub tempo : f64,
+    /// The bar is a pickup, a first bar shorter than its meter.
+    pub pickup : bool,
+}
+
+/// A beat of the beat grid of a song. The beats follow the beat grouping of the meter.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GridBeat {
+    pub bar : u64,
+    /// The beat within the bar counted from zero. The beats of a pickup are the last
+    /// beats of its meter.
+    pub beat : u64,
+    pub start_tick : u64,
+    /// The exact start in ticks.
+    pub start : Rational,
+    /// The exact length in ticks.
+    pub length : Rational,
+    pub meter : Meter,
+    /// The tempo at the start of the beat.
+    pub tempo : f64,
+    pub accent : Accent,
+}
+
+/// Iterates the bars of a song starting within a tick range, see `SongSettings::iter_bars`.
+pub struct BarIterator<'a> {
+    settings : &'a SongSettings,
+    segments : Vec<MeterSegment>,
+    segment : usize,
+    /// The next bar counted from the start of the segment.
+    bar : u64,
+    end : Rational,
+}
+
+impl<'a> BarIterator<'a> {
+    /// Start with the first bar starting at or after `start`, or with the bar containing
+    /// it.
+    fn new(settings : &'a SongSettings, start : u64, end : u64, containing : bool) -> Self {
+        let segments = settings.meter_segments();
+        let segment = segments.partition_point(|s| s.start_tick <= start).max(1) - 1;
+        let bar_length = segments[segment].bar_length();
+        let offset = Rational::from(start - segments[segment].start_tick);
+        let bar = match bar_length == Rational::zero() {
+            true => 0,
+            false if containing => (offset / bar_length).floor() as u64,
+            false => (offset / bar_length).ceil() as u64,
+        };
+
+        BarIterator { settings, segments, segment, bar, end : Rational::from(end) }
+    }
+}
+
+impl Iterator for BarIterator<'_> {
+    type Item = GridBar;
+
+    fn next(&mut self) -> Option<GridBar> {
+        loop {
+            let segment = self.segments.get(self.segment)?;
+            let next_start = self.segments.get(self.segment + 1).map(|s| Rational::from(s.start_tick));
+            let bar_length = segment.bar_length();
+            let start = Rational::from(segment.start_tick) + bar_length * Rational::from(self.bar);
+            if next_start.is_some_and(|n| bar_length == Rational::zero() || start >= n) {
+                self.segment += 1;
+                self.bar = 0;
+                continue;
+            }
+            if start >= self.end || bar_length == Rational::zero() {
+                return None;
+            }
+
+            let length = next_start.map(|n| (n - start).min(bar_length)).unwrap_or(bar_length);
+            let start_tick = start.round() as u64;
+            let bar = GridBar {
+                bar : segment.start_bar + self.bar,
+                start_tick,
+                start,
+                length,
+                meter : segment.meter.clone(),
+                tempo : self.settings.get_tempo_at(start_tick),
+                pickup : self.segment == 0 && self.bar == 0 && length < bar_length,
+            };
+            self.bar += 1;
+
+            return Some(bar);
+        }
+    }
+}
+
+/// Iterates the beats of a song starting within a tick range, see
+/// `SongSettings::iter_beats`.
+pub struct BeatIterator<'a> {
+    bars : BarIterator<'a>,
+    beats : std::vec::IntoIter<GridBeat>,
+    start : Rational,
+    end : Rational,
+}
+
+impl Iterator for BeatIterator<'_> {
+    type Item = GridBeat;
+
+    fn next(&mut self) -> Option<GridBeat> {
+        loop {
+            if let Some(beat) = self.beats.next() {
+                if beat.start < self.start {
+                    continue;
+                }
+                return (beat.start < self.end).then_some(beat);
+            }
+            let bar = self.bars.next()?;
+            self.beats = beats_of_bar(&bar, self.bars.settings).into_iter();
+        }
+    }
+}
+
+/// The beats of a bar. A pickup is aligned to the end of its meter, beats starting
+/// before it are left out.
+fn beats_of_bar(bar : &GridBar, settings : &SongSettings) -> Vec<GridBeat> {
+    let meter = &bar.meter;
+    let unit_length = meter.get_unit_length(settings.get_pulses_per_quarter());
+    let shift = match bar.pickup {
+        true => unit_length * Rational::from(meter.get_numerator()) - bar.length,
+        false => Rational::zero(),
+    };
+
+    let mut beats = Vec::new();
+    for beat in 0..meter.get_beat_count() {
+        let offset = unit_length * Rational::from(meter.get_beat_offset(beat)) - shift;
+        if offset < Rational::zero() {
+            continue;
+        }
+        if offset >= bar.length {
+            break;
+        }
+        let start = bar.start + offset;
+        let start_tick = start.round() as u64;
+        beats.push(GridBeat {
+            bar : bar.bar,
+            beat,
+            start_tick,
+            start,
+            length : (unit_length * Rational::from(meter.get_beat_length(beat))).min(bar.length - offset),
+            meter : meter.clone(),
+            tempo : settings.get_tempo_at(start_tick),
+            accent : meter.get_accent(meter.get_beat_offset(beat)),
+        });
+    }
+
+    beats
+}
+
+impl SongSettings {
+    /// The bars starting at or after `start` and before `end`, with their meter and tempo.
+    /// A bar cut short by a meter change still counts as a bar, so a pickup is written as
+    /// a meter change at its end, see `SongSettings::set_pickup`.
+    pub fn iter_bars(&self, start : u64, end : u64) -> BarIterator<'_> {
+        BarIterator::new(self, start, end, false)
+    }
+
+    /// The bar containing a tick, `None` if the meter has no length.
+    pub fn get_bar_at(&self, tick : u64) -> Option<GridBar> {
+        BarIterator::new(self, tick, u64::MAX, true).next()
+    }
+
+    /// The beats starting at or after `start` and before `end`, with their meter and tempo.
+    pub fn iter_beats(&self, start : u64, end : u64) -> BeatIterator<'_> {
+        BeatIterator {
+            bars : BarIterator::new(self, start, end, true),
+            beats : Vec::new().into_iter(),
+            start : Rational::from(start),
+            end : Rational::from(end),
+        }
+    }
+
+    /// Start the song with a pickup of `length` ticks in the meter of the song. The pickup
+    /// is bar 0 and the first full bar starts after it.
+    pub fn set_pickup(&mut self, length : u64) {
+        let meter = self.get_meter();
+        if let Some(tick) = self.get_pickup() {
+            self.get_time_signature_map_mut().remove_change(tick);
+        }
+        if length > 0 {
+            self.get_time_signature_map_mut().add_meter_change(length, meter);
+        }
+    }
+
+    /// The length of the pickup in ticks, if the first bar is cut short by a meter change.
+    pub fn get_pickup(&self) -> Option<u64> {
+        let segments = self.meter_segments();
+        segments.get(1)
+            .filter(|s| Rational::from(s.start_tick) < segments[0].bar_length())
+            .map(|s| s.start_tick)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    fn settings() -> SongSettings {
+        // A bar of 4/4, a bar of 6/8 at 90 bpm and 2+2+3/8 from then on.
+        let mut settings = SongSettings::default();
+        settings.add_time_signature_change_at_bar(1, 6, 8);
+        settings.add_meter_change_at_bar(2, "2+2+3/8".parse().unwrap());
+        settings.get_tempo_map_mut().add_change(3840, 90.0);
+        settings
+    }
+
+    #[test]
+    fn test_bars() {
+        let settings = settings();
+        let bars : Vec<(u64, u64, String, f64)> = settings.iter_bars(0, 20000)
+            .map(|b| (b.bar, b.start_tick, b.meter.to_string(), b.tempo))
+            .collect();
+        assert_eq!(bars, vec![
+            (0, 0, String::from("4/4"), 120.0),
+            (1, 3840, String::from("6/8"), 90.0),
+            (2, 6720, String::from("2+2+3/8"), 90.0),
+            (3, 10080, String::from("2+2+3/8"), 90.0),
+            (4, 13440, String::from("2+2+3/8"), 90.0),
+            (5, 16800, String::from("2+2+3/8"), 90.0),
+        ]);
+    }
+
+    #[test_case(1, 3841, vec![1])]
+    #[test_case(3840, 6721, vec![1, 2])]
+    #[test_case(3841, 6720, vec![])]
+    fn test_bars_in_range(start : u64, end : u64, bars : Vec<u64>) {
+        let settings = settings();
+        assert_eq!(settings.iter_bars(start, end).map(|b| b.bar).collect::<Vec<u64>>(), bars);
+    }
+
+    #[test]
+    fn test_beats() {
+        let settings = settings();
+        let beats : Vec<(u64, u64, u64, Accent)> = settings.iter_beats(2880, 10080)
+            .map(|b| (b.bar, b.beat, b.start_tick, b.accent))
+            .collect();
+        assert_eq!(beats, vec![
+            (0, 3, 2880, Accent::Beat),
+            (1, 0, 3840, Accent::Downbeat),
+            (1, 1, 5280, Accent::Beat),
+            (2, 0, 6720, Accent::Downbeat),
+            (2, 1, 7680, Accent::Beat),
+            (2, 2, 8640, Accent::Beat),
+        ]);
+        assert_eq!(settings.iter_beats(8640, 8641).next().unwrap().length, Rational::from(1440u64));
+    }
+
+    #[test]
+    fn test_pickup() {
+        let mut settings = SongSettings::default();
+        settings.set_pickup(960);
+        assert_eq!(settings.get_pickup(), Some(960));
+
+        let bars : Vec<(u64, u64, bool)> = settings.iter_bars(0, 3840 + 960).map(|b| (b.bar, b.start_tick, b.pickup)).collect();
+        assert_eq!(bars, vec![(0, 0, true), (1, 960, false)]);
+        let beats : Vec<(u64, u64, u64)> = settings.iter_beats(0, 2000).map(|b| (b.bar, b.beat, b.start_tick)).collect();
+        assert_eq!(beats, vec![(0, 3, 0), (1, 0, 960), (1, 1, 1920)]);
+        assert_eq!(settings.get_bar_at(959).map(|b| b.bar), Some(0));
+        assert_eq!(settings.get_bar_at(960 + 3839).map(|b| (b.bar, b.start_tick)), Some((1, 960)));
+
+        settings.set_pickup(1920);
+        assert_eq!(settings.get_pickup(), Some(1920));
+        settings.set_pickup(0);
+        assert_eq!(settings.get_pickup(), None);
+        assert!(settings.get_time_signature_map().is_empty());
+    }
+}
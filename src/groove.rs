@@ -0,0 +1,316 @@
+use crate::prelude::{Duration, NoteValue, Positionable, PositionableMut, Rational, SongNote, SongPosition, SongSettings, Timeline};
+
+/// The timing and velocity offset of one subdivision of a `GrooveTemplate`.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct GrooveStep {
+    /// The timing offset as a fraction of a subdivision, positive values are late.
+    pub timing : f64,
+    pub velocity : i32,
+}
+
+/// Per subdivision timing and velocity offsets that give events the feel of a
+/// performance. The steps repeat from every bar line, e.g. eight steps of sixteenths
+/// cover two beats of a 4/4 bar and are used twice.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GrooveTemplate {
+    subdivision : NoteValue,
+    steps : Vec<GrooveStep>,
+}
+
+impl GrooveTemplate {
+    pub fn new(subdivision : NoteValue, steps : Vec<GrooveStep>) -> Self {
+        GrooveTemplate { subdivision, steps }
+    }
+
+    /// Extract a template of `step_count` steps from a performed part. Every note is
+    /// assigned to its nearest subdivision, the offsets of all notes of a step are
+    /// averaged. Velocities are relative to the mean velocity of the part. Steps
+    /// without notes stay neutral.
+    pub fn extract(notes : &[SongNote], subdivision : NoteValue, step_count : usize, settings : &SongSettings) -> Self {
+        let mut template = GrooveTemplate::new(subdivision, vec![GrooveStep::default(); step_count]);
+        if notes.is_empty() || step_count == 0 {
+            return template;
+        }
+
+        let mean_velocity = notes.iter().map(|n| n.get_velocity() as f64).sum::<f64>() / notes.len() as f64;
+        let mut sums = vec![(0.0, 0.0, 0usize); step_count];
+        for note in notes {
+            let (index, timing) = template.nearest_step(note.get_position().get_ticks_on(), settings);
+            let sum = &mut sums[index % step_count];
+            sum.0 += timing;
+            sum.1 += note.get_velocity() as f64 - mean_velocity;
+            sum.2 += 1;
+        }
+
+        for (step, (timing, velocity, count)) in template.steps.iter_mut().zip(sums) {
+            if count > 0 {
+                step.timing = timing / count as f64;
+                step.velocity = (velocity / count as f64).round() as i32;
+            }
+        }
+
+        template
+    }
+
+    pub fn get_subdivision(&self) -> NoteValue {
+        self.subdivision
+    }
+
+    pub fn get_steps(&self) -> &[GrooveStep] {
+        &self.steps
+    }
+
+    pub fn set_subdivision(&mut self, subdivision : NoteValue) {
+        self.subdivision = subdivision;
+    }
+
+    pub fn set_steps(&mut self, steps : Vec<GrooveStep>) {
+        self.steps = steps;
+    }
+
+    /// The step of the subdivision nearest to the tick.
+    pub fn get_step_at(&self, tick : u64, settings : &SongSettings) -> Option<&GrooveStep> {
+        if self.steps.is_empty() {
+            return None;
+        }
+
+        let (index, _) = self.nearest_step(tick, settings);
+        self.steps.get(index % self.steps.len())
+    }
+
+    /// Move a position by the timing offset of its onset's step, keeping its length.
+    pub fn apply_to_position(&self, position : &SongPosition, settings : &SongSettings) -> SongPosition {
+        let step = match self.get_step_at(position.get_ticks_on(), settings) {
+            Some(step) => step,
+            None => return *position,
+        };
+
+        let shift = (step.timing * self.get_step_ticks(settings).to_f64()).round() as i64;
+        let on = position.get_ticks_on().saturating_add_signed(shift);
+        match position.get_ticks_off() {
+            Some(_) => SongPosition::from(on, on + position.get_length()),
+            None => SongPosition::new(on),
+        }
+    }
+
+    /// Apply the timing offsets to every item of a timeline.
+    pub fn apply<T : PositionableMut>(&self, timeline : &mut Timeline<T>, settings : &SongSettings) {
+        timeline.edit(|items| {
+            for item in items.iter_mut() {
+                let position = self.apply_to_position(item.get_position(), settings);
+                item.set_position(position);
+            }
+        });
+    }
+
+    /// Apply the timing and velocity offsets to notes.
+    pub fn apply_to_notes(&self, notes : &mut Timeline<SongNote>, settings : &SongSettings) {
+        notes.edit(|items| {
+            for note in items.iter_mut() {
+                if let Some(step) = self.get_step_at(note.get_position().get_ticks_on(), settings) {
+                    note.set_velocity(offset_velocity(note.get_velocity(), step.velocity));
+                }
+                let position = self.apply_to_position(note.get_position(), settings);
+                note.set_position(position);
+            }
+        });
+    }
+
+    fn get_step_ticks(&self, settings : &SongSettings) -> Rational {
+        Duration::new(self.subdivision).get_whole_notes().to_ticks_exact(settings.get_pulses_per_quarter())
+    }
+
+    /// The index of the nearest subdivision counted from the bar line and the distance
+    /// to it as a fraction of a subdivision. A tick close to the next bar line belongs
+    /// to its first subdivision.
+    fn nearest_step(&self, tick : u64, settings : &SongSettings) -> (usize, f64) {
+        let step = self.get_step_ticks(settings);
+        let (bar, _, _) = settings.ticks_to_bars_and_beats_exact(tick);
+        let bar_start = settings.bars_and_beats_to_ticks_exact(bar, 0, Rational::zero());
+        let bar_end = settings.bars_and_beats_to_ticks_exact(bar + 1, 0, Rational::zero());
+        let tick = Rational::from(tick);
+
+        let index = ((tick - bar_start) / step).round();
+        let line = bar_start + step * Rational::from_integer(index);
+        if line >= bar_end {
+            return (0, ((tick - bar_end) / step).to_f64());
+        }
+
+        (index as usize, ((tick - line) / step).to_f64())
+    }
+}
+
+/// Applies bounded random timing and velocity offsets. The same seed always gives
+/// the same offsets, so humanized parts can be reproduced.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Humanizer {
+    rng : Rng,
+    /// The largest timing offset in ticks in either direction.
+    timing_range : u64,
+    /// The largest velocity offset in either direction.
+    velocity_range : u8,
+}
+
+impl Humanizer {
+    pub fn new(seed : u64, timing_range : u64, velocity_range : u8) -> Self {
+        Humanizer { rng : Rng::new(seed), timing_range, velocity_range }
+    }
+
+    pub fn get_timing_range(&self) -> u64 {
+        self.timing_range
+    }
+
+    pub fn get_velocity_range(&self) -> u8 {
+        self.velocity_range
+    }
+
+    pub fn set_timing_range(&mut self, timing_range : u64) {
+        self.timing_range = timing_range;
+    }
+
+    pub fn set_velocity_range(&mut self, velocity_range : u8) {
+        self.velocity_range = velocity_range;
+    }
+
+    /// Move a position by a random offset, keeping its length. Positions never move
+    /// before the start of the song.
+    pub fn humanize(&mut self, position : &SongPosition) -> SongPosition {
+        let shift = self.rng.next_in_range(self.timing_range);
+        let on = position.get_ticks_on().saturating_add_signed(shift);
+        match position.get_ticks_off() {
+            Some(_) => SongPosition::from(on, on + position.get_length()),
+            None => SongPosition::new(on),
+        }
+    }
+
+    /// Change a velocity by a random offset, staying within 1 to 127.
+    pub fn humanize_velocity(&mut self, velocity : u8) -> u8 {
+        let offset = self.rng.next_in_range(self.velocity_range as u64);
+        offset_velocity(velocity, offset as i32)
+    }
+
+    pub fn humanize_timeline<T : PositionableMut>(&mut self, timeline : &mut Timeline<T>) {
+        timeline.edit(|items| {
+            for item in items.iter_mut() {
+                let position = self.humanize(item.get_position());
+                item.set_position(position);
+            }
+        });
+    }
+
+    /// Humanize the timing and velocity of notes.
+    pub fn humanize_notes(&mut self, notes : &mut Timeline<SongNote>) {
+        notes.edit(|items| {
+            for note in items.iter_mut() {
+                let position = self.humanize(note.get_position());
+                note.set_position(position);
+                let velocity = self.humanize_velocity(note.get_velocity());
+                note.set_velocity(velocity);
+            }
+        });
+    }
+}
+
+fn offset_velocity(velocity : u8, offset : i32) -> u8 {
+    (velocity as i32 + offset).clamp(1, 127) as u8
+}
+
+/// A small SplitMix64 generator. It is not suitable for anything but musical jitter.
+#[derive(Debug, Clone, PartialEq)]
+struct Rng {
+    state : u64,
+}
+
+impl Rng {
+    fn new(seed : u64) -> Self {
+        Rng { state : seed }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A uniform value in `-range..=range`.
+    fn next_in_range(&mut self, range : u64) -> i64 {
+        if range == 0 {
+            return 0;
+        }
+
+        let span = range.saturating_mul(2).saturating_add(1);
+        (self.next_u64() % span) as i64 - range as i64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn performance() -> Vec<SongNote> {
+        // Eighths where every off beat is a sixth of an eighth late and softer.
+        (0..16u64).map(|i| {
+            let late = if i % 2 == 1 { 80 } else { 0 };
+            let mut note = SongNote::new(i * 480 + late, i * 480 + 400, 60);
+            note.set_velocity(if i % 2 == 1 { 80 } else { 100 });
+            note
+        }).collect()
+    }
+
+    #[test]
+    fn test_extract() {
+        let template = GrooveTemplate::extract(&performance(), NoteValue::Eighth, 2, &SongSettings::default());
+
+        assert_eq!(template.get_steps(), &[
+            GrooveStep { timing : 0.0, velocity : 10 },
+            GrooveStep { timing : 1.0 / 6.0, velocity : -10 },
+        ]);
+    }
+
+    #[test]
+    fn test_apply() {
+        let settings = SongSettings::default();
+        let template = GrooveTemplate::extract(&performance(), NoteValue::Eighth, 2, &settings);
+        let mut notes = Timeline::from_vec(vec![SongNote::new(0, 480, 60), SongNote::new(480, 960, 62), SongNote::new(3840 + 1440, 3840 + 1920, 64)]);
+        template.apply_to_notes(&mut notes, &settings);
+
+        let result : Vec<(u64, u64, u8)> = notes.iter()
+            .map(|n| (n.get_position().get_ticks_on(), n.get_position().get_ticks_off().unwrap(), n.get_velocity()))
+            .collect();
+        assert_eq!(result, vec![(0, 480, 110), (560, 1040, 90), (3840 + 1520, 3840 + 2000, 90)]);
+
+        // A tick just before the bar line belongs to the first step of the next bar.
+        assert_eq!(template.get_step_at(3830, &settings), Some(&template.get_steps()[0]));
+    }
+
+    #[test]
+    fn test_humanize_is_reproducible() {
+        let positions : Vec<SongPosition> = (0..50).map(|i| SongPosition::from(i * 480, i * 480 + 240)).collect();
+        let run = |seed : u64| {
+            let mut humanizer = Humanizer::new(seed, 20, 10);
+            positions.iter().map(|p| (humanizer.humanize(p), humanizer.humanize_velocity(100))).collect::<Vec<_>>()
+        };
+
+        let first = run(42);
+        assert_eq!(first, run(42));
+        assert_ne!(first, run(43));
+        for ((humanized, velocity), original) in first.iter().zip(&positions) {
+            assert!(humanized.get_ticks_on().abs_diff(original.get_ticks_on()) <= 20);
+            assert_eq!(humanized.get_length(), 240);
+            assert!((90..=110).contains(velocity));
+        }
+    }
+
+    #[test]
+    fn test_humanize_bounds() {
+        let mut humanizer = Humanizer::new(1, 100, 50);
+        for _ in 0..100 {
+            assert!(humanizer.humanize(&SongPosition::new(10)).get_ticks_on() <= 110);
+            assert!(humanizer.humanize_velocity(120) <= 127);
+            assert!(humanizer.humanize_velocity(5) >= 1);
+        }
+        assert_eq!(Humanizer::new(1, 0, 0).humanize(&SongPosition::new(10)), SongPosition::new(10));
+    }
+}
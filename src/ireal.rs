@@ -0,0 +1,824 @@
+use crate::error::MusicalDataError;
+use crate::key_signature::{fifths_from_key_name, key_name_from_fifths};
+use crate::melody;
+use crate::prelude::{Chord, Marker, MarkerCategory, Meter, NoteName, Positionable, Song, SongChord};
+use crate::repeats::{unroll, Jump, RepeatMarks};
+
+/// The marker in front of the obfuscated chord progression of `irealb://` songs.
+const MUSIC_PREFIX : &str = "1r34LbKcu7";
+
+/// Characters that end a chord symbol in a chord progression.
+const CHORD_END : &str = " ,|[]{}()<ZYNTQSfU*";
+
+impl Song {
+    /// Read all songs of an iReal Pro link. Both the current `irealb://` format with its
+    /// obfuscated chord progression and the older `irealbook://` format are understood,
+    /// the link may also be embedded in the html iReal Pro exports. Repeats and endings are
+    /// unrolled as well as D.C., D.S., codas and Fine, so the returned songs contain
+    /// the chords in playback order.
+    pub fn from_ireal(input : &str) -> Result<Vec<Song>, MusicalDataError> {
+        let invalid = |msg : &str| MusicalDataError::InvalidIReal(String::from(msg));
+
+        let (start, legacy) = match (input.find("irealb://"), input.find("irealbook://")) {
+            (Some(start), _) => (start + "irealb://".len(), false),
+            (None, Some(start)) => (start + "irealbook://".len(), true),
+            (None, None) => return Err(invalid("no irealb:// or irealbook:// link found")),
+        };
+        let link = &input[start..];
+        let end = link.find(['"', '\'', '<', '>', '\n', '\r']).unwrap_or(link.len());
+        let data = percent_decode(&link[..end]);
+
+        let mut songs = Vec::new();
+        if legacy {
+            // Every song has six fields: title, composer, style, key, an unused field and the chords.
+            let fields : Vec<&str> = data.split('=').collect();
+            for song in fields.chunks(6) {
+                if song.len() < 6 {
+                    if song.iter().all(|f| f.trim().is_empty()) {
+                        continue;
+                    }
+                    return Err(invalid("incomplete song"));
+                }
+                songs.push(read_song(song[0], song[1], song[2], song[3], song[5], None)?);
+            }
+        } else {
+            let mut parts : Vec<&str> = data.split("===").collect();
+            // A playlist ends with its name.
+            if parts.len() > 1 {
+                parts.pop();
+            }
+            for part in parts {
+                let fields : Vec<&str> = part.split('=').collect();
+                if fields.len() < 7 {
+                    return Err(invalid("incomplete song"));
+                }
+                let music = match fields[6].split_once(MUSIC_PREFIX) {
+                    Some((_, music)) => unscramble(music),
+                    None => String::from(fields[6]),
+                };
+                let tempo = fields.get(8).and_then(|t| t.trim().parse::<f64>().ok()).filter(|t| *t > 0.0);
+                songs.push(read_song(fields[0], fields[1], fields[3], fields[4], &music, tempo)?);
+            }
+        }
+
+        Ok(songs)
+    }
+
+    /// Write this song as an `irealb://` link that iReal Pro can import.
+    pub fn to_ireal(&self) -> String {
+        format!("irealb://{}", percent_encode(&self.ireal_fields()))
+    }
+
+    /// Write several songs as an `irealb://` playlist link with the given name.
+    pub fn to_ireal_playlist(songs : &[Song], name : &str) -> String {
+        let mut data : Vec<String> = songs.iter().map(|s| s.ireal_fields()).collect();
+        data.push(String::from(name));
+
+        format!("irealb://{}", percent_encode(&data.join("===")))
+    }
+
+    fn ireal_fields(&self) -> String {
+        let meta = self.get_song_meta();
+        let settings = self.get_song_settings();
+
+        // iReal Pro sorts by "Girl From Ipanema, The" and "Jobim Antonio-Carlos".
+        let title = meta.get_title().map(|t| match t.strip_prefix("The ") {
+            Some(rest) => format!("{}, The", rest),
+            None => t.clone(),
+        }).unwrap_or_else(|| String::from("Untitled"));
+        let composer = meta.get_composer().map(|c| swap_names(c)).unwrap_or_else(|| String::from("Composer"));
+        let style = meta.get_genre().cloned().unwrap_or_else(|| String::from("Medium Swing"));
+        let key = ireal_key(settings.get_key_signature());
+        let tempo = settings.get_tempo().round() as u64;
+
+        format!("{}={}=={}={}=={}{}=={}=0", title, composer, style, key, MUSIC_PREFIX, scramble(&self.ireal_chart()), tempo)
+    }
+
+    /// The chord progression in iReal Pro notation, one bar per four cells. Meter changes
+    /// are written in front of the first bar in the new meter.
+    fn ireal_chart(&self) -> String {
+        let settings = self.get_song_settings();
+        let barlines = melody::barlines(self);
+        let chords = self.get_chords();
+        let mut out = String::from("[");
+        let mut previous = None;
+        for (bar, start) in barlines[..barlines.len() - 1].iter().enumerate() {
+            if bar > 0 {
+                out.push('|');
+            }
+            let meter = settings.get_meter_at(*start);
+            let (numerator, denominator) = (meter.get_numerator(), meter.get_denominator());
+            if previous != Some((numerator, denominator)) {
+                out.push_str(&format!("T{}", ireal_meter(numerator, denominator)));
+                previous = Some((numerator, denominator));
+            }
+            let section = self.get_markers_by_category(MarkerCategory::Section).into_iter()
+                .find(|m| m.get_position().get_ticks_on() == *start)
+                .and_then(|m| section_mark(m.get_name()));
+            if let Some(section) = section {
+                out.push_str(&format!("*{}", section));
+            }
+
+            let beat = settings.get_pulses_per_quarter() * 4 / denominator;
+            let in_bar : Vec<(u64, &Chord)> = chords.iter()
+                .filter(|c| (*start..barlines[bar + 1]).contains(&c.get_position().get_ticks_on()))
+                .map(|c| ((c.get_position().get_ticks_on() - start) / beat, c.get_chord()))
+                .collect();
+
+            // A bar has four cells, or a cell per beat if its chords don't fit in four.
+            let cell_count = match in_bar.iter().all(|(b, _)| (b * 4).is_multiple_of(numerator)) {
+                true => 4,
+                false => numerator,
+            };
+            let mut cells : Vec<Option<&Chord>> = vec![None; cell_count as usize];
+            for (b, chord) in &in_bar {
+                cells[(b * cell_count / numerator) as usize] = Some(*chord);
+            }
+            // Empty cells are spaces, chords in neighboring cells are separated by commas.
+            for (cell, chord) in cells.iter().enumerate() {
+                match chord {
+                    Some(chord) => {
+                        if cell > 0 && cells[cell - 1].is_some() {
+                            out.push(',');
+                        }
+                        out.push_str(&ireal_symbol(chord));
+                    },
+                    None => out.push(' '),
+                }
+            }
+        }
+        out.push('Z');
+
+        out
+    }
+}
+
+fn read_song(title : &str, composer : &str, style : &str, key : &str, music : &str, tempo : Option<f64>) -> Result<Song, MusicalDataError> {
+    let mut song = Song::new();
+    let title = title.trim();
+    let title = match title.strip_suffix(", The") {
+        Some(rest) => format!("The {}", rest),
+        None => String::from(title),
+    };
+
+    let meta = song.get_song_meta_mut();
+    meta.set_title(Some(title));
+    if !composer.trim().is_empty() {
+        meta.set_composer(Some(swap_names(composer.trim())));
+    }
+    if !style.trim().is_empty() {
+        meta.set_genre(Some(String::from(style.trim())));
+    }
+
+    let settings = song.get_song_settings_mut();
+    if let Some(key) = key_from_ireal(key) {
+        settings.set_key_signature(key);
+    }
+    if let Some(tempo) = tempo {
+        settings.set_tempo(tempo);
+    }
+
+    let mut reader = ChartReader::new();
+    reader.read(music).map_err(|msg| MusicalDataError::InvalidIReal(format!("{} in '{}'", msg, song.get_song_meta().get_title().map(|t| t.as_str()).unwrap_or_default())))?;
+    reader.finish(&mut song);
+
+    Ok(song)
+}
+
+/// Swap "Last First" to "First Last" and back. Names with more parts are kept as they are.
+fn swap_names(name : &str) -> String {
+    match name.split_whitespace().collect::<Vec<&str>>().as_slice() {
+        [first, second] => format!("{} {}", second, first),
+        _ => String::from(name),
+    }
+}
+
+/// Convert an iReal Pro key like "Eb" or "F#-" to a key signature name.
+fn key_from_ireal(key : &str) -> Option<String> {
+    let key = key.trim();
+    let (tonic, mode) = match key.strip_suffix('-') {
+        Some(tonic) => (tonic, "Minor"),
+        None => (key, "Major"),
+    };
+    let tonic : NoteName = tonic.parse().ok()?;
+
+    Some(format!("{} {}", tonic, mode))
+}
+
+/// Convert a key signature name to an iReal Pro key. Church modes are written as the
+/// major key with the same key signature.
+fn ireal_key(key_signature : &str) -> String {
+    match fifths_from_key_name(key_signature) {
+        Some((fifths, mode)) if mode == "minor" || mode == "aeolian" => {
+            let name = key_name_from_fifths(fifths, "minor").unwrap_or_default();
+            format!("{}-", name.split_whitespace().next().unwrap_or("A"))
+        },
+        Some((fifths, _)) => {
+            let name = key_name_from_fifths(fifths, "major").unwrap_or_default();
+            String::from(name.split_whitespace().next().unwrap_or("C"))
+        },
+        None => String::from("C"),
+    }
+}
+
+/// Write a chord in the notation of iReal Pro, e.g. "C^7", "D-7", "Bh7" or "Eo7".
+fn ireal_symbol(chord : &Chord) -> String {
+    let symbol = chord.to_string();
+    let (main, base) = match symbol.split_once('/') {
+        Some((main, base)) => (main, Some(base)),
+        None => (symbol.as_str(), None),
+    };
+    let root_length = chord.get_root().to_string().len();
+    let (root, quality) = main.split_at(root_length);
+
+    let quality = if let Some(rest) = quality.strip_prefix("m7b5") {
+        format!("h7{}", rest)
+    } else if let Some(rest) = quality.strip_prefix("dim7") {
+        format!("o7{}", rest)
+    } else if let Some(rest) = quality.strip_prefix("dim") {
+        format!("o{}", rest)
+    } else if let Some(rest) = quality.strip_prefix("aug") {
+        format!("+{}", rest)
+    } else if quality.starts_with('m') && !quality.starts_with("maj") {
+        format!("-{}", &quality[1..])
+    } else {
+        String::from(quality)
+    };
+
+    let mut result = format!("{}{}", root, quality.replace("maj", "^"));
+    if let Some(base) = base {
+        result.push('/');
+        result.push_str(base);
+    }
+
+    result
+}
+
+/// Parse a chord in the notation of iReal Pro.
+fn parse_ireal_symbol(token : &str) -> Option<Chord> {
+    let (main, base) = match token.split_once('/') {
+        Some((main, base)) => (main, Some(base)),
+        None => (token, None),
+    };
+    let root_length = 1 + main.chars().skip(1).take(2).take_while(|c| *c == '#' || *c == 'b').count();
+    if main.len() < root_length {
+        return None;
+    }
+    let (root, quality) = main.split_at(root_length);
+
+    // Half diminished chords are always seventh chords, altered dominants get a sharp
+    // fifth and ninth and "2" adds the second to the triad.
+    let quality = if let Some(rest) = quality.strip_prefix('h') {
+        format!("m7b5{}", rest.strip_prefix('7').unwrap_or(rest))
+    } else if quality == "2" {
+        String::from("add2")
+    } else {
+        quality.replace("alt", "#5#9")
+    };
+
+    let mut symbol = format!("{}{}", root, quality);
+    if let Some(base) = base {
+        symbol.push('/');
+        symbol.push_str(base);
+    }
+
+    symbol.parse().ok()
+}
+
+#[derive(Clone)]
+enum Slot {
+    Chord(Chord),
+    /// A slash: the previous chord continues.
+    Continue,
+    /// N.C.
+    NoChord,
+}
+
+#[derive(Clone)]
+struct ChartBar {
+    /// The chords, slashes and N.C. of the bar with the cell they are written in.
+    slots : Vec<(usize, Slot)>,
+    repeat : RepeatMarks,
+    meter : (u64, u64),
+    /// The bar repeats the previous bar (1) or the previous two bars (2).
+    repeat_bars : usize,
+    /// The number of cells of the bar, empty cells included. The chords are placed in the
+    /// bar by their cell.
+    cells : usize,
+    /// A section mark like 'A' or 'i' starting at this bar.
+    section : Option<char>,
+}
+
+impl ChartBar {
+    /// Add a chord, slash or N.C. in the next cell.
+    fn push(&mut self, slot : Slot) {
+        self.slots.push((self.cells, slot));
+        self.cells += 1;
+    }
+}
+
+struct ChartReader {
+    bars : Vec<ChartBar>,
+    current : ChartBar,
+    last_chord : Option<Chord>,
+}
+
+impl ChartReader {
+    fn new() -> Self {
+        ChartReader {
+            bars : Vec::new(),
+            current : ChartBar {
+                slots : Vec::new(),
+                repeat : RepeatMarks::default(),
+                meter : (4, 4),
+                repeat_bars : 0,
+                cells : 0,
+                section : None,
+            },
+            last_chord : None,
+        }
+    }
+
+    fn read(&mut self, music : &str) -> Result<(), String> {
+        let chars : Vec<char> = music.chars().collect();
+        let mut i = 0;
+        while i < chars.len() {
+            let c = chars[i];
+            i += 1;
+            match c {
+                '{' => {
+                    self.end_bar(false, false);
+                    self.current.repeat.start_repeat = true;
+                },
+                '}' => self.end_bar(true, false),
+                '[' | '|' => self.end_bar(false, false),
+                ']' | 'Z' => self.end_bar(false, true),
+                'T' => {
+                    let digits : String = chars[i..].iter().take(2).collect();
+                    i += digits.len();
+                    self.current.meter = match digits.as_str() {
+                        "12" => (12, 8),
+                        _ => {
+                            let mut d = digits.chars().filter_map(|c| c.to_digit(10));
+                            match (d.next(), d.next()) {
+                                (Some(n), Some(d)) if n > 0 && matches!(d, 1 | 2 | 4 | 8) => (n as u64, d as u64),
+                                _ => return Err(format!("invalid time signature 'T{}'", digits)),
+                            }
+                        },
+                    };
+                },
+                // Section marks like "*A" or "*i".
+                '*' => {
+                    self.current.section = chars.get(i).copied();
+                    i += 1;
+                },
+                'N' => {
+                    if let Some(n) = chars.get(i).and_then(|c| c.to_digit(10)) {
+                        i += 1;
+                        if n > 0 {
+                            self.current.repeat.ending = Some(vec![n as u64]);
+                        }
+                    }
+                },
+                // Comments like "<D.C. al Fine>" or "<Fine>" and alternate chords in parentheses.
+                '<' => {
+                    let length = chars[i..].iter().position(|c| *c == '>').unwrap_or(chars.len() - i);
+                    let comment : String = chars[i..i + length].iter().collect();
+                    i = (i + length + 1).min(chars.len());
+                    let comment = comment.trim();
+                    if comment.starts_with("D.C.") {
+                        self.current.repeat.jump = Some(Jump::DaCapo);
+                    } else if comment.starts_with("D.S.") {
+                        self.current.repeat.jump = Some(Jump::DalSegno);
+                    } else if comment.eq_ignore_ascii_case("fine") {
+                        self.current.repeat.fine = true;
+                    }
+                },
+                'S' => self.current.repeat.segno = true,
+                // The first coda sign jumps to the second one.
+                'Q' => {
+                    if self.bars.iter().any(|b| b.repeat.to_coda) || self.current.repeat.to_coda {
+                        self.current.repeat.coda = true;
+                    } else {
+                        self.current.repeat.to_coda = true;
+                    }
+                },
+                '(' => i += chars[i..].iter().position(|c| *c == ')').map(|p| p + 1).unwrap_or(chars.len() - i),
+                'x' => self.current.repeat_bars = 1,
+                'r' => self.current.repeat_bars = 2,
+                'p' => self.current.push(Slot::Continue),
+                'n' => self.current.push(Slot::NoChord),
+                'A'..='G' | 'W' => {
+                    let length = chars[i..].iter().take_while(|c| !CHORD_END.contains(**c)).count();
+                    let token : String = chars[i - 1..i + length].iter().collect();
+                    i += length;
+                    let chord = match token.strip_prefix('W') {
+                        // An invisible root keeps the previous chord with a new bass note.
+                        Some(rest) => {
+                            let mut chord = self.last_chord.ok_or_else(|| format!("invalid chord symbol '{}'", token))?;
+                            if let Some(base) = rest.strip_prefix('/') {
+                                chord.set_base(Some(base.parse().map_err(|_| format!("invalid chord symbol '{}'", token))?));
+                            }
+                            chord
+                        },
+                        None => parse_ireal_symbol(&token).ok_or_else(|| format!("invalid chord symbol '{}'", token))?,
+                    };
+                    self.last_chord = Some(chord);
+                    self.current.push(Slot::Chord(chord));
+                },
+                ' ' => self.current.cells += 1,
+                // Vertical space, chord sizes, fermatas, end markers and separators.
+                _ => {},
+            }
+        }
+        self.end_bar(false, false);
+
+        Ok(())
+    }
+
+    fn end_bar(&mut self, end_repeat : bool, section_end : bool) {
+        let meter = self.current.meter;
+        let bar = &mut self.current;
+        if bar.slots.is_empty() && bar.repeat_bars == 0 && bar.cells == 0 {
+            // Bar lines without content in between, e.g. "|[" or "}[".
+            if let Some(last) = self.bars.last_mut() {
+                last.repeat.end_repeat |= end_repeat;
+                last.repeat.section_end |= section_end;
+                last.repeat.fine |= bar.repeat.fine;
+                last.repeat.jump = last.repeat.jump.or(bar.repeat.jump);
+            }
+            bar.repeat.end_repeat = false;
+            bar.repeat.fine = false;
+            bar.repeat.jump = None;
+            return;
+        }
+
+        bar.repeat.end_repeat |= end_repeat;
+        bar.repeat.section_end |= section_end;
+        let next = ChartBar {
+            slots : Vec::new(),
+            repeat : RepeatMarks::default(),
+            meter,
+            repeat_bars : 0,
+            cells : 0,
+            section : None,
+        };
+        self.bars.push(std::mem::replace(&mut self.current, next));
+    }
+
+    fn finish(mut self, song : &mut Song) {
+        // Empty bars at the end only hold the last chord.
+        while self.bars.last().is_some_and(|b| b.slots.is_empty() && b.repeat_bars == 0) {
+            self.bars.pop();
+        }
+
+        // "r" repeats two bars and is followed by an empty bar.
+        for i in 1..self.bars.len() {
+            if self.bars[i - 1].repeat_bars == 2 && self.bars[i].slots.is_empty() && self.bars[i].repeat_bars == 0 {
+                self.bars[i].repeat_bars = 2;
+            }
+        }
+        for i in 0..self.bars.len() {
+            let repeat_bars = self.bars[i].repeat_bars;
+            if repeat_bars > 0 && i >= repeat_bars {
+                self.bars[i].slots = self.bars[i - repeat_bars].slots.clone();
+                self.bars[i].cells = self.bars[i - repeat_bars].cells;
+            }
+        }
+
+        if let Some(first) = self.bars.first() {
+            let settings = song.get_song_settings_mut();
+            settings.set_time_signature_numerator(first.meter.0);
+            settings.set_time_signature_denominator(first.meter.1);
+        }
+
+        let ppq = song.get_song_settings().get_pulses_per_quarter();
+        let marks : Vec<RepeatMarks> = self.bars.iter().map(|b| b.repeat.clone()).collect();
+        let mut cursor = 0;
+        let mut chords = Vec::new();
+        let mut sections : Vec<(u64, char)> = Vec::new();
+        for index in unroll(&marks) {
+            let bar = &self.bars[index];
+            if let Some(section) = bar.section {
+                sections.push((cursor, section));
+            }
+            let meter = Meter::new(bar.meter.0, bar.meter.1);
+            if song.get_song_settings().get_meter_at(cursor) != meter {
+                song.get_song_settings_mut().get_time_signature_map_mut().add_meter_change(cursor, meter);
+            }
+            let beat = ppq * 4 / bar.meter.1;
+            let bar_length = beat * bar.meter.0;
+
+            // The cells divide the bar evenly, usually four cells make a bar.
+            let cells = bar.cells.max(1) as u64;
+            for (cell, slot) in &bar.slots {
+                if let Slot::Chord(chord) = slot {
+                    chords.push(SongChord::new(cursor + *cell as u64 * bar_length / cells, *chord));
+                }
+            }
+            cursor += bar_length;
+        }
+        song.get_chord_timeline_mut().extend(chords);
+
+        // Every section lasts until the next one or the end of the chart.
+        let markers = sections.iter().enumerate().filter_map(|(i, (start, section))| {
+            let end = sections.get(i + 1).map(|(next, _)| *next).unwrap_or(cursor);
+            Marker::region(*start, end, &section_name(*section), MarkerCategory::Section).ok()
+        });
+        song.get_marker_timeline_mut().extend(markers);
+    }
+}
+
+/// The name of a section mark, "i" is the intro and "v" the verse.
+fn section_name(mark : char) -> String {
+    match mark {
+        'i' => String::from("Intro"),
+        'v' | 'V' => String::from("Verse"),
+        _ => mark.to_string(),
+    }
+}
+
+/// The section mark of a section name, the reverse of `section_name`.
+fn section_mark(name : &str) -> Option<char> {
+    match name {
+        "Intro" => Some('i'),
+        "Verse" => Some('V'),
+        _ => {
+            let mut chars = name.chars();
+            chars.next().filter(|c| c.is_ascii_alphabetic() && chars.next().is_none())
+        },
+    }
+}
+
+/// A time signature like "T44", 12/8 is written as "T12".
+fn ireal_meter(numerator : u64, denominator : u64) -> String {
+    match (numerator, denominator) {
+        (12, 8) => String::from("12"),
+        _ => format!("{}{}", numerator, denominator),
+    }
+}
+
+/// Swap the first five and the characters 10 to 23 of a block of 50 characters with
+/// their mirror images. Applying it twice gives the original block.
+fn obfuscate_block(block : &[char]) -> Vec<char> {
+    let mut result = block.to_vec();
+    for i in (0..5).chain(10..24) {
+        result[i] = block[49 - i];
+        result[49 - i] = block[i];
+    }
+
+    result
+}
+
+/// Undo the obfuscation of the chord progression of an `irealb://` song and expand the
+/// abbreviations iReal Pro uses for common cell sequences.
+fn unscramble(music : &str) -> String {
+    scramble(music)
+        .replace("Kcl", "| x")
+        .replace("LZ", " |")
+        .replace("XyQ", "   ")
+}
+
+/// Obfuscate a chord progression in blocks of 50 characters. The last block is kept as is
+/// when it is shorter than 52 characters.
+fn scramble(music : &str) -> String {
+    let mut chars : Vec<char> = music.chars().collect();
+    let mut result = String::new();
+    while chars.len() > 51 {
+        let rest = chars.split_off(50);
+        result.extend(obfuscate_block(&chars));
+        chars = rest;
+    }
+    result.extend(chars);
+
+    result
+}
+
+fn percent_decode(s : &str) -> String {
+    let bytes = s.as_bytes();
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let decoded = match bytes[i] {
+            b'%' => s.get(i + 1..i + 3).and_then(|h| u8::from_str_radix(h, 16).ok()),
+            _ => None,
+        };
+        match decoded {
+            Some(b) => {
+                result.push(b);
+                i += 3;
+            },
+            None => {
+                result.push(bytes[i]);
+                i += 1;
+            },
+        }
+    }
+
+    String::from_utf8_lossy(&result).into_owned()
+}
+
+fn percent_encode(s : &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    for b in s.bytes() {
+        if b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.' | b'~') {
+            result.push(b as char);
+        } else {
+            result.push_str(&format!("%{:02X}", b));
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    fn chord_ticks(song : &Song) -> Vec<(u64, String)> {
+        song.get_chords().iter()
+            .map(|c| (c.get_position().get_ticks_on(), c.get_chord().to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_legacy_link() {
+        let songs = Song::from_ireal("<a href=\"irealbook://Song Title=LastName FirstName=Style=Ab=n=T44*A{C^7 |A-7 |D-9 |G7#5 }\">Song</a>").unwrap();
+        assert_eq!(songs.len(), 1);
+
+        let song = &songs[0];
+        assert_eq!(song.get_song_meta().get_title(), Some(&String::from("Song Title")));
+        assert_eq!(song.get_song_meta().get_composer(), Some(&String::from("FirstName LastName")));
+        assert_eq!(song.get_song_meta().get_genre(), Some(&String::from("Style")));
+        assert_eq!(song.get_song_settings().get_key_signature(), "Ab Major");
+
+        let chords = chord_ticks(song);
+        assert_eq!(chords.len(), 8);
+        assert_eq!(chords[1], (3840, String::from("Am7")));
+        assert_eq!(chords[4], (4 * 3840, String::from("Cmaj7")));
+        assert_eq!(chords[7], (7 * 3840, String::from("G7#5")));
+    }
+
+    #[test]
+    fn test_bars_endings_and_repeat_signs() {
+        let songs = Song::from_ireal("irealbook://Blues, The=Doe John=Medium Swing=F-=n=[T34F7 |x |{Bb7 Bh7 Eo7 |N1C-7 }N2F7,p,C7Z").unwrap();
+        let song = &songs[0];
+        assert_eq!(song.get_song_meta().get_title(), Some(&String::from("The Blues")));
+        assert_eq!(song.get_song_settings().get_key_signature(), "F Minor");
+        assert_eq!(song.get_song_settings().get_time_signature_numerator(), 3);
+
+        let bar = 3 * 960;
+        assert_eq!(chord_ticks(song), vec![
+            (0, String::from("F7")),
+            (bar, String::from("F7")),
+            (2 * bar, String::from("Bb7")),
+            (2 * bar + 960, String::from("Bm7b5")),
+            (2 * bar + 1920, String::from("Edim7")),
+            (3 * bar, String::from("Cm7")),
+            (4 * bar, String::from("Bb7")),
+            (4 * bar + 960, String::from("Bm7b5")),
+            (4 * bar + 1920, String::from("Edim7")),
+            (5 * bar, String::from("F7")),
+            (5 * bar + 1920, String::from("C7")),
+        ]);
+    }
+
+    #[test_case("[T44C |D<Fine>|E |F<D.C. al Fine> Z", vec!["C", "D", "E", "F", "C", "D"])]
+    #[test_case("[T44C |SD |EQ |F<D.S. al Coda> ZQG Z", vec!["C", "D", "E", "F", "D", "E", "G"])]
+    #[test_case("{T44C }D Z<D.C.>", vec!["C", "C", "D", "C", "D"])]
+    fn test_navigation_marks(music : &str, roots : Vec<&str>) {
+        let songs = Song::from_ireal(&format!("irealbook://{}", percent_encode(&format!("Title=Composer=Style=C=n={}", music)))).unwrap();
+        let chords : Vec<String> = chord_ticks(&songs[0]).into_iter().map(|(_, c)| c).collect();
+        assert_eq!(chords, roots);
+    }
+
+    #[test]
+    fn test_obfuscated_playlist() {
+        let music = "[T44C^7 D-7 |E-7 A7 |D-7 G7 |C^7   |F^7   |Bh7 E7b9 |A-7 D7 |G7sus G7 Z";
+        let data = format!("First=Composer==Bossa Nova=C=={}{}==140=0===Second=Composer==Ballad=D-=={}[T44D-7   Z==0=0=0===My List",
+            MUSIC_PREFIX, scramble(music), MUSIC_PREFIX);
+        let songs = Song::from_ireal(&format!("irealb://{}", percent_encode(&data))).unwrap();
+
+        assert_eq!(songs.len(), 2);
+        assert_eq!(songs[0].get_song_settings().get_tempo(), 140.0);
+        assert_eq!(songs[0].get_chords().len(), 14);
+        assert_eq!(songs[0].get_chords()[8].get_chord().to_string(), "Bm7b5");
+        assert_eq!(songs[1].get_song_meta().get_genre(), Some(&String::from("Ballad")));
+        assert_eq!(songs[1].get_song_settings().get_key_signature(), "D Minor");
+        assert_eq!(songs[1].get_song_settings().get_tempo(), 120.0);
+    }
+
+    #[test]
+    fn test_scramble() {
+        let music : String = (0..120).map(|i| char::from(b'a' + (i % 26) as u8)).collect();
+        assert_ne!(scramble(&music), music);
+        assert_eq!(scramble(&scramble(&music)), music);
+        assert_eq!(scramble("C^7 |"), "C^7 |");
+        assert_eq!(unscramble("C^7XyQKcl LZ"), "C^7   | x  |");
+    }
+
+    #[test_case("C^7", "Cmaj7")]
+    #[test_case("D-7", "Dm7")]
+    #[test_case("Bh7", "Bm7b5")]
+    #[test_case("Bh", "Bm7b5")]
+    #[test_case("Eo7", "Edim7")]
+    #[test_case("F-^7", "Fmmaj7")]
+    #[test_case("G7alt", "G7#5#9")]
+    #[test_case("C2", "Cadd2")]
+    #[test_case("Bb7sus", "Bb7sus4")]
+    #[test_case("A-7/G", "Am7/G")]
+    fn test_parse_ireal_symbol(token : &str, symbol : &str) {
+        assert_eq!(parse_ireal_symbol(token).map(|c| c.to_string()), Some(String::from(symbol)));
+    }
+
+    #[test_case("Cmaj7", "C^7")]
+    #[test_case("Dm7", "D-7")]
+    #[test_case("Bm7b5", "Bh7")]
+    #[test_case("Edim7", "Eo7")]
+    #[test_case("Fmmaj7", "F-^7")]
+    #[test_case("Abaug", "Ab+")]
+    #[test_case("G7/B", "G7/B")]
+    fn test_ireal_symbol(symbol : &str, token : &str) {
+        assert_eq!(ireal_symbol(&symbol.parse().unwrap()), token);
+    }
+
+    #[test]
+    fn test_export_round_trip() {
+        let mut song = Song::new();
+        song.get_song_meta_mut().set_title(Some(String::from("The Test")));
+        song.get_song_meta_mut().set_composer(Some(String::from("John Doe")));
+        song.get_song_meta_mut().set_genre(Some(String::from("Up Tempo Swing")));
+        song.get_song_settings_mut().set_key_signature(String::from("Bb Major"));
+        song.get_song_settings_mut().set_tempo(200.0);
+        for (tick, symbol) in [(0, "Bbmaj7"), (1920, "G7"), (3840, "Cm7"), (4800, "F7"), (7680, "Bbmaj7"), (11520 + 2880, "Bdim")] {
+            song.add_chord(SongChord::new(tick, symbol.parse().unwrap()));
+        }
+
+        let link = song.to_ireal();
+        assert!(link.starts_with("irealb://"));
+
+        let songs = Song::from_ireal(&link).unwrap();
+        let result = &songs[0];
+        assert_eq!(result.get_song_meta().get_title(), Some(&String::from("The Test")));
+        assert_eq!(result.get_song_meta().get_composer(), Some(&String::from("John Doe")));
+        assert_eq!(result.get_song_meta().get_genre(), Some(&String::from("Up Tempo Swing")));
+        assert_eq!(result.get_song_settings().get_key_signature(), "Bb Major");
+        assert_eq!(result.get_song_settings().get_tempo(), 200.0);
+        assert_eq!(chord_ticks(result), chord_ticks(&song));
+
+        let playlist = Song::from_ireal(&Song::to_ireal_playlist(&[song, Song::new()], "Practice")).unwrap();
+        assert_eq!(playlist.len(), 2);
+    }
+
+    #[test]
+    fn test_cell_positions() {
+        // Chords are placed by their cell, empty cells are spaces.
+        let songs = Song::from_ireal("irealbook://Title=Composer=Style=C=n=[T44C  G| D  |E,F,G,A|T34B-7 E7Z").unwrap();
+        let song = &songs[0];
+        let ticks : Vec<u64> = chord_ticks(song).into_iter().map(|(tick, _)| tick).collect();
+        assert_eq!(ticks, vec![0, 2880, 3840 + 960, 7680, 7680 + 960, 7680 + 1920, 7680 + 2880, 11520, 11520 + 1920]);
+
+        let chart = song.ireal_chart();
+        assert!(chart.starts_with("[T44C  G| D  |E,F,G,A|T34B-7 E7Z"), "{}", chart);
+        assert_eq!(chord_ticks(&Song::from_ireal(&song.to_ireal()).unwrap()[0]), chord_ticks(song));
+    }
+
+    #[test]
+    fn test_meter_changes() {
+        let songs = Song::from_ireal("irealbook://Title=Composer=Style=C=n=[T44C |T34D |E |T44F Z").unwrap();
+        let song = &songs[0];
+        let changes : Vec<(u64, String)> = song.get_song_settings().get_time_signature_map().get_changes().iter()
+            .map(|c| (c.get_tick(), c.get_meter().to_string()))
+            .collect();
+        assert_eq!(changes, vec![(3840, String::from("3/4")), (3840 + 2 * 2880, String::from("4/4"))]);
+        assert_eq!(song.get_song_settings().ticks_to_bars_and_beats(3840 + 2 * 2880), (3, 0, 0.0));
+
+        let again = Song::from_ireal(&song.to_ireal()).unwrap();
+        assert_eq!(again[0].get_song_settings().get_time_signature_map(), song.get_song_settings().get_time_signature_map());
+        assert_eq!(chord_ticks(&again[0]), chord_ticks(song));
+        assert!(song.ireal_chart().starts_with("[T44C   |T34D   |E   |T44F   Z"), "{}", song.ireal_chart());
+    }
+
+    #[test]
+    fn test_section_marks() {
+        let songs = Song::from_ireal("irealbook://Title=Composer=Style=C=n=[T44*iC |D ]{*AE |F }[*BG |A Z").unwrap();
+        let song = &songs[0];
+        let sections : Vec<(u64, u64, &str)> = song.get_markers_by_category(MarkerCategory::Section).iter()
+            .map(|m| (m.get_position().get_ticks_on(), m.get_position().get_ticks_end(), m.get_name().as_str()))
+            .collect();
+        assert_eq!(sections, vec![(0, 7680, "Intro"), (7680, 15360, "A"), (15360, 23040, "A"), (23040, 30720, "B")]);
+
+        let chart = song.ireal_chart();
+        assert!(chart.starts_with("[T44*iC   |D   |*AE   |F   |*AE   |F   |*BG   |A   Z"), "{}", chart);
+        let again = Song::from_ireal(&song.to_ireal()).unwrap();
+        assert_eq!(again[0].get_markers(), song.get_markers());
+    }
+
+    #[test]
+    fn test_invalid_links() {
+        assert!(Song::from_ireal("https://example.com").is_err());
+        assert!(Song::from_ireal("irealbook://Title=Composer=Style=C=n=[T44Cq Z").is_err());
+        assert!(Song::from_ireal("irealb://Title=Composer").is_err());
+    }
+}
@@ -0,0 +1,73 @@
+use crate::prelude::{NoteMod, NoteName};
+
+/// Position of each mode's tonic on the circle of fifths relative to the major key
+/// with the same key signature.
+const MODES : [(&str, i64); 9] = [
+    ("major", 0),
+    ("minor", 3),
+    ("ionian", 0),
+    ("dorian", 2),
+    ("phrygian", 4),
+    ("lydian", -1),
+    ("mixolydian", 1),
+    ("aeolian", 3),
+    ("locrian", 5),
+];
+
+/// The note name at a position on the circle of fifths, 0 being C, 1 being G and -1 being F.
+pub(crate) fn note_on_circle_of_fifths(index : i64) -> Option<NoteName> {
+    let letter = b"FCGDAEB"[(index + 1).rem_euclid(7) as usize] as char;
+    let m = NoteMod::from_alter((index + 1).div_euclid(7))?;
+
+    NoteName::from_step(letter, m)
+}
+
+/// Build a key signature name like "G Major" or "F# Minor" from the number of
+/// sharps (positive) or flats (negative) and a mode.
+pub(crate) fn key_name_from_fifths(fifths : i64, mode : &str) -> Option<String> {
+    let mode = mode.trim().to_lowercase();
+    let (_, offset) = MODES.iter().find(|(m, _)| *m == mode)?;
+    let tonic = note_on_circle_of_fifths(fifths + offset)?;
+
+    let mut chars = mode.chars();
+    let first = chars.next()?.to_uppercase().collect::<String>();
+
+    Some(format!("{} {}{}", tonic, first, chars.as_str()))
+}
+
+/// Split a key signature name like "Eb Major" or "F# minor" into the number of sharps
+/// (positive) or flats (negative) and the lower case mode. The mode defaults to major.
+pub(crate) fn fifths_from_key_name(name : &str) -> Option<(i64, String)> {
+    let mut tokens = name.split_whitespace();
+    let tonic : NoteName = tokens.next()?.parse().ok()?;
+    let mode = tokens.next().unwrap_or("major").to_lowercase();
+    let (_, offset) = MODES.iter().find(|(m, _)| *m == mode)?;
+    let index = (-15..=15).find(|i| note_on_circle_of_fifths(*i) == Some(tonic))?;
+
+    Some((index - offset, mode))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    #[test_case(0, "major", "C Major")]
+    #[test_case(1, "major", "G Major")]
+    #[test_case(-3, "major", "Eb Major")]
+    #[test_case(6, "major", "F# Major")]
+    #[test_case(0, "minor", "A Minor")]
+    #[test_case(-1, "minor", "D Minor")]
+    #[test_case(4, "minor", "C# Minor")]
+    #[test_case(0, "dorian", "D Dorian")]
+    fn test_key_name_from_fifths(fifths : i64, mode : &str, name : &str) {
+        assert_eq!(key_name_from_fifths(fifths, mode), Some(String::from(name)));
+        assert_eq!(fifths_from_key_name(name), Some((fifths, String::from(mode))));
+    }
+
+    #[test]
+    fn test_invalid_key_name() {
+        assert_eq!(fifths_from_key_name("H Major"), None);
+        assert_eq!(fifths_from_key_name("C Blues"), None);
+    }
+}
@@ -3,17 +3,91 @@ mod song_meta;
 mod song_settings;
 mod song_position;
 mod song_chord;
+mod song_note;
+mod song_lyric;
+mod error;
+mod xml;
+mod key_signature;
+mod music_xml;
+mod melody;
+mod abc;
+mod lilypond;
+mod repeats;
+mod ireal;
+mod tempo_map;
+mod time_signature_map;
+mod meter;
+mod smpte;
+mod rational;
+mod position_display;
+mod whole_notes;
+mod duration;
+mod tick_offset;
+mod timeline;
+mod quantize;
+mod groove;
+mod rescale;
+mod marker;
+mod arrangement;
+mod metronome;
+mod grid;
 
 pub mod prelude {
     pub use crate::song::Song;
     pub use crate::song_meta::SongMeta;
     pub use crate::song_settings::SongSettings;
+    pub use crate::tempo_map::TempoMap;
+    pub use crate::tempo_map::TempoChange;
+    pub use crate::tempo_map::TempoCurve;
+    pub use crate::time_signature_map::TimeSignatureMap;
+    pub use crate::time_signature_map::TimeSignatureChange;
+    pub use crate::meter::Meter;
+    pub use crate::meter::Accent;
+    pub use crate::smpte::FrameRate;
+    pub use crate::smpte::Timecode;
+    pub use crate::rational::Rational;
+    pub use crate::whole_notes::WholeNotes;
+    pub use crate::duration::Duration;
+    pub use crate::duration::NoteValue;
+    pub use crate::tick_offset::TickOffset;
+    pub use crate::position_display::PositionDisplay;
+    pub use crate::position_display::PositionFormat;
     pub use crate::song_position::SongPosition;
     pub use crate::song_position::Positionable;
+    pub use crate::song_position::PositionableMut;
+    pub use crate::timeline::Timeline;
+    pub use crate::quantize::Quantizer;
+    pub use crate::quantize::GridType;
+    pub use crate::groove::GrooveTemplate;
+    pub use crate::groove::GrooveStep;
+    pub use crate::groove::Humanizer;
+    pub use crate::rescale::RoundingPolicy;
+    pub use crate::rescale::RoundedTick;
+    pub use crate::rescale::RescaleReport;
     pub use crate::song_chord::SongChord;
     pub use crate::song_chord::Chord;
     pub use crate::song_chord::NoteName;
     pub use crate::song_chord::NoteMod;
     pub use crate::song_chord::ChordFuntion;
     pub use crate::song_chord::ChordType;
-}
\ No newline at end of file
+    pub use crate::song_note::SongNote;
+    pub use crate::song_lyric::SongLyric;
+    pub use crate::song_lyric::Syllabic;
+    pub use crate::marker::Marker;
+    pub use crate::marker::MarkerCategory;
+    pub use crate::marker::Color;
+    pub use crate::arrangement::Section;
+    pub use crate::arrangement::SectionChord;
+    pub use crate::arrangement::ArrangedSection;
+    pub use crate::arrangement::Arrangement;
+    pub use crate::repeats::RepeatMarks;
+    pub use crate::repeats::Jump;
+    pub use crate::repeats::PlaybackOrder;
+    pub use crate::metronome::Metronome;
+    pub use crate::metronome::Click;
+    pub use crate::grid::GridBar;
+    pub use crate::grid::GridBeat;
+    pub use crate::grid::BarIterator;
+    pub use crate::grid::BeatIterator;
+    pub use crate::error::MusicalDataError;
+}
@@ -0,0 +1,461 @@
+use crate::key_signature::fifths_from_key_name;
+use crate::melody;
+use crate::prelude::{Chord, ChordFuntion, ChordType, Meter, NoteMod, NoteName, Positionable, Song, Syllabic, WholeNotes};
+
+/// Plain and dotted note values from the whole note down to the 128th, longest first.
+/// The first entry is the LilyPond duration, the second the length in 128th notes.
+const DURATIONS : [(&str, u64); 15] = [
+    ("1.", 192), ("1", 128), ("2.", 96), ("2", 64), ("4.", 48), ("4", 32), ("8.", 24), ("8", 16),
+    ("16.", 12), ("16", 8), ("32.", 6), ("32", 4), ("64.", 3), ("64", 2), ("128", 1),
+];
+
+impl Song {
+    /// Write this song as a LilyPond source file. The score contains the chord track as
+    /// `\chordmode` block and the melody and lyrics if present.
+    pub fn to_lilypond(&self) -> String {
+        let meta = self.get_song_meta();
+        let settings = self.get_song_settings();
+
+        let mut out = String::from("\\version \"2.24.0\"\n\n\\header {\n");
+        let fields = [
+            ("title", meta.get_title()),
+            ("composer", meta.get_composer()),
+            ("poet", meta.get_songwriter()),
+            ("arranger", meta.get_arranger()),
+            ("copyright", meta.get_copyright()),
+        ];
+        for (field, value) in fields {
+            if let Some(value) = value {
+                out.push_str(&format!("  {} = \"{}\"\n", field, escape(value)));
+            }
+        }
+        out.push_str("  tagline = ##f\n}\n\n");
+
+        out.push_str("global = {\n");
+        out.push_str(&format!("  {}\n", time(&settings.get_meter_at(0))));
+        let key = fifths_from_key_name(settings.get_key_signature());
+        if let (Some((_, mode)), Some(tonic)) = (&key, settings.get_key_signature().split_whitespace().next()) {
+            if let Ok(tonic) = tonic.parse::<NoteName>() {
+                out.push_str(&format!("  \\key {} \\{}\n", pitch_name(tonic), mode));
+            }
+        }
+        out.push_str(&format!("  \\tempo 4 = {}\n", settings.get_tempo().round()));
+
+        let prefer_flats = key.map(|(fifths, _)| fifths < 0).unwrap_or(false);
+        let writer = ScoreWriter::new(self, prefer_flats);
+        out.push_str(&writer.global_changes());
+        out.push_str("}\n\n");
+        out.push_str(&format!("harmonies = \\chordmode {{\n  {}\n}}\n\n", writer.chords()));
+
+        let has_melody = !self.get_notes().is_empty();
+        let has_lyrics = has_melody && !self.get_lyrics().is_empty();
+        if has_melody {
+            out.push_str(&format!("melody = {{\n  \\global\n  {}\n}}\n\n", writer.melody()));
+        }
+        if has_lyrics {
+            out.push_str(&format!("words = \\lyricmode {{\n  {}\n}}\n\n", writer.lyrics()));
+        }
+
+        out.push_str("\\score {\n  <<\n    \\new ChordNames \\harmonies\n");
+        if has_melody {
+            out.push_str("    \\new Staff { \\new Voice = \"melody\" \\melody }\n");
+        }
+        if has_lyrics {
+            out.push_str("    \\new Lyrics \\lyricsto \"melody\" \\words\n");
+        }
+        out.push_str("  >>\n  \\layout { }\n  \\midi { }\n}\n");
+
+        out
+    }
+}
+
+/// A time signature, additive meters are written with their beat structure like
+/// "\\time 2,2,3 7/8".
+fn time(meter : &Meter) -> String {
+    let groups = if meter.has_default_grouping() {
+        String::new()
+    } else {
+        format!("{} ", meter.get_groups().iter().map(|g| g.to_string()).collect::<Vec<String>>().join(","))
+    };
+
+    format!("\\time {}{}/{}", groups, meter.get_numerator(), meter.get_denominator())
+}
+
+fn escape(s : &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// The LilyPond name of a note in the default (dutch) note names, i.e. "fis" or "bes".
+fn pitch_name(name : NoteName) -> String {
+    let suffix = match name.get_mod() {
+        NoteMod::DoubleFlat => "eses",
+        NoteMod::Flat => "es",
+        NoteMod::Normal => "",
+        NoteMod::Sharp => "is",
+        NoteMod::DoubleSharp => "isis",
+    };
+
+    format!("{}{}", name.get_step().to_ascii_lowercase(), suffix)
+}
+
+/// The chord as a `\chordmode` name, i.e. "c4:m7" or "g2:7/b".
+fn chord_name(chord : &Chord, duration : &str) -> String {
+    let intervals = chord.get_intervals();
+    let seventh = intervals.iter().find(|i| i.get_degree() == 7).map(|i| i.get_mod());
+    let mut covered : Vec<ChordFuntion> = Vec::new();
+
+    let mut modifier = String::from(match (chord.get_chord_type(), seventh) {
+        (ChordType::Diminished, Some(NoteMod::Flat)) => "m7.5-",
+        (ChordType::Diminished, Some(NoteMod::DoubleFlat)) => "dim7",
+        (ChordType::Diminished, _) => "dim",
+        (ChordType::Augmented, _) => "aug",
+        (ChordType::Minor, _) => "m",
+        (ChordType::Power, _) => "1.5",
+        (ChordType::Major, _) | (ChordType::Sus(_), _) => "",
+    });
+    if chord.get_chord_type() == ChordType::Diminished {
+        covered.extend(seventh.and_then(|m| ChordFuntion::from_degree(7, m)));
+    }
+
+    match seventh {
+        Some(m) if covered.is_empty() && (m == NoteMod::Flat || m == NoteMod::Normal) => {
+            covered.push(ChordFuntion::Seventh(m));
+            let mut number = 7;
+            for upper in [ChordFuntion::Nineth(NoteMod::Normal), ChordFuntion::Eleventh(NoteMod::Normal), ChordFuntion::Thirteenth(NoteMod::Normal)] {
+                if !intervals.contains(&upper) {
+                    break;
+                }
+                covered.push(upper);
+                number = upper.get_degree();
+            }
+            match (m, chord.get_chord_type()) {
+                // The major seventh of a minor chord is written as raised seventh.
+                (NoteMod::Normal, ChordType::Minor) if number == 7 => modifier.push_str("7+"),
+                (NoteMod::Normal, ChordType::Minor) => modifier.push_str(&format!("{}.7+", number)),
+                (NoteMod::Normal, _) => modifier.push_str(&format!("maj{}", number)),
+                _ => modifier.push_str(&number.to_string()),
+            }
+        },
+        _ => {
+            if intervals.contains(&ChordFuntion::Sixth(NoteMod::Normal)) {
+                covered.push(ChordFuntion::Sixth(NoteMod::Normal));
+                modifier.push('6');
+                if intervals.contains(&ChordFuntion::Nineth(NoteMod::Normal)) {
+                    covered.push(ChordFuntion::Nineth(NoteMod::Normal));
+                    modifier.push_str(".9");
+                }
+            }
+        },
+    }
+
+    if let ChordType::Sus(n) = chord.get_chord_type() {
+        modifier.push_str(&format!("sus{}", n));
+    }
+
+    let mut rest : Vec<&ChordFuntion> = intervals.iter().filter(|i| !covered.contains(i)).collect();
+    rest.sort_by_key(|i| i.get_degree());
+    for interval in rest {
+        // Added steps need a chord to be added to, "5" is the plain triad.
+        if modifier.is_empty() {
+            modifier.push('5');
+        }
+        let alter = match interval.get_mod() {
+            NoteMod::DoubleFlat => "--",
+            NoteMod::Flat => "-",
+            NoteMod::Normal => "",
+            NoteMod::Sharp => "+",
+            NoteMod::DoubleSharp => "++",
+        };
+        modifier.push_str(&format!(".{}{}", interval.get_degree(), alter));
+    }
+
+    let mut name = pitch_name(chord.get_root());
+    name.push_str(duration);
+    if !modifier.is_empty() {
+        name.push(':');
+        name.push_str(&modifier);
+    }
+    if let Some(base) = chord.get_base() {
+        name.push('/');
+        name.push_str(&pitch_name(base));
+    }
+
+    name
+}
+
+/// Split a length into tied plain and dotted note values, longest first. Lengths that
+/// can not be written this way (i.e. tuplets) are written with a duration multiplier.
+fn durations(ticks : u64, ppq : u64) -> Vec<String> {
+    let whole = ppq * 4;
+    let mut result = Vec::new();
+    let mut rest = ticks;
+    for (name, value) in DURATIONS {
+        if !(whole * value).is_multiple_of(128) {
+            continue;
+        }
+        let value = whole * value / 128;
+        while rest >= value {
+            result.push(String::from(name));
+            rest -= value;
+        }
+    }
+
+    if rest != 0 {
+        return vec![multiplied(ticks, ppq)];
+    }
+
+    result
+}
+
+/// A whole note scaled to the given length, i.e. "1*5/4".
+fn multiplied(ticks : u64, ppq : u64) -> String {
+    format!("1*{}", WholeNotes::from_ticks(ticks, ppq))
+}
+
+struct ScoreWriter<'a> {
+    song : &'a Song,
+    prefer_flats : bool,
+    /// The start of every bar and the end of the last one.
+    barlines : Vec<u64>,
+    end : u64,
+}
+
+impl<'a> ScoreWriter<'a> {
+    fn new(song : &'a Song, prefer_flats : bool) -> Self {
+        let barlines = melody::barlines(song);
+
+        ScoreWriter {
+            song,
+            prefer_flats,
+            end : barlines[barlines.len() - 1],
+            barlines,
+        }
+    }
+
+    fn ppq(&self) -> u64 {
+        self.song.get_song_settings().get_pulses_per_quarter()
+    }
+
+    /// Every chord lasts until the next chord change, a duration multiplier
+    /// is used where a single note value does not fit.
+    fn chords(&self) -> String {
+        let chords = self.song.get_chords();
+        let mut parts = Vec::new();
+
+        let first = chords.first().map(|c| c.get_position().get_ticks_on()).unwrap_or(self.end);
+        if first > 0 {
+            parts.push(format!("s{}", self.duration(first)));
+        }
+        for (i, chord) in chords.iter().enumerate() {
+            let start = chord.get_position().get_ticks_on();
+            let end = chords.get(i + 1).map(|c| c.get_position().get_ticks_on()).unwrap_or(self.end);
+            if end > start {
+                parts.push(chord_name(chord.get_chord(), &self.duration(end - start)));
+            }
+        }
+
+        parts.join(" ")
+    }
+
+    fn duration(&self, ticks : u64) -> String {
+        match durations(ticks, self.ppq()).as_slice() {
+            [single] => single.clone(),
+            _ => multiplied(ticks, self.ppq()),
+        }
+    }
+
+    /// The meter and tempo changes between spacer rests.
+    fn global_changes(&self) -> String {
+        let settings = self.song.get_song_settings();
+        let mut changes : Vec<(u64, String)> = self.barlines[1..self.barlines.len() - 1].iter()
+            .filter(|b| settings.get_meter_at(**b) != settings.get_meter_at(**b - 1))
+            .map(|b| (*b, time(&settings.get_meter_at(*b))))
+            .collect();
+        let tempo_map = settings.get_tempo_map().to_steps(self.ppq());
+        changes.extend(tempo_map.get_changes().iter()
+            .filter(|c| c.get_tick() < self.end)
+            .map(|c| (c.get_tick(), format!("\\tempo 4 = {}", c.get_tempo().round()))));
+        changes.sort_by_key(|(tick, _)| *tick);
+
+        let mut out = String::new();
+        let mut tick = 0;
+        for (change_tick, change) in changes {
+            if change_tick > tick {
+                out.push_str(&format!("  s{}\n", self.duration(change_tick - tick)));
+                tick = change_tick;
+            }
+            out.push_str(&format!("  {}\n", change));
+        }
+
+        out
+    }
+
+    fn melody(&self) -> String {
+        let barlines = &self.barlines[1..self.barlines.len() - 1];
+        let events = melody::split_at(melody::fill_rests(melody::melody_events(self.song), 0, self.end), barlines);
+
+        let mut out = String::new();
+        for event in events {
+            if let Ok(bar) = barlines.binary_search(&event.start) {
+                out.push_str(if (bar + 1).is_multiple_of(4) { "|\n  " } else { "| " });
+            }
+
+            let pitch = match event.pitches.as_slice() {
+                [] => String::from("r"),
+                [single] => self.pitch(*single),
+                pitches => format!("<{}>", pitches.iter().map(|p| self.pitch(*p)).collect::<Vec<_>>().join(" ")),
+            };
+
+            let durations = durations(event.get_length(), self.ppq());
+            for (i, duration) in durations.iter().enumerate() {
+                out.push_str(&pitch);
+                out.push_str(duration);
+                let tied = i + 1 < durations.len() || event.tie_start;
+                if tied && !event.is_rest() {
+                    out.push('~');
+                }
+                out.push(' ');
+            }
+        }
+        out.push_str("\\bar \"|.\"");
+
+        out
+    }
+
+    /// A pitch in absolute octave notation, "c'" being middle C.
+    fn pitch(&self, pitch : u8) -> String {
+        let (name, octave) = NoteName::from_midi_pitch(pitch, self.prefer_flats);
+        let marks = if octave >= 3 { "'".repeat((octave - 3) as usize) } else { ",".repeat((3 - octave) as usize) };
+
+        format!("{}{}", pitch_name(name), marks)
+    }
+
+    /// One syllable per note onset, notes without lyrics get an empty syllable.
+    fn lyrics(&self) -> String {
+        let mut syllables = Vec::new();
+        for event in melody::melody_events(self.song) {
+            let lyric = self.song.get_lyrics().iter().find(|l| l.get_position().get_ticks_on() == event.start);
+            match lyric {
+                Some(l) => {
+                    let text = l.get_text();
+                    let text = if text.contains(' ') || text.contains('"') { format!("\"{}\"", escape(text)) } else { text.clone() };
+                    syllables.push(text);
+                    if matches!(l.get_syllabic(), Syllabic::Begin | Syllabic::Middle) {
+                        syllables.push(String::from("--"));
+                    }
+                },
+                None => syllables.push(String::from("_")),
+            }
+        }
+
+        syllables.join(" ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prelude::{SongChord, SongLyric, SongNote, TempoCurve};
+    use test_case::test_case;
+
+    #[test_case("C", "c4")]
+    #[test_case("Am7", "a4:m7")]
+    #[test_case("G7/B", "g4:7/b")]
+    #[test_case("Bbmaj7", "bes4:maj7")]
+    #[test_case("Ammaj7", "a4:m7+")]
+    #[test_case("F#m7b5", "fis4:m7.5-")]
+    #[test_case("Bdim7", "b4:dim7")]
+    #[test_case("C9", "c4:9")]
+    #[test_case("C7b9", "c4:7.9-")]
+    #[test_case("G7sus4", "g4:7sus4")]
+    #[test_case("Cadd9", "c4:5.9")]
+    #[test_case("D69", "d4:6.9")]
+    #[test_case("E5", "e4:1.5")]
+    fn test_chord_name(symbol : &str, name : &str) {
+        assert_eq!(chord_name(&symbol.parse().unwrap(), "4"), name);
+    }
+
+    #[test_case(960, vec!["4"])]
+    #[test_case(1440, vec!["4."])]
+    #[test_case(2400, vec!["2", "8"])]
+    #[test_case(4800, vec!["1", "4"])]
+    #[test_case(320, vec!["1*1/12"])]
+    fn test_durations(ticks : u64, expected : Vec<&str>) {
+        assert_eq!(durations(ticks, 960), expected);
+    }
+
+    fn lead_sheet() -> Song {
+        let mut song = Song::new();
+        song.get_song_meta_mut().set_title(Some(String::from("My \"Song\"")));
+        song.get_song_meta_mut().set_composer(Some(String::from("Jane Doe")));
+        song.get_song_settings_mut().set_key_signature(String::from("F Major"));
+        song.get_song_settings_mut().set_time_signature_numerator(3);
+        song.add_chord(SongChord::new(960, "F".parse().unwrap()));
+        song.add_chord(SongChord::new(2880, "C7".parse().unwrap()));
+        song.add_note(SongNote::new(960, 2880, 70));
+        song.add_note(SongNote::new(2880, 6720, 60));
+        song.add_lyric(SongLyric::new(960, "Hel", Syllabic::Begin));
+        song.add_lyric(SongLyric::new(2880, "lo", Syllabic::End));
+
+        song
+    }
+
+    #[test]
+    fn test_header_and_global() {
+        let ly = lead_sheet().to_lilypond();
+        assert!(ly.contains("  title = \"My \\\"Song\\\"\"\n"));
+        assert!(ly.contains("  composer = \"Jane Doe\"\n"));
+        assert!(ly.contains("  \\time 3/4\n  \\key f \\major\n  \\tempo 4 = 120\n"));
+    }
+
+    #[test]
+    fn test_chordmode() {
+        let ly = lead_sheet().to_lilypond();
+        assert!(ly.contains("harmonies = \\chordmode {\n  s4 f2 c1.:7\n}"), "{}", ly);
+    }
+
+    #[test]
+    fn test_melody_and_lyrics() {
+        let ly = lead_sheet().to_lilypond();
+        assert!(ly.contains("r4 bes'2 | c'2.~ | c'4 r2 \\bar \"|.\""), "{}", ly);
+        assert!(ly.contains("words = \\lyricmode {\n  Hel -- lo\n}"));
+        assert!(ly.contains("\\new Lyrics \\lyricsto \"melody\" \\words"));
+    }
+
+    #[test]
+    fn test_tempo_changes() {
+        let mut song = lead_sheet();
+        let tempo_map = song.get_song_settings_mut().get_tempo_map_mut();
+        tempo_map.add_change(960, 90.0);
+        tempo_map.add_ramp(2880, 90.0, 4800, 60.0, TempoCurve::Linear);
+        let ly = song.to_lilypond();
+        assert!(ly.contains("  \\tempo 4 = 120\n  s4\n  \\tempo 4 = 90\n  s2\n  \\tempo 4 = 82\n  s4\n  \\tempo 4 = 67\n  s4\n  \\tempo 4 = 60\n}"), "{}", ly);
+    }
+
+    #[test]
+    fn test_meter_changes() {
+        let mut song = lead_sheet();
+        song.get_song_settings_mut().add_time_signature_change_at_bar(1, 6, 8);
+        song.add_note(SongNote::new(6720, 7680, 62));
+        let ly = song.to_lilypond();
+        assert!(ly.contains("  \\time 3/4\n  \\key f \\major\n  \\tempo 4 = 120\n  s2.\n  \\time 6/8\n}"), "{}", ly);
+        assert!(ly.contains("r4 bes'2 | c'2.~ | c'4 d'4 r4 \\bar"), "{}", ly);
+    }
+
+    #[test]
+    fn test_additive_meter() {
+        let mut song = lead_sheet();
+        song.get_song_settings_mut().set_meter(Meter::with_groups(vec![2, 2, 3], 8));
+        assert!(song.to_lilypond().contains("  \\time 2,2,3 7/8\n"));
+    }
+
+    #[test]
+    fn test_without_melody() {
+        let mut song = Song::new();
+        song.add_chord(SongChord::new(0, "Dm".parse().unwrap()));
+        let ly = song.to_lilypond();
+        assert!(ly.contains("  d1:m\n"));
+        assert!(!ly.contains("melody ="));
+        assert!(!ly.contains("\\lyricsto"));
+    }
+}
@@ -0,0 +1,250 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::error::MusicalDataError;
+use crate::prelude::{Positionable, PositionableMut, Song, SongPosition};
+
+/// What a marker or region is used for.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MarkerCategory {
+    #[default]
+    Generic,
+    /// A part of the song like verse or chorus.
+    Section,
+    /// A rehearsal mark like "A" or "23".
+    Rehearsal,
+    /// A cue for a hit point or an entry.
+    Cue,
+    /// A range to play in a loop for practice.
+    Loop,
+}
+
+/// An RGB color, written as "#rrggbb".
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Color {
+    red : u8,
+    green : u8,
+    blue : u8,
+}
+
+impl Color {
+    pub fn new(red : u8, green : u8, blue : u8) -> Self {
+        Color { red, green, blue }
+    }
+
+    pub fn get_red(&self) -> u8 {
+        self.red
+    }
+
+    pub fn get_green(&self) -> u8 {
+        self.green
+    }
+
+    pub fn get_blue(&self) -> u8 {
+        self.blue
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.red, self.green, self.blue)
+    }
+}
+
+impl FromStr for Color {
+    type Err = MusicalDataError;
+
+    fn from_str(s : &str) -> Result<Self, Self::Err> {
+        let hex = s.trim().strip_prefix('#').unwrap_or(s.trim());
+        let value = match hex.len() {
+            6 if hex.chars().all(|c| c.is_ascii_hexdigit()) => u32::from_str_radix(hex, 16).ok(),
+            _ => None,
+        }.ok_or_else(|| MusicalDataError::InvalidColor(s.to_string()))?;
+
+        Ok(Color::new((value >> 16) as u8, (value >> 8) as u8, value as u8))
+    }
+}
+
+/// A named marker at a single position, or a region if the position has an end.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Marker {
+    pos : SongPosition,
+    name : String,
+    category : MarkerCategory,
+    color : Option<Color>,
+}
+
+impl Positionable for Marker {
+    fn get_position(&self) -> &SongPosition {
+        &self.pos
+    }
+}
+
+impl PositionableMut for Marker {
+    fn set_position(&mut self, position : SongPosition) {
+        self.pos = position;
+    }
+}
+
+impl Marker {
+    pub fn new(tick : u64, name : &str, category : MarkerCategory) -> Self {
+        Marker {
+            pos : SongPosition::new(tick),
+            name : String::from(name),
+            category,
+            color : None,
+        }
+    }
+
+    /// Create a region from `ticks_on` up to `ticks_off`.
+    pub fn region(ticks_on : u64, ticks_off : u64, name : &str, category : MarkerCategory) -> Result<Self, MusicalDataError> {
+        Ok(Marker {
+            pos : SongPosition::checked_from(ticks_on, ticks_off)?,
+            name : String::from(name),
+            category,
+            color : None,
+        })
+    }
+
+    /// Set the color and return the marker.
+    pub fn with_color(mut self, color : Color) -> Self {
+        self.color = Some(color);
+        self
+    }
+
+    /// True if the marker spans a range instead of a single position.
+    pub fn is_region(&self) -> bool {
+        self.pos.get_ticks_off().is_some()
+    }
+
+    pub fn get_name(&self) -> &String {
+        &self.name
+    }
+
+    pub fn set_name(&mut self, name : &str) {
+        self.name = String::from(name);
+    }
+
+    pub fn get_category(&self) -> MarkerCategory {
+        self.category
+    }
+
+    pub fn set_category(&mut self, category : MarkerCategory) {
+        self.category = category;
+    }
+
+    pub fn get_color(&self) -> Option<Color> {
+        self.color
+    }
+
+    pub fn set_color(&mut self, color : Option<Color>) {
+        self.color = color;
+    }
+}
+
+impl Song {
+    /// The markers of a category, ordered by position.
+    pub fn get_markers_by_category(&self, category : MarkerCategory) -> Vec<&Marker> {
+        self.get_markers().iter().filter(|m| m.category == category).collect()
+    }
+
+    /// The markers at the tick and the regions containing it.
+    pub fn get_markers_at(&self, tick : u64) -> Vec<&Marker> {
+        self.get_marker_timeline().get_at(tick)
+    }
+
+    /// The first marker named `name`.
+    pub fn find_marker(&self, name : &str) -> Option<&Marker> {
+        self.get_markers().iter().find(|m| m.name == name)
+    }
+
+    /// The innermost section region containing the tick: the one starting last, or the
+    /// shortest of those.
+    pub fn get_section_at(&self, tick : u64) -> Option<&Marker> {
+        self.get_markers_at(tick).into_iter()
+            .filter(|m| m.category == MarkerCategory::Section && m.is_region())
+            .min_by_key(|m| (std::cmp::Reverse(m.pos.get_ticks_on()), m.pos.get_length()))
+    }
+
+    /// The loop regions, ordered by position.
+    pub fn get_loops(&self) -> Vec<&Marker> {
+        self.get_markers_by_category(MarkerCategory::Loop).into_iter()
+            .filter(|m| m.is_region())
+            .collect()
+    }
+
+    /// The next rehearsal mark after the tick, for jumping forward.
+    pub fn get_next_rehearsal_mark(&self, tick : u64) -> Option<&Marker> {
+        let markers = self.get_markers();
+        let index = markers.partition_point(|m| m.pos.get_ticks_on() <= tick);
+        markers[index..].iter().find(|m| m.category == MarkerCategory::Rehearsal)
+    }
+
+    /// The last rehearsal mark before the tick, for jumping back.
+    pub fn get_previous_rehearsal_mark(&self, tick : u64) -> Option<&Marker> {
+        let markers = self.get_markers();
+        let index = markers.partition_point(|m| m.pos.get_ticks_on() < tick);
+        markers[..index].iter().rfind(|m| m.category == MarkerCategory::Rehearsal)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    fn song() -> Song {
+        let mut song = Song::new();
+        song.add_marker(Marker::region(0, 7680, "Verse", MarkerCategory::Section).unwrap());
+        song.add_marker(Marker::region(7680, 15360, "Chorus", MarkerCategory::Section).unwrap().with_color(Color::new(255, 0, 0)));
+        song.add_marker(Marker::new(0, "A", MarkerCategory::Rehearsal));
+        song.add_marker(Marker::new(7680, "B", MarkerCategory::Rehearsal));
+        song.add_marker(Marker::new(9000, "Hit", MarkerCategory::Cue));
+        song.add_marker(Marker::region(7680, 11520, "Practice", MarkerCategory::Loop).unwrap());
+        song
+    }
+
+    #[test_case("#ff8000", Some(Color::new(255, 128, 0)))]
+    #[test_case("00FF10", Some(Color::new(0, 255, 16)))]
+    #[test_case("#fff", None)]
+    #[test_case("#gg0000", None)]
+    #[test_case("+fffff", None)]
+    #[test_case("#+12345", None)]
+    fn test_color(s : &str, color : Option<Color>) {
+        assert_eq!(s.parse::<Color>().ok(), color);
+        if let Some(color) = color {
+            assert_eq!(color.to_string().parse::<Color>().unwrap(), color);
+        }
+    }
+
+    #[test]
+    fn test_region() {
+        assert_eq!(Marker::region(100, 50, "Back", MarkerCategory::Loop), Err(MusicalDataError::ReversedPosition(100, 50)));
+        let marker = Marker::region(0, 3840, "Intro", MarkerCategory::Section).unwrap();
+        assert!(marker.is_region());
+        assert!(!Marker::new(0, "Intro", MarkerCategory::Section).is_region());
+    }
+
+    #[test_case(0, vec!["A", "Verse"])]
+    #[test_case(7680, vec!["B", "Practice", "Chorus"])]
+    #[test_case(9000, vec!["Practice", "Chorus", "Hit"])]
+    #[test_case(12000, vec!["Chorus"])]
+    #[test_case(20000, vec![])]
+    fn test_markers_at(tick : u64, names : Vec<&str>) {
+        let song = song();
+        let found : Vec<&str> = song.get_markers_at(tick).iter().map(|m| m.get_name().as_str()).collect();
+        assert_eq!(found, names);
+    }
+
+    #[test]
+    fn test_queries() {
+        let song = song();
+        assert_eq!(song.get_section_at(8000).unwrap().get_name(), "Chorus");
+        assert_eq!(song.get_section_at(8000).unwrap().get_color(), Some(Color::new(255, 0, 0)));
+        assert_eq!(song.get_loops().len(), 1);
+        assert_eq!(song.find_marker("Hit").unwrap().get_category(), MarkerCategory::Cue);
+        assert_eq!(song.get_next_rehearsal_mark(0).unwrap().get_name(), "B");
+        assert_eq!(song.get_previous_rehearsal_mark(7680).unwrap().get_name(), "A");
+        assert!(song.get_next_rehearsal_mark(7680).is_none());
+    }
+}
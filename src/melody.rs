@@ -0,0 +1,155 @@
+use crate::prelude::{GridBar, Positionable, Song};
+
+/// A note, a chord or a rest of the melody. Exporters split these events at
+/// barlines and chord changes and tie the pieces of pitched events together.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct MelodyEvent {
+    pub start : u64,
+    pub end : u64,
+    /// Ordered from low to high, empty for rests.
+    pub pitches : Vec<u8>,
+    pub tie_start : bool,
+    pub tie_stop : bool,
+}
+
+impl MelodyEvent {
+    pub fn rest(start : u64, end : u64) -> Self {
+        MelodyEvent { start, end, pitches : Vec::new(), tie_start : false, tie_stop : false }
+    }
+
+    pub fn is_rest(&self) -> bool {
+        self.pitches.is_empty()
+    }
+
+    pub fn get_length(&self) -> u64 {
+        self.end - self.start
+    }
+}
+
+/// The melody of a song as a list of non overlapping events. Notes starting at the
+/// same tick are combined, overlapping notes are cut at the next onset.
+pub(crate) fn melody_events(song : &Song) -> Vec<MelodyEvent> {
+    let notes = song.get_notes();
+    let mut events = Vec::new();
+    let mut i = 0;
+    while i < notes.len() {
+        let start = notes[i].get_position().get_ticks_on();
+        let mut end = start;
+        let mut pitches = Vec::new();
+        while i < notes.len() && notes[i].get_position().get_ticks_on() == start {
+            end = end.max(notes[i].get_position().get_ticks_off().unwrap_or(start));
+            pitches.push(notes[i].get_pitch());
+            i += 1;
+        }
+        pitches.sort_unstable();
+        if let Some(next) = notes.get(i) {
+            end = end.min(next.get_position().get_ticks_on());
+        }
+        if end > start {
+            events.push(MelodyEvent { start, end, pitches, tie_start : false, tie_stop : false });
+        }
+    }
+
+    events
+}
+
+/// The tick after the last note or chord change of a song.
+pub(crate) fn song_end(song : &Song) -> u64 {
+    song.get_notes().iter()
+        .map(|n| n.get_position().get_ticks_off().unwrap_or(n.get_position().get_ticks_on()))
+        .chain(song.get_chords().iter().map(|c| c.get_position().get_ticks_on() + 1))
+        .max()
+        .unwrap_or(0)
+}
+
+/// The start ticks of the bars up to the end of the song followed by the end of the last
+/// bar, following the meter changes. A song has at least one bar.
+pub(crate) fn barlines(song : &Song) -> Vec<u64> {
+    let bars : Vec<GridBar> = song.get_song_settings().iter_bars(0, song_end(song).max(1)).collect();
+    let end = bars.last().map(|b| (b.start + b.length).round() as u64).unwrap_or_default();
+
+    bars.iter().map(|b| b.start_tick).chain([end]).collect()
+}
+
+/// Fill the gaps between the events with rests, so that the result covers `start..end`.
+/// Events outside of the range are dropped, events crossing its borders are clipped.
+pub(crate) fn fill_rests(events : Vec<MelodyEvent>, start : u64, end : u64) -> Vec<MelodyEvent> {
+    let mut result = Vec::new();
+    let mut cursor = start;
+    for mut event in events {
+        if event.end <= start || event.start >= end {
+            continue;
+        }
+        event.start = event.start.max(start);
+        event.end = event.end.min(end);
+        if event.start > cursor {
+            result.push(MelodyEvent::rest(cursor, event.start));
+        }
+        cursor = event.end;
+        result.push(event);
+    }
+    if cursor < end {
+        result.push(MelodyEvent::rest(cursor, end));
+    }
+
+    result
+}
+
+/// Split the events at the given ticks. The pieces of pitched events are tied together.
+pub(crate) fn split_at(events : Vec<MelodyEvent>, ticks : &[u64]) -> Vec<MelodyEvent> {
+    let mut result = Vec::new();
+    for event in events {
+        let mut cuts : Vec<u64> = ticks.iter().copied().filter(|t| *t > event.start && *t < event.end).collect();
+        cuts.sort_unstable();
+        cuts.dedup();
+
+        let mut start = event.start;
+        for (i, end) in cuts.iter().copied().chain(std::iter::once(event.end)).enumerate() {
+            let tied = !event.is_rest();
+            result.push(MelodyEvent {
+                start,
+                end,
+                pitches : event.pitches.clone(),
+                tie_start : if end == event.end { event.tie_start } else { tied },
+                tie_stop : if i == 0 { event.tie_stop } else { tied },
+            });
+            start = end;
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prelude::SongNote;
+
+    #[test]
+    fn test_melody_events() {
+        let mut song = Song::new();
+        song.add_note(SongNote::new(0, 960, 60));
+        song.add_note(SongNote::new(0, 480, 64));
+        song.add_note(SongNote::new(480, 960, 67));
+
+        let events = melody_events(&song);
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].pitches, vec![60, 64]);
+        assert_eq!(events[0].end, 480);
+        assert_eq!(song_end(&song), 960);
+    }
+
+    #[test]
+    fn test_fill_rests_and_split() {
+        let events = vec![MelodyEvent { start : 480, end : 2400, pitches : vec![60], tie_start : false, tie_stop : false }];
+        let events = split_at(fill_rests(events, 0, 3840), &[1920, 2880]);
+
+        assert_eq!(events.len(), 5);
+        assert!(events[0].is_rest());
+        assert_eq!((events[1].start, events[1].end, events[1].tie_start, events[1].tie_stop), (480, 1920, true, false));
+        assert_eq!((events[2].start, events[2].end, events[2].tie_start, events[2].tie_stop), (1920, 2400, false, true));
+        assert!(events[3].is_rest());
+        assert_eq!(events[3].get_length(), 480);
+        assert!(!events[4].tie_stop);
+    }
+}
@@ -0,0 +1,242 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::error::MusicalDataError;
+use crate::prelude::Rational;
+
+/// How strongly a note of the denominator within a bar is accented, from weak to strong.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Accent {
+    /// A subdivision of a beat like the second eighth of a beat in 6/8.
+    Weak,
+    Beat,
+    /// The secondary accent in the middle of a bar, like beat three of 4/4.
+    Strong,
+    Downbeat,
+}
+
+/// A time signature with its beat grouping. The groups are counted in notes of the
+/// denominator, e.g. `[3, 3]` for 6/8 with two dotted quarter beats or `[2, 2, 3]`
+/// for the additive 2+2+3/8.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Meter {
+    groups : Vec<u64>,
+    denominator : u64,
+}
+
+impl Default for Meter {
+    fn default() -> Self {
+        Meter::new(4, 4)
+    }
+}
+
+impl Meter {
+    /// Create a meter with the usual beat grouping. Multiples of three above three over
+    /// eighths or shorter notes are compound, i.e. 6/8, 9/8 and 12/8 have beats of three
+    /// eighths. All other meters have a beat on every note of the denominator.
+    pub fn new(numerator : u64, denominator : u64) -> Self {
+        Meter { groups : default_groups(numerator, denominator), denominator }
+    }
+
+    /// Create a meter from its beat groups, e.g. `[3, 2, 2]` for 3+2+2/8.
+    pub fn with_groups(groups : Vec<u64>, denominator : u64) -> Self {
+        Meter { groups, denominator }
+    }
+
+    pub fn get_numerator(&self) -> u64 {
+        self.groups.iter().sum()
+    }
+
+    pub fn get_denominator(&self) -> u64 {
+        self.denominator
+    }
+
+    /// The length of every beat in notes of the denominator.
+    pub fn get_groups(&self) -> &[u64] {
+        &self.groups
+    }
+
+    pub fn get_beat_count(&self) -> u64 {
+        self.groups.len() as u64
+    }
+
+    /// Whether every beat is made of three notes of the denominator, like in 6/8.
+    pub fn is_compound(&self) -> bool {
+        !self.groups.is_empty() && self.groups.iter().all(|g| *g == 3)
+    }
+
+    /// Whether the beats have different lengths, like in 2+2+3/8.
+    pub fn is_additive(&self) -> bool {
+        self.groups.windows(2).any(|w| w[0] != w[1])
+    }
+
+    /// Whether the grouping is the one `Meter::new` chooses for the time signature.
+    pub fn has_default_grouping(&self) -> bool {
+        self.groups == default_groups(self.get_numerator(), self.denominator)
+    }
+
+    /// The length of a note of the denominator in ticks, possibly fractional.
+    pub fn get_unit_length(&self, ppq : u64) -> Rational {
+        Rational::new(ppq as i128 * 4, self.denominator.max(1) as i128)
+    }
+
+    pub fn get_bar_length(&self, ppq : u64) -> Rational {
+        self.get_unit_length(ppq) * Rational::from(self.get_numerator())
+    }
+
+    /// The start of a beat in notes of the denominator from the start of the bar. Beats
+    /// past the end of the bar continue in the following bars.
+    pub fn get_beat_offset(&self, beat : u64) -> u64 {
+        if self.groups.is_empty() {
+            return 0;
+        }
+
+        let count = self.get_beat_count();
+        let bars = beat / count;
+        bars * self.get_numerator() + self.groups[..(beat % count) as usize].iter().sum::<u64>()
+    }
+
+    /// The length of a beat in notes of the denominator.
+    pub fn get_beat_length(&self, beat : u64) -> u64 {
+        if self.groups.is_empty() {
+            return 0;
+        }
+
+        self.groups[(beat % self.get_beat_count()) as usize]
+    }
+
+    /// The beat a note of the denominator belongs to and its index within the beat.
+    pub fn get_beat_of_unit(&self, unit : u64) -> (u64, u64) {
+        let mut start = 0;
+        for (beat, group) in self.groups.iter().enumerate() {
+            if unit < start + group {
+                return (beat as u64, unit - start);
+            }
+            start += group;
+        }
+
+        (self.get_beat_count().saturating_sub(1), unit.saturating_sub(start))
+    }
+
+    /// The accent of a note of the denominator within the bar. The first beat is the
+    /// downbeat, the middle beat of bars with an even number of at least four beats gets
+    /// a secondary accent, the other beats a beat accent and all notes within a beat
+    /// are weak.
+    pub fn get_accent(&self, unit : u64) -> Accent {
+        let (beat, in_beat) = self.get_beat_of_unit(unit);
+        let count = self.get_beat_count();
+        match (beat, in_beat) {
+            (_, i) if i > 0 => Accent::Weak,
+            (0, _) => Accent::Downbeat,
+            (b, _) if count >= 4 && count.is_multiple_of(2) && b == count / 2 => Accent::Strong,
+            _ => Accent::Beat,
+        }
+    }
+
+    /// The accents of all notes of the denominator of a bar.
+    pub fn get_accents(&self) -> Vec<Accent> {
+        (0..self.get_numerator()).map(|unit| self.get_accent(unit)).collect()
+    }
+}
+
+fn default_groups(numerator : u64, denominator : u64) -> Vec<u64> {
+    if denominator >= 8 && numerator > 3 && numerator.is_multiple_of(3) {
+        vec![3; (numerator / 3) as usize]
+    } else {
+        vec![1; numerator as usize]
+    }
+}
+
+/// Parse a meter like "6/8", "2+2+3/8" or "(3+2)/8".
+impl FromStr for Meter {
+    type Err = MusicalDataError;
+
+    fn from_str(s : &str) -> Result<Self, Self::Err> {
+        let invalid = || MusicalDataError::InvalidMeter(String::from(s));
+        let (numerator, denominator) = s.split_once('/').ok_or_else(invalid)?;
+        let denominator : u64 = denominator.trim().parse().map_err(|_| invalid())?;
+        let groups = numerator.trim().trim_start_matches('(').trim_end_matches(')')
+            .split('+')
+            .map(|g| g.trim().parse::<u64>().ok().filter(|g| *g > 0))
+            .collect::<Option<Vec<u64>>>()
+            .ok_or_else(invalid)?;
+        if denominator == 0 {
+            return Err(invalid());
+        }
+
+        if groups.len() == 1 {
+            return Ok(Meter::new(groups[0], denominator));
+        }
+
+        Ok(Meter::with_groups(groups, denominator))
+    }
+}
+
+/// Write the meter like "6/8", with the groups like "2+2+3/8" if the grouping is not
+/// the default one.
+impl fmt::Display for Meter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.has_default_grouping() || self.groups.is_empty() {
+            return write!(f, "{}/{}", self.get_numerator(), self.denominator);
+        }
+
+        let groups : Vec<String> = self.groups.iter().map(|g| g.to_string()).collect();
+        write!(f, "{}/{}", groups.join("+"), self.denominator)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    #[test_case("4/4", &[1, 1, 1, 1], false)]
+    #[test_case("3/8", &[1, 1, 1], false)]
+    #[test_case("6/8", &[3, 3], true)]
+    #[test_case("12/8", &[3, 3, 3, 3], true)]
+    #[test_case("6/4", &[1, 1, 1, 1, 1, 1], false)]
+    #[test_case("2+2+3/8", &[2, 2, 3], false)]
+    #[test_case("3+3/8", &[3, 3], true)]
+    fn test_parse(text : &str, groups : &[u64], compound : bool) {
+        let meter : Meter = text.parse().unwrap();
+        assert_eq!(meter.get_groups(), groups);
+        assert_eq!(meter.is_compound(), compound);
+    }
+
+    #[test_case("(3+2)/8", "3+2/8")]
+    #[test_case("3+3/8", "6/8")]
+    #[test_case("1+1+1/4", "3/4")]
+    #[test_case(" 7 / 8 ", "7/8")]
+    fn test_display(text : &str, expected : &str) {
+        assert_eq!(text.parse::<Meter>().unwrap().to_string(), expected);
+    }
+
+    #[test_case("")]
+    #[test_case("4")]
+    #[test_case("4/0")]
+    #[test_case("2++3/8")]
+    #[test_case("a/4")]
+    fn test_parse_invalid(text : &str) {
+        assert_eq!(text.parse::<Meter>(), Err(MusicalDataError::InvalidMeter(String::from(text))));
+    }
+
+    #[test]
+    fn test_beats() {
+        let meter = Meter::with_groups(vec![2, 2, 3], 8);
+        assert!(meter.is_additive());
+        assert_eq!(meter.get_numerator(), 7);
+        assert_eq!(meter.get_beat_offset(2), 4);
+        assert_eq!(meter.get_beat_offset(4), 9);
+        assert_eq!(meter.get_beat_length(2), 3);
+        assert_eq!(meter.get_beat_of_unit(5), (2, 1));
+        assert_eq!(meter.get_bar_length(960), Rational::from(3360u64));
+        assert_eq!(Meter::new(4, 3).get_unit_length(960), Rational::new(1280, 1));
+    }
+
+    #[test_case("4/4", vec![Accent::Downbeat, Accent::Beat, Accent::Strong, Accent::Beat])]
+    #[test_case("6/8", vec![Accent::Downbeat, Accent::Weak, Accent::Weak, Accent::Beat, Accent::Weak, Accent::Weak])]
+    #[test_case("2+2+3/8", vec![Accent::Downbeat, Accent::Weak, Accent::Beat, Accent::Weak, Accent::Beat, Accent::Weak, Accent::Weak])]
+    fn test_accents(text : &str, accents : Vec<Accent>) {
+        assert_eq!(text.parse::<Meter>().unwrap().get_accents(), accents);
+    }
+}
@@ -0,0 +1,263 @@
+use std::f64::consts::TAU;
+
+use crate::prelude::{Accent, Rational, SongSettings, TickOffset};
+
+/// A single click of a metronome.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Click {
+    /// The tick from the start of the song, negative for a count-in before it.
+    pub tick : TickOffset,
+    /// The bar counted from zero, for a count-in from the first count-in bar.
+    pub bar : u64,
+    pub beat : u64,
+    /// The subdivision within the beat, zero on the beat itself.
+    pub subdivision : u64,
+    pub accent : Accent,
+    pub count_in : bool,
+}
+
+/// Generates the clicks of a click track from the tempo and meter maps of a song. The
+/// beats follow the beat grouping of the meter, so 6/8 clicks twice per bar and 2+2+3/8
+/// clicks three times with a longer last beat.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Metronome {
+    /// The number of clicks per beat, 1 clicks on the beats only.
+    subdivision : u64,
+    count_in_bars : u64,
+    /// The length of a rendered click in seconds.
+    click_length : f64,
+}
+
+impl Default for Metronome {
+    fn default() -> Self {
+        Metronome::new()
+    }
+}
+
+impl Metronome {
+    /// Create a metronome clicking on every beat without count-in.
+    pub fn new() -> Self {
+        Metronome {
+            subdivision : 1,
+            count_in_bars : 0,
+            click_length : 0.03,
+        }
+    }
+
+    pub fn get_subdivision(&self) -> u64 {
+        self.subdivision
+    }
+
+    pub fn get_count_in_bars(&self) -> u64 {
+        self.count_in_bars
+    }
+
+    pub fn get_click_length(&self) -> f64 {
+        self.click_length
+    }
+
+    /// Set the number of clicks per beat, at least 1.
+    pub fn set_subdivision(&mut self, subdivision : u64) {
+        self.subdivision = subdivision.max(1);
+    }
+
+    pub fn set_count_in_bars(&mut self, count_in_bars : u64) {
+        self.count_in_bars = count_in_bars;
+    }
+
+    pub fn set_click_length(&mut self, click_length : f64) {
+        self.click_length = click_length.max(0.0);
+    }
+
+    /// The clicks from `start` up to `end`, preceded by the count-in bars in the meter and
+    /// tempo at `start`.
+    pub fn get_clicks(&self, settings : &SongSettings, start : u64, end : u64) -> Vec<Click> {
+        let mut clicks = self.count_in(settings, start);
+        for beat in settings.iter_beats(start, end) {
+            for (offset, subdivision) in self.subdivide(beat.length) {
+                let tick = beat.start + offset;
+                if tick >= Rational::from(end) {
+                    break;
+                }
+                clicks.push(Click {
+                    tick : TickOffset::new(tick.round() as i64),
+                    bar : beat.bar,
+                    beat : beat.beat,
+                    subdivision,
+                    accent : if subdivision == 0 { beat.accent } else { Accent::Weak },
+                    count_in : false,
+                });
+            }
+        }
+
+        clicks
+    }
+
+    /// Render the clicks from `start` up to `end` including the count-in as mono samples
+    /// at the sample rate of the song. The buffer starts with the count-in.
+    pub fn render(&self, settings : &SongSettings, start : u64, end : u64) -> Vec<f32> {
+        let sample_rate = settings.get_sample_rate().max(1) as f64;
+        let count_in_samples = self.count_in_ticks(settings, start) as f64 * seconds_per_tick(settings, start) * sample_rate;
+        let start_sample = settings.ticks_to_samples(start) as f64;
+        let length = (settings.ticks_to_samples(end.max(start)) as f64 - start_sample + count_in_samples).round() as usize;
+
+        let mut buffer = vec![0.0f32; length];
+        let click_samples = (self.click_length * sample_rate).round() as usize;
+        for click in self.get_clicks(settings, start, end) {
+            let position = match click.count_in {
+                true => (click.tick.get_ticks() - start as i64 + self.count_in_ticks(settings, start) as i64) as f64 * seconds_per_tick(settings, start) * sample_rate,
+                false => settings.ticks_to_samples(click.tick.get_ticks() as u64) as f64 - start_sample + count_in_samples,
+            }.round() as usize;
+
+            let (frequency, gain) = sound(click.accent);
+            for (i, sample) in buffer.iter_mut().skip(position).take(click_samples).enumerate() {
+                let t = i as f64 / sample_rate;
+                let envelope = (-5.0 * i as f64 / click_samples as f64).exp();
+                *sample += (gain * envelope * (TAU * frequency * t).sin()) as f32;
+            }
+        }
+
+        buffer
+    }
+
+    /// The exact offsets of the clicks within a beat with their subdivision.
+    fn subdivide(&self, beat_length : Rational) -> Vec<(Rational, u64)> {
+        (0..self.subdivision)
+            .map(|s| (beat_length * Rational::new(s as i128, self.subdivision as i128), s))
+            .collect()
+    }
+
+    fn count_in(&self, settings : &SongSettings, start : u64) -> Vec<Click> {
+        let meter = settings.get_meter_at(start);
+        let unit_length = meter.get_unit_length(settings.get_pulses_per_quarter());
+        let count_in_start = start as i64 - self.count_in_ticks(settings, start) as i64;
+
+        let mut clicks = Vec::new();
+        for bar in 0..self.count_in_bars {
+            let bar_start = meter.get_bar_length(settings.get_pulses_per_quarter()) * Rational::from(bar);
+            for beat in 0..meter.get_beat_count() {
+                let beat_start = bar_start + unit_length * Rational::from(meter.get_beat_offset(beat));
+                let beat_length = unit_length * Rational::from(meter.get_beat_length(beat));
+                for (offset, subdivision) in self.subdivide(beat_length) {
+                    clicks.push(Click {
+                        tick : TickOffset::new(count_in_start + (beat_start + offset).round() as i64),
+                        bar,
+                        beat,
+                        subdivision,
+                        accent : if subdivision == 0 { meter.get_accent(meter.get_beat_offset(beat)) } else { Accent::Weak },
+                        count_in : true,
+                    });
+                }
+            }
+        }
+
+        clicks
+    }
+
+    fn count_in_ticks(&self, settings : &SongSettings, start : u64) -> u64 {
+        let bar_length = settings.get_meter_at(start).get_bar_length(settings.get_pulses_per_quarter());
+        (bar_length * Rational::from(self.count_in_bars)).round() as u64
+    }
+}
+
+fn seconds_per_tick(settings : &SongSettings, tick : u64) -> f64 {
+    60.0 / (settings.get_tempo_at(tick) * settings.get_pulses_per_quarter() as f64)
+}
+
+/// The frequency and gain of a click.
+fn sound(accent : Accent) -> (f64, f64) {
+    match accent {
+        Accent::Downbeat => (1760.0, 1.0),
+        Accent::Strong => (1320.0, 0.8),
+        Accent::Beat => (1320.0, 0.6),
+        Accent::Weak => (880.0, 0.4),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    fn ticks(clicks : &[Click]) -> Vec<(i64, Accent)> {
+        clicks.iter().map(|c| (c.tick.get_ticks(), c.accent)).collect()
+    }
+
+    #[test]
+    fn test_beats() {
+        let clicks = Metronome::new().get_clicks(&SongSettings::default(), 0, 3840 + 960);
+        assert_eq!(ticks(&clicks), vec![
+            (0, Accent::Downbeat), (960, Accent::Beat), (1920, Accent::Strong), (2880, Accent::Beat),
+            (3840, Accent::Downbeat),
+        ]);
+        assert_eq!((clicks[4].bar, clicks[4].beat), (1, 0));
+    }
+
+    #[test]
+    fn test_meter_changes() {
+        // A bar of 4/4, a bar of 6/8 and a bar of 2+2+3/8.
+        let mut settings = SongSettings::default();
+        settings.add_time_signature_change_at_bar(1, 6, 8);
+        settings.add_meter_change_at_bar(2, "2+2+3/8".parse().unwrap());
+        let clicks = Metronome::new().get_clicks(&settings, 3840, 3840 + 2880 + 3360);
+        assert_eq!(ticks(&clicks), vec![
+            (3840, Accent::Downbeat), (3840 + 1440, Accent::Beat),
+            (6720, Accent::Downbeat), (6720 + 960, Accent::Beat), (6720 + 1920, Accent::Beat),
+        ]);
+        assert_eq!(clicks[2].bar, 2);
+    }
+
+    #[test]
+    fn test_pickup() {
+        let mut settings = SongSettings::default();
+        settings.set_pickup(960);
+        let clicks = Metronome::new().get_clicks(&settings, 0, 2880);
+        assert_eq!(ticks(&clicks), vec![(0, Accent::Beat), (960, Accent::Downbeat), (1920, Accent::Beat)]);
+        assert_eq!((clicks[0].bar, clicks[0].beat), (0, 3));
+    }
+
+    #[test_case(1, 4)]
+    #[test_case(2, 8)]
+    #[test_case(3, 12)]
+    fn test_subdivisions(subdivision : u64, count : usize) {
+        let mut metronome = Metronome::new();
+        metronome.set_subdivision(subdivision);
+        let clicks = metronome.get_clicks(&SongSettings::default(), 0, 3840);
+        assert_eq!(clicks.len(), count);
+        assert_eq!(clicks[1].tick.get_ticks(), 960 / subdivision as i64);
+        assert!(clicks.iter().filter(|c| c.subdivision > 0).all(|c| c.accent == Accent::Weak));
+    }
+
+    #[test]
+    fn test_range_and_count_in() {
+        let mut metronome = Metronome::new();
+        metronome.set_count_in_bars(1);
+        let clicks = metronome.get_clicks(&SongSettings::default(), 3840 + 960, 2 * 3840);
+        assert_eq!(ticks(&clicks), vec![
+            (960, Accent::Downbeat), (1920, Accent::Beat), (2880, Accent::Strong), (3840, Accent::Beat),
+            (3840 + 960, Accent::Beat), (3840 + 1920, Accent::Strong), (3840 + 2880, Accent::Beat),
+        ]);
+        assert!(clicks[..4].iter().all(|c| c.count_in));
+
+        // A count-in before the start of the song has negative ticks.
+        let clicks = metronome.get_clicks(&SongSettings::default(), 0, 960);
+        assert_eq!(clicks[0].tick.get_ticks(), -3840);
+        assert_eq!(clicks[4].tick.get_ticks(), 0);
+    }
+
+    #[test]
+    fn test_render() {
+        let mut metronome = Metronome::new();
+        metronome.set_count_in_bars(1);
+        let settings = SongSettings::default();
+        let samples_per_beat = settings.ticks_to_samples(960) as usize;
+        let buffer = metronome.render(&settings, 0, 3840);
+        assert_eq!(buffer.len(), 8 * samples_per_beat);
+        // Every beat starts with a click and is silent before the next one.
+        for beat in 0..8 {
+            let start = beat * samples_per_beat;
+            assert!(buffer[start..start + 100].iter().any(|s| s.abs() > 0.1));
+            assert!(buffer[start + samples_per_beat / 2..start + samples_per_beat].iter().all(|s| *s == 0.0));
+        }
+    }
+}
@@ -0,0 +1,1048 @@
+use crate::error::MusicalDataError;
+use crate::key_signature::{fifths_from_key_name, key_name_from_fifths};
+use crate::prelude::{Chord, ChordFuntion, ChordType, Duration, Meter, NoteMod, NoteName, NoteValue, Positionable, Song, SongChord, SongPosition, Syllabic};
+use crate::melody::{self, MelodyEvent};
+use crate::xml::{self, XmlElement};
+
+use NoteMod::{DoubleFlat, Flat, Normal, Sharp};
+
+/// Maps the MusicXML `<kind>` values onto a chord type and its additional intervals.
+const KINDS : [(&str, ChordType, &[ChordFuntion]); 33] = [
+    ("major", ChordType::Major, &[]),
+    ("minor", ChordType::Minor, &[]),
+    ("augmented", ChordType::Augmented, &[]),
+    ("diminished", ChordType::Diminished, &[]),
+    ("dominant", ChordType::Major, &[ChordFuntion::Seventh(Flat)]),
+    ("major-seventh", ChordType::Major, &[ChordFuntion::Seventh(Normal)]),
+    ("minor-seventh", ChordType::Minor, &[ChordFuntion::Seventh(Flat)]),
+    ("diminished-seventh", ChordType::Diminished, &[ChordFuntion::Seventh(DoubleFlat)]),
+    ("augmented-seventh", ChordType::Augmented, &[ChordFuntion::Seventh(Flat)]),
+    ("half-diminished", ChordType::Diminished, &[ChordFuntion::Seventh(Flat)]),
+    ("major-minor", ChordType::Minor, &[ChordFuntion::Seventh(Normal)]),
+    ("major-sixth", ChordType::Major, &[ChordFuntion::Sixth(Normal)]),
+    ("minor-sixth", ChordType::Minor, &[ChordFuntion::Sixth(Normal)]),
+    ("dominant-ninth", ChordType::Major, &[ChordFuntion::Seventh(Flat), ChordFuntion::Nineth(Normal)]),
+    ("major-ninth", ChordType::Major, &[ChordFuntion::Seventh(Normal), ChordFuntion::Nineth(Normal)]),
+    ("minor-ninth", ChordType::Minor, &[ChordFuntion::Seventh(Flat), ChordFuntion::Nineth(Normal)]),
+    ("dominant-11th", ChordType::Major, &[ChordFuntion::Seventh(Flat), ChordFuntion::Nineth(Normal), ChordFuntion::Eleventh(Normal)]),
+    ("major-11th", ChordType::Major, &[ChordFuntion::Seventh(Normal), ChordFuntion::Nineth(Normal), ChordFuntion::Eleventh(Normal)]),
+    ("minor-11th", ChordType::Minor, &[ChordFuntion::Seventh(Flat), ChordFuntion::Nineth(Normal), ChordFuntion::Eleventh(Normal)]),
+    ("dominant-13th", ChordType::Major, &[ChordFuntion::Seventh(Flat), ChordFuntion::Nineth(Normal), ChordFuntion::Eleventh(Normal), ChordFuntion::Thirteenth(Normal)]),
+    ("major-13th", ChordType::Major, &[ChordFuntion::Seventh(Normal), ChordFuntion::Nineth(Normal), ChordFuntion::Eleventh(Normal), ChordFuntion::Thirteenth(Normal)]),
+    ("minor-13th", ChordType::Minor, &[ChordFuntion::Seventh(Flat), ChordFuntion::Nineth(Normal), ChordFuntion::Eleventh(Normal), ChordFuntion::Thirteenth(Normal)]),
+    ("suspended-second", ChordType::Sus(2), &[]),
+    ("suspended-fourth", ChordType::Sus(4), &[]),
+    ("power", ChordType::Power, &[]),
+    ("pedal", ChordType::Power, &[]),
+    ("Neapolitan", ChordType::Major, &[]),
+    ("Italian", ChordType::Major, &[ChordFuntion::Sixth(Sharp)]),
+    ("French", ChordType::Major, &[ChordFuntion::Fourth(Sharp), ChordFuntion::Sixth(Sharp)]),
+    ("German", ChordType::Major, &[ChordFuntion::Sixth(Sharp)]),
+    ("Tristan", ChordType::Major, &[ChordFuntion::Fourth(Sharp), ChordFuntion::Sixth(Sharp), ChordFuntion::Nineth(Sharp)]),
+    ("other", ChordType::Major, &[]),
+    ("none", ChordType::Major, &[]),
+];
+
+impl Song {
+    /// Read a song from a MusicXML document. Both `score-partwise` and `score-timewise`
+    /// documents are supported. Metadata, the initial settings and the `<harmony>`
+    /// elements of the first part that has any are imported, as parts like piano and
+    /// guitar often repeat the same chords.
+    pub fn from_music_xml(input : &str) -> Result<Song, MusicalDataError> {
+        let root = xml::parse(input)?;
+        let parts = match root.name.as_str() {
+            "score-partwise" => partwise_measures(&root),
+            "score-timewise" => timewise_measures(&root),
+            name => return Err(MusicalDataError::InvalidMusicXml(format!("unknown root element '{}'", name))),
+        };
+
+        let mut song = Song::new();
+        read_meta(&root, &mut song);
+
+        // The first <divisions> found in the document define the song's resolution.
+        let ppq = parts.iter()
+            .flat_map(|(_, measures)| measures.iter())
+            .flat_map(|m| m.children_named("attributes"))
+            .find_map(|a| a.child_text("divisions"))
+            .map(|d| parse_number::<u64>(&d, "divisions"))
+            .transpose()?
+            .unwrap_or(song.get_song_settings().get_pulses_per_quarter());
+        if ppq == 0 {
+            return Err(MusicalDataError::InvalidMusicXml(String::from("divisions must be positive")));
+        }
+        song.get_song_settings_mut().set_pulses_per_quarter(ppq);
+
+        let mut reader = Reader::default();
+        for (_, measures) in &parts {
+            reader.read_part(measures, ppq, &mut song)?;
+        }
+        song.get_chord_timeline_mut().extend(reader.chords);
+
+        Ok(song)
+    }
+}
+
+/// The elements that contain the music of each measure, grouped by part.
+fn partwise_measures(root : &XmlElement) -> Vec<(String, Vec<&XmlElement>)> {
+    root.children_named("part")
+        .map(|p| (String::from(p.attribute("id").unwrap_or_default()), p.children_named("measure").collect()))
+        .collect()
+}
+
+fn timewise_measures(root : &XmlElement) -> Vec<(String, Vec<&XmlElement>)> {
+    let mut parts : Vec<(String, Vec<&XmlElement>)> = Vec::new();
+    for measure in root.children_named("measure") {
+        for part in measure.children_named("part") {
+            let id = String::from(part.attribute("id").unwrap_or_default());
+            match parts.iter_mut().find(|(i, _)| *i == id) {
+                Some((_, measures)) => measures.push(part),
+                None => parts.push((id, vec![part])),
+            }
+        }
+    }
+
+    parts
+}
+
+fn read_meta(root : &XmlElement, song : &mut Song) {
+    let meta = song.get_song_meta_mut();
+    let title = root.child("work")
+        .and_then(|w| w.child_text("work-title"))
+        .or_else(|| root.child_text("movement-title"));
+    meta.set_title(title);
+
+    if let Some(identification) = root.child("identification") {
+        for creator in identification.children_named("creator") {
+            let name = Some(creator.text());
+            match creator.attribute("type") {
+                Some("composer") => meta.set_composer(name),
+                Some("lyricist") => meta.set_songwriter(name),
+                Some("arranger") => meta.set_arranger(name),
+                _ => {},
+            }
+        }
+
+        let rights : Vec<String> = identification.children_named("rights").map(|r| r.text()).collect();
+        if !rights.is_empty() {
+            meta.set_copyright(Some(rights.join("\n")));
+        }
+    }
+}
+
+fn parse_number<T : std::str::FromStr>(s : &str, what : &str) -> Result<T, MusicalDataError> {
+    s.trim().parse::<T>()
+        .map_err(|_| MusicalDataError::InvalidMusicXml(format!("invalid {} '{}'", what, s)))
+}
+
+/// Keeps track of the song wide settings while the parts are read.
+#[derive(Default)]
+struct Reader {
+    time_read : bool,
+    key_read : bool,
+    /// The chords of the first part with chords, added to the song at once.
+    chords : Vec<SongChord>,
+}
+
+impl Reader {
+    fn read_part(&mut self, measures : &[&XmlElement], ppq : u64, song : &mut Song) -> Result<(), MusicalDataError> {
+        let mut divisions : i64 = ppq as i64;
+        let mut measure_start : i64 = 0;
+        let mut chords = Vec::new();
+
+        for measure in measures {
+            let mut cursor : i64 = 0;
+            let mut measure_length : i64 = 0;
+
+            for element in measure.elements() {
+                let to_ticks = |value : i64| value * ppq as i64 / divisions;
+                match element.name.as_str() {
+                    "attributes" => {
+                        if let Some(d) = element.child_text("divisions") {
+                            divisions = parse_number(&d, "divisions")?;
+                            if divisions <= 0 {
+                                return Err(MusicalDataError::InvalidMusicXml(String::from("divisions must be positive")));
+                            }
+                        }
+                        self.read_attributes(element, (measure_start + cursor).max(0) as u64, song)?;
+                    },
+                    "direction" => {
+                        let offset = match element.child_text("offset") {
+                            Some(o) => to_ticks(parse_number(&o, "offset")?),
+                            None => 0,
+                        };
+                        let tick = (measure_start + cursor + offset).max(0) as u64;
+                        for sound in element.children_named("sound") {
+                            read_sound(sound, tick, song)?;
+                        }
+                    },
+                    "sound" => read_sound(element, (measure_start + cursor).max(0) as u64, song)?,
+                    "harmony" => {
+                        let offset = match element.child_text("offset") {
+                            Some(o) => to_ticks(parse_number(&o, "offset")?),
+                            None => 0,
+                        };
+                        if let Some(chord) = read_harmony(element)? {
+                            let tick = (measure_start + cursor + offset).max(0) as u64;
+                            chords.push(SongChord::new(tick, chord));
+                        }
+                    },
+                    // Chord notes share the onset of the previous note, grace notes take no time.
+                    "note" if element.has_child("chord") || element.has_child("grace") => {},
+                    "note" | "forward" => {
+                        if let Some(d) = element.child_text("duration") {
+                            cursor += to_ticks(parse_number(&d, "duration")?);
+                        }
+                    },
+                    "backup" => {
+                        if let Some(d) = element.child_text("duration") {
+                            cursor -= to_ticks(parse_number(&d, "duration")?);
+                        }
+                    },
+                    _ => {},
+                }
+                measure_length = measure_length.max(cursor);
+            }
+
+            if measure_length == 0 {
+                let meter = song.get_song_settings().get_meter_at(measure_start.max(0) as u64);
+                measure_length = meter.get_bar_length(ppq).floor() as i64;
+            }
+            measure_start += measure_length;
+        }
+        if self.chords.is_empty() {
+            self.chords = chords;
+        }
+
+        Ok(())
+    }
+
+    /// Read the time and key signature. The first time signature is the time signature of the
+    /// song, later changes are added to the time signature map.
+    fn read_attributes(&mut self, attributes : &XmlElement, tick : u64, song : &mut Song) -> Result<(), MusicalDataError> {
+        if let Some(time) = attributes.child("time") {
+            if let (Some(beats), Some(beat_type)) = (time.child_text("beats"), time.child_text("beat-type")) {
+                // Additive signatures like 3+2 keep their beat grouping.
+                let mut groups = Vec::new();
+                for b in beats.split('+') {
+                    groups.push(parse_number::<u64>(b, "beats")?);
+                }
+                let denominator = parse_number(&beat_type, "beat-type")?;
+                let meter = match groups[..] {
+                    [numerator] => Meter::new(numerator, denominator),
+                    _ => Meter::with_groups(groups, denominator),
+                };
+                let settings = song.get_song_settings_mut();
+                if !self.time_read {
+                    settings.set_meter(meter);
+                    self.time_read = true;
+                } else if settings.get_meter_at(tick) != meter {
+                    settings.get_time_signature_map_mut().add_meter_change(tick, meter);
+                }
+            }
+        }
+
+        if let Some(key) = attributes.child("key") {
+            if !self.key_read {
+                if let Some(fifths) = key.child_text("fifths") {
+                    let mode = key.child_text("mode").unwrap_or(String::from("major"));
+                    if let Some(name) = key_name_from_fifths(parse_number(&fifths, "fifths")?, &mode) {
+                        song.get_song_settings_mut().set_key_signature(name);
+                        self.key_read = true;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+}
+
+/// Read the tempo of a `<sound>` element. The tempo at the start is the tempo of the
+/// song, later tempos are added to the tempo map.
+fn read_sound(sound : &XmlElement, tick : u64, song : &mut Song) -> Result<(), MusicalDataError> {
+    if let Some(tempo) = sound.attribute("tempo") {
+        let tempo : f64 = parse_number(tempo, "tempo")?;
+        if !(tempo > 0.0 && tempo.is_finite()) {
+            return Err(MusicalDataError::InvalidMusicXml(format!("invalid tempo '{}'", tempo)));
+        }
+        let settings = song.get_song_settings_mut();
+        if tick == 0 {
+            settings.set_tempo(tempo);
+        } else {
+            settings.get_tempo_map_mut().add_change(tick, tempo);
+        }
+    }
+
+    Ok(())
+}
+
+fn read_note_name(element : &XmlElement, step : &str, alter : &str) -> Result<Option<NoteName>, MusicalDataError> {
+    let step = match element.child_text(step) {
+        Some(s) => s,
+        None => return Ok(None),
+    };
+
+    let alter = match element.child_text(alter) {
+        Some(a) => parse_number::<f64>(&a, "alter")?.round() as i64,
+        None => 0,
+    };
+
+    let m = NoteMod::from_alter(alter)
+        .ok_or_else(|| MusicalDataError::InvalidMusicXml(format!("unsupported alter '{}'", alter)))?;
+    let name = step.chars().next().and_then(|c| NoteName::from_step(c, m))
+        .ok_or_else(|| MusicalDataError::InvalidMusicXml(format!("invalid step '{}'", step)))?;
+
+    Ok(Some(name))
+}
+
+/// A time signature, additive meters like 2+2+3/8 keep their grouping.
+fn time_element(meter : &Meter) -> XmlElement {
+    let beats = if meter.has_default_grouping() {
+        meter.get_numerator().to_string()
+    } else {
+        meter.get_groups().iter().map(|g| g.to_string()).collect::<Vec<String>>().join("+")
+    };
+
+    let mut time = XmlElement::new("time");
+    time.push_text("beats", &beats);
+    time.push_text("beat-type", &meter.get_denominator().to_string());
+
+    time
+}
+
+/// A metronome mark with a sound element, `offset` ticks after the current position.
+fn tempo_direction(tempo : f64, offset : u64) -> XmlElement {
+    let mut metronome = XmlElement::new("metronome");
+    metronome.push_text("beat-unit", "quarter");
+    metronome.push_text("per-minute", &tempo.to_string());
+    let mut direction_type = XmlElement::new("direction-type");
+    direction_type.push(metronome);
+    let mut sound = XmlElement::new("sound");
+    sound.set_attribute("tempo", &tempo.to_string());
+    let mut direction = XmlElement::new("direction");
+    direction.set_attribute("placement", "above");
+    direction.push(direction_type);
+    if offset > 0 {
+        direction.push_text("offset", &offset.to_string());
+    }
+    direction.push(sound);
+
+    direction
+}
+
+/// Convert a `<harmony>` element into a chord. Returns `None` for "no chord" and
+/// for harmonies without a root (i.e. roman numeral functions).
+fn read_harmony(harmony : &XmlElement) -> Result<Option<Chord>, MusicalDataError> {
+    let root = match harmony.child("root") {
+        Some(r) => read_note_name(r, "root-step", "root-alter")?,
+        None => None,
+    };
+    let root = match root {
+        Some(r) => r,
+        None => return Ok(None),
+    };
+
+    let kind = harmony.child_text("kind").unwrap_or(String::from("major"));
+    if kind == "none" {
+        return Ok(None);
+    }
+
+    let (chord_type, intervals) = KINDS.iter()
+        .find(|(k, _, _)| *k == kind)
+        .map(|(_, t, i)| (*t, *i))
+        .ok_or_else(|| MusicalDataError::InvalidMusicXml(format!("unknown chord kind '{}'", kind)))?;
+
+    let mut chord = Chord::new(root, chord_type);
+    for interval in intervals {
+        chord.add_interval(*interval);
+    }
+
+    if let Some(bass) = harmony.child("bass") {
+        chord.set_base(read_note_name(bass, "bass-step", "bass-alter")?);
+    }
+
+    for degree in harmony.children_named("degree") {
+        let value : u64 = parse_number(&degree.child_text("degree-value").unwrap_or_default(), "degree-value")?;
+        let alter = match degree.child_text("degree-alter") {
+            Some(a) => parse_number::<f64>(&a, "degree-alter")?.round() as i64,
+            None => 0,
+        };
+        let m = NoteMod::from_alter(alter).unwrap_or_default();
+        match degree.child_text("degree-type").as_deref() {
+            Some("subtract") => chord.remove_interval(value),
+            _ => {
+                if let Some(interval) = ChordFuntion::from_degree(value, m) {
+                    chord.add_interval(interval);
+                }
+            },
+        }
+    }
+
+    Ok(Some(chord))
+}
+
+const DOCTYPE : &str = "<!DOCTYPE score-partwise PUBLIC \"-//Recordare//DTD MusicXML 4.0 Partwise//EN\" \"http://www.musicxml.org/dtds/partwise.dtd\">";
+
+/// Note types that can be written without a tuplet, starting with the whole note.
+const NOTE_TYPES : [&str; 8] = ["whole", "half", "quarter", "eighth", "16th", "32nd", "64th", "128th"];
+
+impl Song {
+    /// Write this song as a single part MusicXML lead sheet. The part contains the
+    /// chord track as `<harmony>` elements and the melody and lyrics if present.
+    /// Notes crossing a barline are split and tied.
+    pub fn to_music_xml(&self) -> String {
+        let mut root = XmlElement::new("score-partwise");
+        root.set_attribute("version", "4.0");
+        write_meta(self, &mut root);
+
+        let mut part_list = XmlElement::new("part-list");
+        let mut score_part = XmlElement::new("score-part");
+        score_part.set_attribute("id", "P1");
+        score_part.push_text("part-name", "Lead Sheet");
+        part_list.push(score_part);
+        root.push(part_list);
+
+        let mut part = XmlElement::new("part");
+        part.set_attribute("id", "P1");
+        for measure in MeasureWriter::new(self).write() {
+            part.push(measure);
+        }
+        root.push(part);
+
+        xml::write_document(&root, Some(DOCTYPE))
+    }
+}
+
+fn write_meta(song : &Song, root : &mut XmlElement) {
+    let meta = song.get_song_meta();
+    if let Some(title) = meta.get_title() {
+        let mut work = XmlElement::new("work");
+        work.push_text("work-title", title);
+        root.push(work);
+    }
+
+    let mut identification = XmlElement::new("identification");
+    let creators = [
+        ("composer", meta.get_composer()),
+        ("lyricist", meta.get_songwriter()),
+        ("arranger", meta.get_arranger()),
+    ];
+    for (creator_type, name) in creators {
+        if let Some(name) = name {
+            let mut creator = XmlElement::with_text("creator", name);
+            creator.set_attribute("type", creator_type);
+            identification.push(creator);
+        }
+    }
+    if let Some(copyright) = meta.get_copyright() {
+        identification.push_text("rights", copyright);
+    }
+    let mut encoding = XmlElement::new("encoding");
+    encoding.push_text("software", "musical_data");
+    identification.push(encoding);
+    root.push(identification);
+}
+
+struct MeasureWriter<'a> {
+    song : &'a Song,
+    prefer_flats : bool,
+}
+
+impl<'a> MeasureWriter<'a> {
+    fn new(song : &'a Song) -> Self {
+        let settings = song.get_song_settings();
+        let prefer_flats = fifths_from_key_name(settings.get_key_signature())
+            .map(|(fifths, _)| fifths < 0)
+            .unwrap_or(false);
+
+        MeasureWriter {
+            song,
+            prefer_flats,
+        }
+    }
+
+    fn bar_of(&self, tick : u64) -> u64 {
+        SongPosition::new(tick).get_as_bars_and_beats_on(self.song.get_song_settings()).0
+    }
+
+    fn bar_start(&self, bar : u64) -> u64 {
+        self.song.get_song_settings().bars_and_beats_to_ticks(bar, 0, 0.0)
+    }
+
+    fn write(&self) -> Vec<XmlElement> {
+        let settings = self.song.get_song_settings();
+        let song_end = melody::song_end(self.song);
+        let measure_count = if song_end == 0 { 1 } else { self.bar_of(song_end - 1) + 1 };
+
+        let barlines : Vec<u64> = (0..=measure_count).map(|bar| self.bar_start(bar)).collect();
+        let events = melody::melody_events(self.song);
+        let events = melody::split_at(melody::fill_rests(events, 0, barlines[measure_count as usize]), &barlines);
+
+        let mut measures : Vec<XmlElement> = (0..measure_count).map(|bar| {
+            let mut measure = XmlElement::new("measure");
+            measure.set_attribute("number", &(bar + 1).to_string());
+            if bar == 0 {
+                self.write_attributes(&mut measure);
+            }
+            measure
+        }).collect();
+
+        // Time signature changes always start a new measure.
+        for change in settings.get_time_signature_map().get_changes() {
+            if let Some(measure) = measures.get_mut(self.bar_of(change.get_tick()) as usize) {
+                let mut attributes = XmlElement::new("attributes");
+                attributes.push(time_element(change.get_meter()));
+                measure.push(attributes);
+            }
+        }
+
+        // Tempo changes are written at the start of their measure with an offset.
+        for change in settings.get_tempo_map().to_steps(settings.get_pulses_per_quarter()).get_changes() {
+            let bar = self.bar_of(change.get_tick());
+            if let Some(measure) = measures.get_mut(bar as usize) {
+                measure.push(tempo_direction(change.get_tempo(), change.get_tick() - barlines[bar as usize]));
+            }
+        }
+
+        for event in &events {
+            let bar = self.bar_of(event.start) as usize;
+            let whole_measure = event.start == barlines[bar] && event.end == barlines[bar + 1];
+            let measure = &mut measures[bar];
+            for (piece, duration) in self.split(event, whole_measure) {
+                for chord in self.song.get_chord_timeline().get_in_range(piece.start, piece.end) {
+                    measure.push(write_harmony(chord.get_chord(), chord.get_position().get_ticks_on() - piece.start));
+                }
+                for note in self.write_event(&piece, duration, whole_measure) {
+                    measure.push(note);
+                }
+            }
+        }
+
+        measures
+    }
+
+    fn write_attributes(&self, measure : &mut XmlElement) {
+        let settings = self.song.get_song_settings();
+        let mut attributes = XmlElement::new("attributes");
+        attributes.push_text("divisions", &settings.get_pulses_per_quarter().to_string());
+
+        if let Some((fifths, mode)) = fifths_from_key_name(settings.get_key_signature()) {
+            let mut key = XmlElement::new("key");
+            key.push_text("fifths", &fifths.to_string());
+            key.push_text("mode", &mode);
+            attributes.push(key);
+        }
+
+        attributes.push(time_element(&settings.get_meter()));
+
+        let mut clef = XmlElement::new("clef");
+        clef.push_text("sign", "G");
+        clef.push_text("line", "2");
+        attributes.push(clef);
+        measure.push(attributes);
+        measure.push(tempo_direction(settings.get_tempo(), 0));
+    }
+
+    /// Split an event into notated durations, the pieces of notes are tied together. A
+    /// measure rest stays whole and the end of an event no duration covers is written
+    /// without a note type.
+    fn split(&self, event : &MelodyEvent, whole_measure : bool) -> Vec<(MelodyEvent, Option<Duration>)> {
+        if whole_measure && event.is_rest() {
+            return vec![(event.clone(), None)];
+        }
+
+        let settings = self.song.get_song_settings();
+        let ppq = settings.get_pulses_per_quarter();
+        let (durations, remainder) = Duration::decompose(&SongPosition::from(event.start, event.end), settings);
+        let mut pieces : Vec<(u64, u64, Option<Duration>)> = durations.iter()
+            .map(|(start, duration)| (*start, start + duration.to_ticks(ppq).unwrap_or(0), Some(*duration)))
+            .collect();
+        if remainder > 0 {
+            pieces.push((event.end - remainder, event.end, None));
+        }
+
+        let count = pieces.len();
+        pieces.into_iter().enumerate().map(|(i, (start, end, duration))| {
+            let mut piece = event.clone();
+            piece.start = start;
+            piece.end = end;
+            if !event.is_rest() {
+                piece.tie_stop = i > 0 || event.tie_stop;
+                piece.tie_start = i + 1 < count || event.tie_start;
+            }
+            (piece, duration)
+        }).collect()
+    }
+
+    /// Write a note, a chord or a rest. Rests filling a whole measure are written as measure rests.
+    fn write_event(&self, event : &MelodyEvent, note_type : Option<Duration>, whole_measure : bool) -> Vec<XmlElement> {
+        let duration = event.get_length();
+
+        if event.is_rest() {
+            let mut note = XmlElement::new("note");
+            let mut rest = XmlElement::new("rest");
+            if whole_measure {
+                rest.set_attribute("measure", "yes");
+            }
+            note.push(rest);
+            note.push_text("duration", &duration.to_string());
+            note.push_text("voice", "1");
+            if let (Some(note_type), false) = (note_type, whole_measure) {
+                write_note_type(&mut note, &note_type);
+            }
+            return vec![note];
+        }
+
+        let lyric = self.song.get_lyrics().iter()
+            .find(|l| !event.tie_stop && l.get_position().get_ticks_on() == event.start);
+
+        let mut notes = Vec::new();
+        for (i, pitch) in event.pitches.iter().enumerate() {
+            let mut note = XmlElement::new("note");
+            if i > 0 {
+                note.push(XmlElement::new("chord"));
+            }
+
+            let (name, octave) = NoteName::from_midi_pitch(*pitch, self.prefer_flats);
+            let mut xml_pitch = XmlElement::new("pitch");
+            xml_pitch.push_text("step", &name.get_step().to_string());
+            if name.get_mod() != Normal {
+                xml_pitch.push_text("alter", &name.get_mod().get_alter().to_string());
+            }
+            xml_pitch.push_text("octave", &octave.to_string());
+            note.push(xml_pitch);
+            note.push_text("duration", &duration.to_string());
+
+            let mut notations = XmlElement::new("notations");
+            for (tied, tie_type) in [(event.tie_stop, "stop"), (event.tie_start, "start")] {
+                if tied {
+                    let mut tie = XmlElement::new("tie");
+                    tie.set_attribute("type", tie_type);
+                    note.push(tie);
+                    let mut tied = XmlElement::new("tied");
+                    tied.set_attribute("type", tie_type);
+                    notations.push(tied);
+                }
+            }
+
+            note.push_text("voice", "1");
+            if let Some(note_type) = note_type {
+                write_note_type(&mut note, &note_type);
+            }
+            if !notations.children.is_empty() {
+                note.push(notations);
+            }
+
+            if let (Some(lyric), 0) = (lyric, i) {
+                let mut xml_lyric = XmlElement::new("lyric");
+                xml_lyric.set_attribute("number", "1");
+                let syllabic = match lyric.get_syllabic() {
+                    Syllabic::Single => "single",
+                    Syllabic::Begin => "begin",
+                    Syllabic::Middle => "middle",
+                    Syllabic::End => "end",
+                };
+                xml_lyric.push_text("syllabic", syllabic);
+                xml_lyric.push_text("text", lyric.get_text());
+                note.push(xml_lyric);
+            }
+            notes.push(note);
+        }
+
+        notes
+    }
+}
+
+/// Write the note type of a duration with its dots and the `<time-modification>` of a
+/// tuplet.
+fn write_note_type(note : &mut XmlElement, duration : &Duration) {
+    let index = NoteValue::ALL.iter().position(|v| *v == duration.get_note_value()).unwrap_or(0);
+    note.push_text("type", NOTE_TYPES[index]);
+    for _ in 0..duration.get_dots() {
+        note.push(XmlElement::new("dot"));
+    }
+    if let Some((actual, normal)) = duration.get_tuplet() {
+        let mut time_modification = XmlElement::new("time-modification");
+        time_modification.push_text("actual-notes", &actual.to_string());
+        time_modification.push_text("normal-notes", &normal.to_string());
+        note.push(time_modification);
+    }
+}
+
+/// Convert a chord into a `<harmony>` element. The kind is the one covering most of the
+/// chord's intervals, the remaining intervals are written as `<degree>` elements.
+fn write_harmony(chord : &Chord, offset : u64) -> XmlElement {
+    let intervals = chord.get_intervals();
+    let (kind, kind_intervals) = KINDS.iter()
+        .filter(|(k, t, _)| *t == chord.get_chord_type() && *k != "none" && *k != "other")
+        .filter(|(_, _, i)| i.iter().all(|i| intervals.contains(i)))
+        .rev()
+        .max_by_key(|(_, _, i)| i.len())
+        .map(|(k, _, i)| (*k, *i))
+        .unwrap_or(("other", &[]));
+
+    let mut harmony = XmlElement::new("harmony");
+    let mut root = XmlElement::new("root");
+    root.push_text("root-step", &chord.get_root().get_step().to_string());
+    if chord.get_root().get_mod() != Normal {
+        root.push_text("root-alter", &chord.get_root().get_mod().get_alter().to_string());
+    }
+    harmony.push(root);
+    harmony.push_text("kind", kind);
+
+    if let Some(base) = chord.get_base() {
+        let mut bass = XmlElement::new("bass");
+        bass.push_text("bass-step", &base.get_step().to_string());
+        if base.get_mod() != Normal {
+            bass.push_text("bass-alter", &base.get_mod().get_alter().to_string());
+        }
+        harmony.push(bass);
+    }
+
+    for interval in intervals.iter().filter(|i| !kind_intervals.contains(i)) {
+        let mut degree = XmlElement::new("degree");
+        degree.push_text("degree-value", &interval.get_degree().to_string());
+        degree.push_text("degree-alter", &interval.get_mod().get_alter().to_string());
+        // The root and fifth are part of every triad, so they can only be altered.
+        let degree_type = if matches!(interval.get_degree(), 1 | 5) { "alter" } else { "add" };
+        degree.push_text("degree-type", degree_type);
+        harmony.push(degree);
+    }
+
+    if offset > 0 {
+        harmony.push_text("offset", &offset.to_string());
+    }
+
+    harmony
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prelude::{SongLyric, SongNote, TempoCurve};
+    use test_case::test_case;
+
+    const PARTWISE : &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="no"?>
+<!DOCTYPE score-partwise PUBLIC "-//Recordare//DTD MusicXML 4.0 Partwise//EN" "http://www.musicxml.org/dtds/partwise.dtd">
+<score-partwise version="4.0">
+  <work><work-title>Autumn Test</work-title></work>
+  <identification>
+    <creator type="composer">Jane Doe</creator>
+    <creator type="lyricist">John Doe</creator>
+    <creator type="arranger">Max Mustermann</creator>
+    <rights>Copyright 2024</rights>
+  </identification>
+  <part-list><score-part id="P1"><part-name>Lead</part-name></score-part></part-list>
+  <part id="P1">
+    <measure number="1">
+      <attributes>
+        <divisions>2</divisions>
+        <key><fifths>-1</fifths><mode>minor</mode></key>
+        <time><beats>3</beats><beat-type>4</beat-type></time>
+      </attributes>
+      <direction><direction-type><words>Swing</words></direction-type><sound tempo="132"/></direction>
+      <harmony><root><root-step>D</root-step></root><kind>minor-seventh</kind></harmony>
+      <note><pitch><step>D</step><octave>4</octave></pitch><duration>4</duration></note>
+      <harmony><root><root-step>G</root-step></root><kind>dominant</kind><bass><bass-step>B</bass-step></bass></harmony>
+      <note><pitch><step>F</step><octave>4</octave></pitch><duration>2</duration></note>
+    </measure>
+    <measure number="2">
+      <harmony><root><root-step>B</root-step><root-alter>-1</root-alter></root><kind>major-seventh</kind></harmony>
+      <note><rest/><duration>6</duration></note>
+    </measure>
+  </part>
+</score-partwise>"#;
+
+    const TIMEWISE : &str = r#"<?xml version="1.0"?>
+<score-timewise>
+  <movement-title>Timewise</movement-title>
+  <measure number="1">
+    <part id="P1">
+      <attributes><divisions>1</divisions><time><beats>4</beats><beat-type>4</beat-type></time></attributes>
+      <harmony><root><root-step>C</root-step></root><kind>major</kind></harmony>
+      <note><rest/><duration>4</duration></note>
+    </part>
+  </measure>
+  <measure number="2">
+    <part id="P1">
+      <note><rest/><duration>2</duration></note>
+      <harmony><root><root-step>F</root-step><root-alter>1</root-alter></root><kind>half-diminished</kind></harmony>
+      <note><rest/><duration>2</duration></note>
+    </part>
+  </measure>
+</score-timewise>"#;
+
+    #[test]
+    fn test_partwise_meta_and_settings() {
+        let song = Song::from_music_xml(PARTWISE).unwrap();
+        let meta = song.get_song_meta();
+        assert_eq!(meta.get_title(), Some(&String::from("Autumn Test")));
+        assert_eq!(meta.get_composer(), Some(&String::from("Jane Doe")));
+        assert_eq!(meta.get_songwriter(), Some(&String::from("John Doe")));
+        assert_eq!(meta.get_arranger(), Some(&String::from("Max Mustermann")));
+        assert_eq!(meta.get_copyright(), Some(&String::from("Copyright 2024")));
+
+        let settings = song.get_song_settings();
+        assert_eq!(settings.get_pulses_per_quarter(), 2);
+        assert_eq!(settings.get_time_signature_numerator(), 3);
+        assert_eq!(settings.get_time_signature_denominator(), 4);
+        assert_eq!(settings.get_key_signature(), "D Minor");
+        assert_eq!(settings.get_tempo(), 132.0);
+    }
+
+    #[test]
+    fn test_partwise_harmonies() {
+        let song = Song::from_music_xml(PARTWISE).unwrap();
+        let chords = song.get_chords();
+        assert_eq!(chords.len(), 3);
+
+        assert_eq!(chords[0].get_position().get_ticks_on(), 0);
+        assert_eq!(chords[0].get_chord().get_root(), NoteName::D(Normal));
+        assert_eq!(chords[0].get_chord().get_chord_type(), ChordType::Minor);
+        assert_eq!(chords[0].get_chord().get_intervals(), vec![ChordFuntion::Seventh(Flat)]);
+
+        assert_eq!(chords[1].get_position().get_ticks_on(), 4);
+        assert_eq!(chords[1].get_chord().get_base(), Some(NoteName::B(Normal)));
+
+        assert_eq!(chords[2].get_position().get_ticks_on(), 6);
+        assert_eq!(chords[2].get_chord().get_root(), NoteName::B(Flat));
+        assert_eq!(chords[2].get_chord().get_intervals(), vec![ChordFuntion::Seventh(Normal)]);
+    }
+
+    #[test]
+    fn test_timewise() {
+        let song = Song::from_music_xml(TIMEWISE).unwrap();
+        assert_eq!(song.get_song_meta().get_title(), Some(&String::from("Timewise")));
+
+        let chords = song.get_chords();
+        assert_eq!(chords.len(), 2);
+        assert_eq!(chords[1].get_position().get_ticks_on(), 6);
+        assert_eq!(chords[1].get_chord().get_root(), NoteName::F(Sharp));
+        assert_eq!(chords[1].get_chord().get_chord_type(), ChordType::Diminished);
+        assert!(chords[1].get_chord().has_interval(ChordFuntion::Seventh(Flat)));
+    }
+
+    #[test]
+    fn test_harmonies_of_several_parts() {
+        // The guitar part repeats the chords of the lead, the bass has other ones.
+        let part = |id : &str, root : &str| format!(
+            "<part id=\"{}\"><measure number=\"1\"><attributes><divisions>1</divisions></attributes>\
+            <harmony><root><root-step>{}</root-step></root><kind>major</kind></harmony>\
+            <note><rest/><duration>4</duration></note></measure></part>", id, root);
+        let xml = format!("<score-partwise><part-list/>{}{}{}{}</score-partwise>",
+            "<part id=\"P0\"><measure number=\"1\"><note><rest/><duration>4</duration></note></measure></part>",
+            part("P1", "C"), part("P2", "C"), part("P3", "G"));
+        let song = Song::from_music_xml(&xml).unwrap();
+
+        let chords : Vec<(u64, NoteName)> = song.get_chords().iter()
+            .map(|c| (c.get_position().get_ticks_on(), c.get_chord().get_root()))
+            .collect();
+        assert_eq!(chords, vec![(0, NoteName::C(NoteMod::Normal))]);
+    }
+
+    #[test_case("major", ChordType::Major, vec![])]
+    #[test_case("diminished-seventh", ChordType::Diminished, vec![ChordFuntion::Seventh(DoubleFlat)])]
+    #[test_case("suspended-fourth", ChordType::Sus(4), vec![])]
+    #[test_case("dominant-ninth", ChordType::Major, vec![ChordFuntion::Seventh(Flat), ChordFuntion::Nineth(Normal)])]
+    fn test_harmony_kinds(kind : &str, chord_type : ChordType, intervals : Vec<ChordFuntion>) {
+        let harmony = xml::parse(&format!("<harmony><root><root-step>C</root-step></root><kind>{}</kind></harmony>", kind)).unwrap();
+        let chord = read_harmony(&harmony).unwrap().unwrap();
+        assert_eq!(chord.get_chord_type(), chord_type);
+        assert_eq!(chord.get_intervals(), intervals);
+    }
+
+    #[test]
+    fn test_harmony_degrees() {
+        let harmony = xml::parse("<harmony><root><root-step>G</root-step></root><kind>dominant</kind>\
+            <degree><degree-value>9</degree-value><degree-alter>-1</degree-alter><degree-type>add</degree-type></degree></harmony>").unwrap();
+        let chord = read_harmony(&harmony).unwrap().unwrap();
+        assert_eq!(chord.get_intervals(), vec![ChordFuntion::Seventh(Flat), ChordFuntion::Nineth(Flat)]);
+    }
+
+    #[test]
+    fn test_no_chord() {
+        let harmony = xml::parse("<harmony><root><root-step>C</root-step></root><kind>none</kind></harmony>").unwrap();
+        assert!(read_harmony(&harmony).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_invalid_root() {
+        assert!(Song::from_music_xml("<score/>").is_err());
+    }
+
+    #[test_case("0")]
+    #[test_case("-60")]
+    fn test_invalid_tempo(tempo : &str) {
+        let xml = PARTWISE.replace("tempo=\"132\"", &format!("tempo=\"{}\"", tempo));
+        assert!(matches!(Song::from_music_xml(&xml), Err(MusicalDataError::InvalidMusicXml(_))));
+    }
+
+    fn lead_sheet() -> Song {
+        let mut song = Song::new();
+        song.get_song_meta_mut().set_title(Some(String::from("Lead Sheet")));
+        song.get_song_meta_mut().set_composer(Some(String::from("Jane Doe")));
+        song.get_song_settings_mut().set_key_signature(String::from("F Major"));
+
+        let mut c7 = Chord::new(NoteName::C(Normal), ChordType::Major);
+        c7.add_interval(ChordFuntion::Seventh(Flat));
+        c7.add_interval(ChordFuntion::Nineth(Flat));
+        song.add_chord(SongChord::new(0, Chord::new(NoteName::F(Normal), ChordType::Major)));
+        song.add_chord(SongChord::new(2880, c7));
+
+        song.add_note(SongNote::new(0, 960, 65));
+        song.add_note(SongNote::new(960, 1920, 70));
+        // Crosses the barline into the second measure.
+        song.add_note(SongNote::new(2880, 4800, 69));
+        song.add_lyric(SongLyric::new(0, "Hel", Syllabic::Begin));
+        song.add_lyric(SongLyric::new(960, "lo", Syllabic::End));
+
+        song
+    }
+
+    #[test]
+    fn test_export_round_trip() {
+        let song = lead_sheet();
+        let imported = Song::from_music_xml(&song.to_music_xml()).unwrap();
+
+        assert_eq!(imported.get_song_meta().get_title(), Some(&String::from("Lead Sheet")));
+        assert_eq!(imported.get_song_meta().get_composer(), Some(&String::from("Jane Doe")));
+        assert_eq!(imported.get_song_settings().get_key_signature(), "F Major");
+        assert_eq!(imported.get_song_settings().get_pulses_per_quarter(), 960);
+
+        let chords = imported.get_chords();
+        assert_eq!(chords.len(), 2);
+        assert_eq!(chords[1].get_position().get_ticks_on(), 2880);
+        assert_eq!(chords[1].get_chord(), song.get_chords()[1].get_chord());
+    }
+
+    #[test]
+    fn test_tempo_changes_round_trip() {
+        let mut song = lead_sheet();
+        song.get_song_settings_mut().get_tempo_map_mut().add_change(3840 + 1920, 90.0);
+        let imported = Song::from_music_xml(&song.to_music_xml()).unwrap();
+
+        let settings = imported.get_song_settings();
+        assert_eq!(settings.get_tempo(), song.get_song_settings().get_tempo());
+        assert_eq!(settings.get_tempo_map(), song.get_song_settings().get_tempo_map());
+        assert_eq!(settings.get_tempo_at(5760), 90.0);
+    }
+
+    #[test]
+    fn test_tempo_ramp_round_trip() {
+        let mut song = lead_sheet();
+        song.add_note(SongNote::new(7680, 9600, 65));
+        song.get_song_settings_mut().get_tempo_map_mut().add_ramp(3840, 120.0, 7680, 60.0, TempoCurve::Linear);
+        let imported = Song::from_music_xml(&song.to_music_xml()).unwrap();
+
+        let settings = imported.get_song_settings();
+        assert_eq!(settings.get_tempo_map().get_changes().len(), 5);
+        // The steps are rounded to the tempo precision, which is less than a sample off.
+        for tick in [0, 3840, 4800, 5760, 7680, 9600] {
+            assert!(settings.ticks_to_samples(tick).abs_diff(song.get_song_settings().ticks_to_samples(tick)) <= 1, "{}", tick);
+        }
+    }
+
+    #[test]
+    fn test_time_signature_changes_round_trip() {
+        let mut song = lead_sheet();
+        song.get_song_settings_mut().add_time_signature_change_at_bar(1, 7, 8);
+        song.add_note(SongNote::new(3840 + 2880, 3840 + 3360, 72));
+        let xml = song.to_music_xml();
+        let imported = Song::from_music_xml(&xml).unwrap();
+
+        let settings = imported.get_song_settings();
+        assert_eq!(settings.get_time_signature_at(0), (4, 4));
+        assert_eq!(settings.get_time_signature_at(3840), (7, 8));
+        assert_eq!(settings.get_time_signature_map(), song.get_song_settings().get_time_signature_map());
+
+        let doc = xml::parse(&xml).unwrap();
+        let measures : Vec<&XmlElement> = doc.child("part").unwrap().children_named("measure").collect();
+        assert_eq!(measures.len(), 2);
+        let durations : u64 = measures[1].children_named("note")
+            .map(|n| n.child_text("duration").unwrap().parse::<u64>().unwrap())
+            .sum();
+        assert_eq!(durations, 3360);
+    }
+
+    #[test]
+    fn test_additive_meter_round_trip() {
+        let mut song = lead_sheet();
+        song.get_song_settings_mut().set_meter("2+2+3/8".parse().unwrap());
+        song.get_song_settings_mut().add_meter_change_at_bar(1, "3+2/8".parse().unwrap());
+        let xml = song.to_music_xml();
+        assert!(xml.contains("<beats>2+2+3</beats>"), "{}", xml);
+
+        let imported = Song::from_music_xml(&xml).unwrap();
+        let settings = imported.get_song_settings();
+        assert_eq!(settings.get_meter().get_groups(), &[2, 2, 3]);
+        assert_eq!(settings.get_meter_at(3360).get_groups(), &[3, 2]);
+    }
+
+    #[test]
+    fn test_export_measures_and_ties() {
+        let doc = xml::parse(&lead_sheet().to_music_xml()).unwrap();
+        let part = doc.child("part").unwrap();
+        let measures : Vec<&XmlElement> = part.children_named("measure").collect();
+        assert_eq!(measures.len(), 2);
+
+        let last_of_first = measures[0].children_named("note").last().unwrap();
+        assert_eq!(last_of_first.child("tie").unwrap().attribute("type"), Some("start"));
+        assert_eq!(last_of_first.child_text("type"), Some(String::from("quarter")));
+
+        let first_of_second = measures[1].children_named("note").next().unwrap();
+        assert_eq!(first_of_second.child("tie").unwrap().attribute("type"), Some("stop"));
+        assert_eq!(first_of_second.child_text("duration"), Some(String::from("960")));
+
+        let first = measures[0].children_named("note").next().unwrap();
+        let pitch = first.child("pitch").unwrap();
+        assert_eq!(pitch.child_text("step"), Some(String::from("F")));
+        assert_eq!(pitch.child_text("octave"), Some(String::from("4")));
+        assert_eq!(first.child("lyric").unwrap().child_text("syllabic"), Some(String::from("begin")));
+
+        let second = measures[0].children_named("note").nth(1).unwrap();
+        assert_eq!(second.child("pitch").unwrap().child_text("alter"), Some(String::from("-1")));
+    }
+
+    #[test]
+    fn test_export_harmony_degrees() {
+        let doc = xml::parse(&lead_sheet().to_music_xml()).unwrap();
+        let harmonies : Vec<&XmlElement> = doc.child("part").unwrap()
+            .children_named("measure")
+            .flat_map(|m| m.children_named("harmony"))
+            .collect();
+        assert_eq!(harmonies.len(), 2);
+        assert_eq!(harmonies[1].child_text("kind"), Some(String::from("dominant")));
+        // The chord starts together with the tied note.
+        assert_eq!(harmonies[1].child_text("offset"), None);
+        let degree = harmonies[1].child("degree").unwrap();
+        assert_eq!(degree.child_text("degree-value"), Some(String::from("9")));
+        assert_eq!(degree.child_text("degree-alter"), Some(String::from("-1")));
+    }
+
+    #[test]
+    fn test_export_tied_durations_and_triplets() {
+        // Five eighths, an eighth rest and three triplet eighths.
+        let mut song = Song::new();
+        song.add_note(SongNote::new(0, 2400, 60));
+        for (i, pitch) in [62, 64, 65].into_iter().enumerate() {
+            song.add_note(SongNote::new(2880 + i as u64 * 320, 3200 + i as u64 * 320, pitch));
+        }
+        let doc = xml::parse(&song.to_music_xml()).unwrap();
+        let notes : Vec<&XmlElement> = doc.child("part").unwrap().child("measure").unwrap().children_named("note").collect();
+
+        let types : Vec<Option<String>> = notes.iter().map(|n| n.child_text("type")).collect();
+        assert_eq!(types, ["half", "eighth", "eighth", "eighth", "eighth", "eighth"].map(|t| Some(String::from(t))));
+        assert_eq!(notes[0].child("tie").unwrap().attribute("type"), Some("start"));
+        assert_eq!(notes[1].child("tie").unwrap().attribute("type"), Some("stop"));
+        assert_eq!(notes[1].child_text("duration"), Some(String::from("480")));
+        assert!(notes[2].has_child("rest"));
+        assert!(notes[2].child("time-modification").is_none());
+        for note in &notes[3..] {
+            let time_modification = note.child("time-modification").unwrap();
+            assert_eq!(time_modification.child_text("actual-notes"), Some(String::from("3")));
+            assert_eq!(time_modification.child_text("normal-notes"), Some(String::from("2")));
+            assert_eq!(note.child_text("duration"), Some(String::from("320")));
+        }
+    }
+}
@@ -0,0 +1,282 @@
+use crate::error::MusicalDataError;
+use crate::prelude::{GridBar, Meter, Rational, SongPosition, SongSettings};
+
+/// The notations a `PositionDisplay` writes and reads.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PositionFormat {
+    /// Bar, beat and the ticks within the beat like "12.3.480". Bars and beats start at one,
+    /// "|" and ":" are also accepted as separators.
+    #[default]
+    BarsBeatsTicks,
+    /// Bar, beat, sixteenth within the beat and the ticks within the sixteenth like "12:3:2:0".
+    BarsBeatsSixteenths,
+    /// Bar and the distance from the start of the bar in whole notes like "12 3/8".
+    Fraction,
+}
+
+/// Formats ticks as DAW style positions and parses them back. All bar and beat
+/// calculations follow the bar grid of the song. Like in most DAWs beats are counted in
+/// notes of the denominator, so a bar of 6/8 has six beats. The beats of a pickup are the
+/// last beats of its meter, like in `SongSettings::iter_beats`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PositionDisplay {
+    format : PositionFormat,
+    bar_offset : i64,
+}
+
+impl Default for PositionDisplay {
+    fn default() -> Self {
+        PositionDisplay::new(PositionFormat::default())
+    }
+}
+
+impl PositionDisplay {
+    /// Create a display for a format with bars counted from one.
+    pub fn new(format : PositionFormat) -> Self {
+        PositionDisplay { format, bar_offset : 1 }
+    }
+
+    pub fn get_format(&self) -> PositionFormat {
+        self.format
+    }
+
+    /// The number shown for the first bar of the song.
+    pub fn get_bar_offset(&self) -> i64 {
+        self.bar_offset
+    }
+
+    pub fn set_format(&mut self, format : PositionFormat) {
+        self.format = format;
+    }
+
+    /// Set the number shown for the first bar, e.g. 0 for songs starting with a pickup bar.
+    pub fn set_bar_offset(&mut self, bar_offset : i64) {
+        self.bar_offset = bar_offset;
+    }
+
+    /// Format the start of a position.
+    pub fn format(&self, position : &SongPosition, settings : &SongSettings) -> String {
+        self.format_ticks(position.get_ticks_on(), settings)
+    }
+
+    pub fn format_ticks(&self, ticks : u64, settings : &SongSettings) -> String {
+        let (bar, in_bar, beat_length) = match settings.get_bar_at(ticks) {
+            Some(bar) => (bar.bar, ticks - bar.start_tick + bar_range(settings, &bar).0, beat_length(settings, &bar.meter)),
+            None => (0, ticks, 1),
+        };
+        let (beat, in_beat) = (in_bar / beat_length, in_bar % beat_length);
+        let display_bar = bar as i64 + self.bar_offset;
+
+        match self.format {
+            PositionFormat::BarsBeatsTicks => format!("{}.{}.{:03}", display_bar, beat + 1, in_beat),
+            PositionFormat::BarsBeatsSixteenths => {
+                let sixteenth = sixteenth_length(settings);
+                format!("{}:{}:{}:{}", display_bar, beat + 1, in_beat / sixteenth + 1, in_beat % sixteenth)
+            },
+            PositionFormat::Fraction => {
+                let whole = Rational::from(in_bar) / Rational::from(settings.get_pulses_per_quarter().max(1) * 4);
+                if whole == Rational::zero() {
+                    display_bar.to_string()
+                } else {
+                    format!("{} {}", display_bar, whole)
+                }
+            },
+        }
+    }
+
+    /// Parse a position in the format of this display.
+    pub fn parse(&self, s : &str, settings : &SongSettings) -> Result<SongPosition, MusicalDataError> {
+        let invalid = || MusicalDataError::InvalidPosition(String::from(s));
+        let number = |p : &str| -> Result<i64, MusicalDataError> {
+            p.trim().parse::<i64>().map_err(|_| invalid())
+        };
+
+        let (bar, rest) = match self.format {
+            PositionFormat::Fraction => {
+                let text = s.trim();
+                match text.split_once([' ', '+']) {
+                    Some((bar, fraction)) => (number(bar)?, fraction.trim()),
+                    None => (number(text)?, ""),
+                }
+            },
+            _ => {
+                let first = s.find(['.', '|', ':']).ok_or_else(invalid)?;
+                (number(&s[..first])?, &s[first + 1..])
+            },
+        };
+        let bar = u64::try_from(bar - self.bar_offset).map_err(|_| invalid())?;
+        let grid_bar = settings.get_bar_at(settings.bars_and_beats_to_ticks(bar, 0, 0.0))
+            .filter(|b| b.bar == bar)
+            .ok_or_else(invalid)?;
+        let (start, end) = bar_range(settings, &grid_bar);
+        let numerator = grid_bar.meter.get_numerator();
+        let beat_length = beat_length(settings, &grid_bar.meter);
+
+        let in_bar = match self.format {
+            PositionFormat::BarsBeatsTicks | PositionFormat::BarsBeatsSixteenths => {
+                let parts = rest.split(['.', '|', ':'])
+                    .map(|p| number(p).and_then(|n| u64::try_from(n).map_err(|_| invalid())))
+                    .collect::<Result<Vec<u64>, MusicalDataError>>()?;
+                let (beat, in_beat) = match (self.format, parts.as_slice()) {
+                    (PositionFormat::BarsBeatsTicks, [beat, ticks]) if *ticks < beat_length => (*beat, *ticks),
+                    (PositionFormat::BarsBeatsSixteenths, [beat, sixteenth, ticks]) => {
+                        let length = sixteenth_length(settings);
+                        if *sixteenth == 0 || *ticks >= length || (*sixteenth - 1) * length + *ticks >= beat_length {
+                            return Err(invalid());
+                        }
+                        (*beat, (*sixteenth - 1) * length + *ticks)
+                    },
+                    _ => return Err(invalid()),
+                };
+                if beat == 0 || beat > numerator {
+                    return Err(invalid());
+                }
+                (beat - 1) * beat_length + in_beat
+            },
+            PositionFormat::Fraction => {
+                if rest.is_empty() {
+                    0
+                } else {
+                    let (n, d) = rest.split_once('/').unwrap_or((rest, "1"));
+                    let (n, d) = (number(n)?, number(d)?);
+                    if n < 0 || d <= 0 {
+                        return Err(invalid());
+                    }
+                    let ticks = Rational::new(n as i128, d as i128) * Rational::from(settings.get_pulses_per_quarter() * 4);
+                    if !ticks.is_integer() || ticks >= Rational::from(end) {
+                        return Err(invalid());
+                    }
+                    ticks.floor() as u64
+                }
+            },
+        };
+        // Positions in a pickup before its start or after the end of a bar cut short by a
+        // meter change are not part of the bar.
+        if in_bar < start || in_bar >= end {
+            return Err(invalid());
+        }
+
+        Ok(SongPosition::new(grid_bar.start_tick + in_bar - start))
+    }
+}
+
+/// The length of a note of the denominator, rounded down.
+fn beat_length(settings : &SongSettings, meter : &Meter) -> u64 {
+    (meter.get_unit_length(settings.get_pulses_per_quarter()).floor() as u64).max(1)
+}
+
+/// The ticks a bar covers, counted from where a full bar of its meter would start. A
+/// pickup starts late, a bar cut short by a meter change ends early.
+fn bar_range(settings : &SongSettings, bar : &GridBar) -> (u64, u64) {
+    let start = match bar.pickup {
+        true => (bar.meter.get_bar_length(settings.get_pulses_per_quarter()) - bar.length).round().max(0) as u64,
+        false => 0,
+    };
+    let length = ((bar.start + bar.length).round().max(0) as u64).saturating_sub(bar.start_tick);
+
+    (start, start + length)
+}
+
+fn sixteenth_length(settings : &SongSettings) -> u64 {
+    (settings.get_pulses_per_quarter() / 4).max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    fn settings() -> SongSettings {
+        // Two bars of 4/4, then 6/8.
+        let mut settings = SongSettings::default();
+        settings.add_time_signature_change_at_bar(2, 6, 8);
+        settings
+    }
+
+    #[test_case(PositionFormat::BarsBeatsTicks, 0, "1.1.000")]
+    #[test_case(PositionFormat::BarsBeatsTicks, 3840 + 960 * 2 + 120, "2.3.120")]
+    #[test_case(PositionFormat::BarsBeatsTicks, 7680 + 480 * 5 + 10, "3.6.010")]
+    #[test_case(PositionFormat::BarsBeatsSixteenths, 0, "1:1:1:0")]
+    #[test_case(PositionFormat::BarsBeatsSixteenths, 960 + 240 * 3 + 5, "1:2:4:5")]
+    #[test_case(PositionFormat::BarsBeatsSixteenths, 7680 + 480 + 240, "3:2:2:0")]
+    #[test_case(PositionFormat::Fraction, 3840, "2")]
+    #[test_case(PositionFormat::Fraction, 3840 + 1440, "2 3/8")]
+    #[test_case(PositionFormat::Fraction, 7680 + 2400, "3 5/8")]
+    fn test_format_and_parse(format : PositionFormat, ticks : u64, text : &str) {
+        let settings = settings();
+        let display = PositionDisplay::new(format);
+
+        assert_eq!(display.format_ticks(ticks, &settings), text);
+        assert_eq!(display.parse(text, &settings).unwrap().get_ticks_on(), ticks);
+    }
+
+    #[test]
+    fn test_bar_offset() {
+        let settings = settings();
+        let mut display = PositionDisplay::default();
+        display.set_bar_offset(0);
+
+        assert_eq!(display.format_ticks(3840, &settings), "1.1.000");
+        assert_eq!(display.parse("0.2.0", &settings).unwrap().get_ticks_on(), 960);
+        assert!(display.parse("-1.1.0", &settings).is_err());
+    }
+
+    #[test]
+    fn test_pickup() {
+        let mut settings = SongSettings::default();
+        settings.set_pickup(960);
+        let display = PositionDisplay::default();
+
+        assert_eq!(display.format_ticks(0, &settings), "1.4.000");
+        assert_eq!(display.format_ticks(960, &settings), "2.1.000");
+        assert_eq!(display.parse("1.4.000", &settings).unwrap().get_ticks_on(), 0);
+        assert_eq!(display.parse("1.4.480", &settings).unwrap().get_ticks_on(), 480);
+        assert!(display.parse("1.1.000", &settings).is_err());
+        assert!(display.parse("1.3.959", &settings).is_err());
+
+        let fraction = PositionDisplay::new(PositionFormat::Fraction);
+        assert_eq!(fraction.format_ticks(480, &settings), "1 7/8");
+        assert_eq!(fraction.parse("1 7/8", &settings).unwrap().get_ticks_on(), 480);
+        assert!(fraction.parse("1 1/2", &settings).is_err());
+    }
+
+    #[test]
+    fn test_bar_cut_short() {
+        // The second bar of 4/4 is cut short after two beats by a change to 3/4.
+        let mut settings = SongSettings::default();
+        settings.get_time_signature_map_mut().add_change(3840 + 1920, 3, 4);
+        let display = PositionDisplay::default();
+
+        assert_eq!(display.format_ticks(3840 + 1919, &settings), "2.2.959");
+        assert_eq!(display.format_ticks(3840 + 1920, &settings), "3.1.000");
+        assert!(display.parse("2.3.000", &settings).is_err());
+        assert!(PositionDisplay::new(PositionFormat::BarsBeatsSixteenths).parse("2:3:1:0", &settings).is_err());
+        assert!(PositionDisplay::new(PositionFormat::Fraction).parse("2 1/2", &settings).is_err());
+    }
+
+    #[test]
+    fn test_other_separators() {
+        let settings = settings();
+        let display = PositionDisplay::default();
+        assert_eq!(display.parse("2|1|480", &settings).unwrap().get_ticks_on(), 3840 + 480);
+        assert_eq!(display.parse(" 2:1:480 ", &settings).unwrap().get_ticks_on(), 3840 + 480);
+        assert_eq!(PositionDisplay::new(PositionFormat::Fraction).parse("2+1/4", &settings).unwrap().get_ticks_on(), 3840 + 960);
+    }
+
+    #[test_case(PositionFormat::BarsBeatsTicks, "1.5.000")]
+    #[test_case(PositionFormat::BarsBeatsTicks, "1.0.000")]
+    #[test_case(PositionFormat::BarsBeatsTicks, "1.1.960")]
+    #[test_case(PositionFormat::BarsBeatsTicks, "1.1")]
+    #[test_case(PositionFormat::BarsBeatsTicks, "0.1.0")]
+    #[test_case(PositionFormat::BarsBeatsTicks, "x.1.0")]
+    #[test_case(PositionFormat::BarsBeatsSixteenths, "1:1:5:0")]
+    #[test_case(PositionFormat::BarsBeatsSixteenths, "3:1:3:0")]
+    #[test_case(PositionFormat::BarsBeatsSixteenths, "1:1:1:240")]
+    #[test_case(PositionFormat::Fraction, "1 1/1")]
+    #[test_case(PositionFormat::Fraction, "1 1/0")]
+    #[test_case(PositionFormat::Fraction, "3 6/8")]
+    fn test_parse_invalid(format : PositionFormat, text : &str) {
+        let result = PositionDisplay::new(format).parse(text, &settings());
+        assert_eq!(result.err(), Some(MusicalDataError::InvalidPosition(String::from(text))));
+    }
+}
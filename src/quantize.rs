@@ -0,0 +1,252 @@
+use crate::prelude::{Duration, NoteValue, PositionableMut, Rational, SongPosition, SongSettings, Timeline};
+
+/// The kind of note value the grid lines of a `Quantizer` are spaced by.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum GridType {
+    #[default]
+    Straight,
+    Triplet,
+    Dotted,
+}
+
+/// Moves onsets and offsets towards a musical grid. The grid starts at every bar line,
+/// so dotted and triplet grids stay aligned to the bars after meter changes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quantizer {
+    note_value : NoteValue,
+    grid_type : GridType,
+    /// How far an event is moved towards its grid line in percent.
+    strength : f64,
+    /// How far every second grid line is delayed in percent, 100 is a triplet feel.
+    swing : f64,
+    /// Events further away from their grid line than this percentage of a grid step are
+    /// left alone.
+    catch_window : f64,
+    preserve_length : bool,
+}
+
+impl Quantizer {
+    /// Create a quantizer with full strength, no swing and a window catching every event
+    /// that moves offsets together with their onsets.
+    pub fn new(note_value : NoteValue, grid_type : GridType) -> Self {
+        Quantizer {
+            note_value,
+            grid_type,
+            strength : 100.0,
+            swing : 0.0,
+            catch_window : 100.0,
+            preserve_length : true,
+        }
+    }
+
+    pub fn get_note_value(&self) -> NoteValue {
+        self.note_value
+    }
+
+    pub fn get_grid_type(&self) -> GridType {
+        self.grid_type
+    }
+
+    pub fn get_strength(&self) -> f64 {
+        self.strength
+    }
+
+    pub fn get_swing(&self) -> f64 {
+        self.swing
+    }
+
+    pub fn get_catch_window(&self) -> f64 {
+        self.catch_window
+    }
+
+    pub fn get_preserve_length(&self) -> bool {
+        self.preserve_length
+    }
+
+    pub fn set_note_value(&mut self, note_value : NoteValue) {
+        self.note_value = note_value;
+    }
+
+    pub fn set_grid_type(&mut self, grid_type : GridType) {
+        self.grid_type = grid_type;
+    }
+
+    /// Set the strength in percent, clamped to 0 to 100.
+    pub fn set_strength(&mut self, strength : f64) {
+        self.strength = strength.clamp(0.0, 100.0);
+    }
+
+    /// Set the swing in percent, clamped to 0 to 100.
+    pub fn set_swing(&mut self, swing : f64) {
+        self.swing = swing.clamp(0.0, 100.0);
+    }
+
+    /// Set the catch window in percent of a grid step, clamped to 0 to 100.
+    pub fn set_catch_window(&mut self, catch_window : f64) {
+        self.catch_window = catch_window.clamp(0.0, 100.0);
+    }
+
+    /// Keep the length of positions by moving their offsets together with their onsets.
+    /// Otherwise offsets are quantized on their own.
+    pub fn set_preserve_length(&mut self, preserve_length : bool) {
+        self.preserve_length = preserve_length;
+    }
+
+    /// The distance between two grid lines in ticks, possibly fractional.
+    pub fn get_grid_ticks(&self, ppq : u64) -> Rational {
+        let duration = match self.grid_type {
+            GridType::Straight => Duration::new(self.note_value),
+            GridType::Triplet => Duration::tuplet(self.note_value, 3, 2),
+            GridType::Dotted => Duration::dotted(self.note_value, 1),
+        };
+
+        duration.get_whole_notes().to_ticks_exact(ppq)
+    }
+
+    /// Move a single tick towards its nearest grid line.
+    pub fn quantize_tick(&self, tick : u64, settings : &SongSettings) -> u64 {
+        let step = self.get_grid_ticks(settings.get_pulses_per_quarter());
+        let distance = (self.nearest_grid_line(tick, settings) - Rational::from(tick)).to_f64();
+        if distance.abs() > step.to_f64() * self.catch_window / 100.0 {
+            return tick;
+        }
+
+        (tick as f64 + distance * self.strength / 100.0).round().max(0.0) as u64
+    }
+
+    pub fn quantize(&self, position : &SongPosition, settings : &SongSettings) -> SongPosition {
+        let on = self.quantize_tick(position.get_ticks_on(), settings);
+        let off = match position.get_ticks_off() {
+            Some(_) if self.preserve_length => on + position.get_length(),
+            Some(off) => {
+                let off = self.quantize_tick(off, settings);
+                // A note collapsing onto its onset keeps one grid step.
+                if off <= on && position.get_length() > 0 {
+                    on + self.get_grid_ticks(settings.get_pulses_per_quarter()).round().max(1) as u64
+                } else {
+                    off.max(on)
+                }
+            },
+            None => return SongPosition::new(on),
+        };
+
+        SongPosition::from(on, off)
+    }
+
+    /// Quantize every item of a timeline, e.g. the notes or chord changes of a song.
+    pub fn quantize_timeline<T : PositionableMut>(&self, timeline : &mut Timeline<T>, settings : &SongSettings) {
+        timeline.edit(|items| {
+            for item in items.iter_mut() {
+                let position = self.quantize(item.get_position(), settings);
+                item.set_position(position);
+            }
+        });
+    }
+
+    /// The grid line nearest to the tick, the earlier one if two are equally near.
+    fn nearest_grid_line(&self, tick : u64, settings : &SongSettings) -> Rational {
+        let step = self.get_grid_ticks(settings.get_pulses_per_quarter());
+        let swing = step * Rational::from_decimal(self.swing / 300.0, 6);
+        let (bar, _, _) = settings.ticks_to_bars_and_beats_exact(tick);
+        let bar_start = settings.bars_and_beats_to_ticks_exact(bar, 0, Rational::zero());
+        let bar_end = settings.bars_and_beats_to_ticks_exact(bar + 1, 0, Rational::zero());
+        let tick = Rational::from(tick);
+
+        let k = ((tick - bar_start) / step).floor();
+        let lines = ((k - 1).max(0)..=k + 2)
+            .map(|k| bar_start + step * Rational::from_integer(k) + if k % 2 == 1 { swing } else { Rational::zero() })
+            .filter(|line| *line < bar_end)
+            .chain(std::iter::once(bar_end));
+
+        let distance = |line : &Rational| if *line < tick { tick - *line } else { *line - tick };
+        let mut best = bar_end;
+        for line in lines {
+            if distance(&line) < distance(&best) {
+                best = line;
+            }
+        }
+
+        best
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prelude::{Positionable, SongNote};
+    use test_case::test_case;
+
+    #[test_case(GridType::Straight, NoteValue::Sixteenth, 240)]
+    #[test_case(GridType::Triplet, NoteValue::Eighth, 320)]
+    #[test_case(GridType::Dotted, NoteValue::Eighth, 720)]
+    fn test_grid_ticks(grid_type : GridType, note_value : NoteValue, ticks : i128) {
+        assert_eq!(Quantizer::new(note_value, grid_type).get_grid_ticks(960), Rational::from_integer(ticks));
+    }
+
+    #[test_case(GridType::Straight, 250, 240)]
+    #[test_case(GridType::Straight, 350, 240)]
+    #[test_case(GridType::Straight, 370, 480)]
+    #[test_case(GridType::Straight, 3830, 3840)]
+    #[test_case(GridType::Triplet, 300, 320)]
+    #[test_case(GridType::Triplet, 650, 640)]
+    #[test_case(GridType::Dotted, 1500, 1440)]
+    // The last dotted eighth of a 4/4 bar is cut short by the bar line.
+    #[test_case(GridType::Dotted, 3750, 3840)]
+    fn test_quantize_tick(grid_type : GridType, tick : u64, expected : u64) {
+        let note_value = if grid_type == GridType::Straight { NoteValue::Sixteenth } else { NoteValue::Eighth };
+        let quantizer = Quantizer::new(note_value, grid_type);
+
+        assert_eq!(quantizer.quantize_tick(tick, &SongSettings::default()), expected);
+    }
+
+    #[test]
+    fn test_strength_swing_and_window() {
+        let settings = SongSettings::default();
+        let mut quantizer = Quantizer::new(NoteValue::Eighth, GridType::Straight);
+        quantizer.set_strength(50.0);
+        assert_eq!(quantizer.quantize_tick(580, &settings), 530);
+
+        quantizer.set_strength(100.0);
+        quantizer.set_swing(100.0);
+        assert_eq!(quantizer.quantize_tick(600, &settings), 640);
+        assert_eq!(quantizer.quantize_tick(900, &settings), 960);
+
+        quantizer.set_swing(0.0);
+        quantizer.set_catch_window(10.0);
+        assert_eq!(quantizer.quantize_tick(500, &settings), 480);
+        assert_eq!(quantizer.quantize_tick(600, &settings), 600);
+    }
+
+    #[test]
+    fn test_quantize_positions() {
+        let settings = SongSettings::default();
+        let mut quantizer = Quantizer::new(NoteValue::Sixteenth, GridType::Straight);
+        assert_eq!(quantizer.quantize(&SongPosition::from(250, 700), &settings), SongPosition::from(240, 690));
+        assert_eq!(quantizer.quantize(&SongPosition::new(250), &settings), SongPosition::new(240));
+
+        quantizer.set_preserve_length(false);
+        assert_eq!(quantizer.quantize(&SongPosition::from(250, 700), &settings), SongPosition::from(240, 720));
+        assert_eq!(quantizer.quantize(&SongPosition::from(250, 300), &settings), SongPosition::from(240, 480));
+    }
+
+    #[test]
+    fn test_quantize_after_meter_change() {
+        let mut settings = SongSettings::default();
+        settings.add_time_signature_change_at_bar(1, 7, 8);
+        let quantizer = Quantizer::new(NoteValue::Quarter, GridType::Dotted);
+
+        // The grid restarts at the bar line after the 4/4 bar.
+        assert_eq!(quantizer.quantize_tick(3840 + 1400, &settings), 3840 + 1440);
+        assert_eq!(quantizer.quantize_tick(3840 + 3300, &settings), 3840 + 3360);
+    }
+
+    #[test]
+    fn test_quantize_timeline() {
+        let settings = SongSettings::default();
+        let mut timeline = Timeline::from_vec(vec![SongNote::new(10, 470, 60), SongNote::new(470, 950, 62), SongNote::new(5, 100, 64)]);
+        Quantizer::new(NoteValue::Eighth, GridType::Straight).quantize_timeline(&mut timeline, &settings);
+
+        let ons : Vec<(u64, u8)> = timeline.iter().map(|n| (n.get_position().get_ticks_on(), n.get_pitch())).collect();
+        assert_eq!(ons, vec![(0, 64), (0, 60), (480, 62)]);
+    }
+}
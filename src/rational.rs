@@ -0,0 +1,257 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+/// An exact fraction, always stored in lowest terms with a positive denominator.
+/// Timing calculations use it instead of floats so that long songs do not drift.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Rational {
+    numerator : i128,
+    denominator : i128,
+}
+
+fn gcd(a : i128, b : i128) -> i128 {
+    let (mut a, mut b) = (a.abs(), b.abs());
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+
+    a
+}
+
+impl Rational {
+    /// Create a fraction. Panics if the denominator is zero.
+    pub fn new(numerator : i128, denominator : i128) -> Self {
+        if denominator == 0 {
+            panic!("Rational with a zero denominator!");
+        }
+
+        let g = gcd(numerator, denominator).max(1) * denominator.signum();
+        Rational {
+            numerator : numerator / g,
+            denominator : denominator / g,
+        }
+    }
+
+    pub fn from_integer(value : i128) -> Self {
+        Rational { numerator : value, denominator : 1 }
+    }
+
+    /// Convert a float with up to `precision` decimal places, e.g. a tempo of 93.5 bpm.
+    pub fn from_decimal(value : f64, precision : u32) -> Self {
+        let scale = 10i128.pow(precision);
+        Rational::new((value * scale as f64).round() as i128, scale)
+    }
+
+    pub fn zero() -> Self {
+        Rational::from_integer(0)
+    }
+
+    pub fn get_numerator(&self) -> i128 {
+        self.numerator
+    }
+
+    pub fn get_denominator(&self) -> i128 {
+        self.denominator
+    }
+
+    pub fn is_integer(&self) -> bool {
+        self.denominator == 1
+    }
+
+    /// The largest integer not greater than the fraction.
+    pub fn floor(&self) -> i128 {
+        self.numerator.div_euclid(self.denominator)
+    }
+
+    /// The smallest integer not less than the fraction.
+    pub fn ceil(&self) -> i128 {
+        -(-*self).floor()
+    }
+
+    /// The nearest integer, halves are rounded up.
+    pub fn round(&self) -> i128 {
+        (*self + Rational::new(1, 2)).floor()
+    }
+
+    /// The nearest fraction with the given denominator if the denominator of this one is
+    /// larger, so that sums over many fractions stay small. Rounded via the integer part
+    /// and the remainder, which never overflows.
+    pub fn limit_denominator(&self, denominator : i128) -> Rational {
+        if self.denominator <= denominator {
+            return *self;
+        }
+
+        let integer = self.floor();
+        let remainder = self.numerator.rem_euclid(self.denominator);
+        let fraction = Rational::new(remainder * denominator, self.denominator).round();
+        Rational::from_integer(integer) + Rational::new(fraction, denominator)
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        self.numerator as f64 / self.denominator as f64
+    }
+}
+
+impl Default for Rational {
+    fn default() -> Self {
+        Rational::zero()
+    }
+}
+
+impl From<i128> for Rational {
+    fn from(value : i128) -> Self {
+        Rational::from_integer(value)
+    }
+}
+
+impl From<u64> for Rational {
+    fn from(value : u64) -> Self {
+        Rational::from_integer(value as i128)
+    }
+}
+
+impl Add for Rational {
+    type Output = Rational;
+
+    fn add(self, other : Rational) -> Rational {
+        let g = gcd(self.denominator, other.denominator);
+        Rational::new(
+            self.numerator * (other.denominator / g) + other.numerator * (self.denominator / g),
+            self.denominator / g * other.denominator,
+        )
+    }
+}
+
+impl Sub for Rational {
+    type Output = Rational;
+
+    fn sub(self, other : Rational) -> Rational {
+        self + -other
+    }
+}
+
+impl Mul for Rational {
+    type Output = Rational;
+
+    fn mul(self, other : Rational) -> Rational {
+        // Cancel first to keep the intermediate values small.
+        let a = gcd(self.numerator, other.denominator).max(1);
+        let b = gcd(other.numerator, self.denominator).max(1);
+        Rational::new(
+            (self.numerator / a) * (other.numerator / b),
+            (self.denominator / b) * (other.denominator / a),
+        )
+    }
+}
+
+impl Div for Rational {
+    type Output = Rational;
+
+    /// Panics when dividing by zero.
+    fn div(self, other : Rational) -> Rational {
+        let reciprocal = Rational::new(other.denominator, other.numerator);
+        reciprocal.mul(self)
+    }
+}
+
+impl Neg for Rational {
+    type Output = Rational;
+
+    fn neg(self) -> Rational {
+        Rational { numerator : -self.numerator, denominator : self.denominator }
+    }
+}
+
+impl PartialOrd for Rational {
+    fn partial_cmp(&self, other : &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Rational {
+    /// Compares the integer parts first and then the remainders, which are smaller than
+    /// their denominators, so that large fractions do not overflow.
+    fn cmp(&self, other : &Self) -> Ordering {
+        self.floor().cmp(&other.floor()).then_with(|| {
+            let a = self.numerator.rem_euclid(self.denominator);
+            let b = other.numerator.rem_euclid(other.denominator);
+            (a * other.denominator).cmp(&(b * self.denominator))
+        })
+    }
+}
+
+/// Write the fraction as "3/4", or as "3" if it is an integer.
+impl fmt::Display for Rational {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_integer() {
+            write!(f, "{}", self.numerator)
+        } else {
+            write!(f, "{}/{}", self.numerator, self.denominator)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    #[test]
+    fn test_normalization() {
+        assert_eq!(Rational::new(2, 4), Rational::new(1, 2));
+        assert_eq!(Rational::new(3, -6), Rational::new(-1, 2));
+        assert_eq!(Rational::new(0, 5), Rational::zero());
+        assert_eq!(Rational::new(-3, -6).get_denominator(), 2);
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let a = Rational::new(1, 3);
+        let b = Rational::new(1, 6);
+        assert_eq!(a + b, Rational::new(1, 2));
+        assert_eq!(a - b, Rational::new(1, 6));
+        assert_eq!(a * b, Rational::new(1, 18));
+        assert_eq!(a / b, Rational::from_integer(2));
+        assert!(b < a);
+        assert_eq!(Rational::from_decimal(93.5, 3), Rational::new(187, 2));
+    }
+
+    #[test_case(7, 2, 3, 4, 4)]
+    #[test_case(-7, 2, -4, -3, -3)]
+    #[test_case(5, 1, 5, 5, 5)]
+    #[test_case(1, 3, 0, 1, 0)]
+    fn test_rounding(n : i128, d : i128, floor : i128, ceil : i128, round : i128) {
+        let r = Rational::new(n, d);
+        assert_eq!(r.floor(), floor);
+        assert_eq!(r.ceil(), ceil);
+        assert_eq!(r.round(), round);
+    }
+
+    #[test_case(Rational::new(1_000_001, 3_000_000), 1000, Rational::new(333, 1000))]
+    #[test_case(Rational::new(-7_000_001, 3_000_000), 10, Rational::new(-23, 10))]
+    #[test_case(Rational::new(1, 4), 10, Rational::new(1, 4))]
+    fn test_limit_denominator(r : Rational, denominator : i128, limited : Rational) {
+        assert_eq!(r.limit_denominator(denominator), limited);
+    }
+
+    #[test]
+    fn test_compare_large() {
+        let a = Rational::new(i128::MAX / 3, 1_000_000_007);
+        let b = Rational::new(i128::MAX / 3 - 1, 1_000_000_009);
+        assert!(b < a);
+        assert!(-a < -b);
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(Rational::new(6, 8).to_string(), "3/4");
+        assert_eq!(Rational::new(8, 4).to_string(), "2");
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_zero_denominator() {
+        Rational::new(1, 0);
+    }
+}
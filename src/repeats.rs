@@ -0,0 +1,334 @@
+use crate::prelude::{PositionableMut, SongPosition, SongSettings, Timeline};
+
+/// A jump back to an earlier bar, taken once after the bar has been played.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Jump {
+    /// D.C., back to the first bar.
+    DaCapo,
+    /// D.S., back to the segno.
+    DalSegno,
+}
+
+/// Repeat signs, endings and navigation marks of a single bar, shared by the importers
+/// of formats that write repeats instead of the music in playback order.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct RepeatMarks {
+    pub start_repeat : bool,
+    pub end_repeat : bool,
+    /// A double bar line or a final bar line ends the bar.
+    pub section_end : bool,
+    /// The passes this bar is played in when it starts a first, second, ... ending.
+    pub ending : Option<Vec<u64>>,
+    /// The bar starts with a segno, the target of a D.S.
+    pub segno : bool,
+    /// The bar starts the coda.
+    pub coda : bool,
+    /// After a D.C. or D.S. playback continues at the coda after this bar.
+    pub to_coda : bool,
+    /// After a D.C. or D.S. playback ends with this bar.
+    pub fine : bool,
+    pub jump : Option<Jump>,
+}
+
+/// The indices of the bars in playback order with repeats, endings and jumps resolved.
+/// Without endings a repeated section is played twice. After a D.C. or D.S. repeats are
+/// not taken again and only their last ending is played.
+pub(crate) fn unroll(bars : &[RepeatMarks]) -> Vec<usize> {
+    let mut order = Vec::new();
+    let mut repeat_start = 0;
+    let mut pass = 1;
+    let mut ending : Option<&Vec<u64>> = None;
+    let mut i = 0;
+    let mut jumped = false;
+    let mut after_jump = false;
+    let mut jumps_taken = Vec::new();
+
+    while i < bars.len() {
+        let bar = &bars[i];
+        if bar.start_repeat && !jumped {
+            repeat_start = i;
+            ending = None;
+            pass = if after_jump { last_pass(&bars[i..]) } else { 1 };
+        }
+        jumped = false;
+        if bar.ending.is_some() {
+            ending = bar.ending.as_ref();
+        }
+
+        let current_ending = ending;
+        let skipped = ending.is_some_and(|e| !e.contains(&pass));
+        if !skipped {
+            order.push(i);
+        }
+        if bar.section_end || bar.end_repeat {
+            ending = None;
+        }
+
+        if bar.end_repeat && !skipped {
+            // An ending like "1,2" is played again in the next pass.
+            let has_further_ending = current_ending.is_some_and(|e| e.contains(&(pass + 1)))
+                || bars[i + 1..].iter()
+                    .take_while(|b| !b.start_repeat)
+                    .any(|b| b.ending.as_ref().is_some_and(|e| e.contains(&(pass + 1))));
+            let in_ending = bars[repeat_start..=i].iter().any(|b| b.ending.is_some());
+            if (!in_ending && pass == 1 && !after_jump) || (in_ending && has_further_ending) {
+                pass += 1;
+                i = repeat_start;
+                jumped = true;
+                continue;
+            }
+            repeat_start = i + 1;
+            pass = if after_jump { last_pass(&bars[i + 1..]) } else { 1 };
+        }
+
+        if !skipped && after_jump && bar.fine {
+            break;
+        }
+        let coda = bars[i + 1..].iter().position(|b| b.coda).map(|c| i + 1 + c);
+        if let Some(coda) = coda.filter(|_| !skipped && after_jump && bar.to_coda) {
+            i = coda;
+            ending = None;
+            continue;
+        }
+        if let Some(jump) = bar.jump.filter(|_| !skipped && !jumps_taken.contains(&i)) {
+            jumps_taken.push(i);
+            after_jump = true;
+            i = match jump {
+                Jump::DaCapo => 0,
+                Jump::DalSegno => bars[..=i].iter().rposition(|b| b.segno)
+                    .or_else(|| bars.iter().position(|b| b.segno))
+                    .unwrap_or(0),
+            };
+            repeat_start = i;
+            pass = last_pass(&bars[i..]);
+            ending = None;
+            continue;
+        }
+        i += 1;
+    }
+
+    order
+}
+
+/// The pass in which the last ending of the repeat starting at the first bar is played.
+fn last_pass(bars : &[RepeatMarks]) -> u64 {
+    let mut last = 1;
+    let mut repeat_ended = false;
+    for (i, bar) in bars.iter().enumerate() {
+        if i > 0 && bar.start_repeat {
+            break;
+        }
+        match &bar.ending {
+            Some(ending) => last = ending.iter().copied().fold(last, u64::max),
+            None if repeat_ended => break,
+            None => {},
+        }
+        repeat_ended |= bar.end_repeat;
+    }
+
+    last
+}
+
+/// The bars of a chart in playback order, mapping every written bar to the bars it is
+/// played as and back. Played bars have the length of their written bar and follow each
+/// other from the start of the song.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PlaybackOrder {
+    order : Vec<usize>,
+}
+
+impl PlaybackOrder {
+    /// Resolve the repeats, endings and jumps of the written bars.
+    pub fn new(bars : &[RepeatMarks]) -> Self {
+        PlaybackOrder { order : unroll(bars) }
+    }
+
+    /// The written bar of every played bar.
+    pub fn get_order(&self) -> &[usize] {
+        &self.order
+    }
+
+    /// The number of played bars.
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+
+    /// The written bar a played bar comes from.
+    pub fn get_written_bar(&self, played : usize) -> Option<usize> {
+        self.order.get(played).copied()
+    }
+
+    /// The played bars of a written bar, empty if it is never played.
+    pub fn get_played_bars(&self, written : usize) -> Vec<usize> {
+        (0..self.order.len()).filter(|p| self.order[*p] == written).collect()
+    }
+
+    /// The written tick a played tick comes from, for highlighting the chart while it
+    /// plays. Ticks after the last played bar have none.
+    pub fn played_to_written_tick(&self, tick : u64, settings : &SongSettings) -> Option<u64> {
+        let starts = self.played_starts(settings);
+        let played = starts.partition_point(|s| *s <= tick).checked_sub(1)?;
+        let written = *self.order.get(played)?;
+
+        Some(settings.bars_and_beats_to_ticks(written as u64, 0, 0.0) + tick - starts[played])
+    }
+
+    /// The played ticks of a written tick, one for every time its bar is played.
+    pub fn written_to_played_ticks(&self, tick : u64, settings : &SongSettings) -> Vec<u64> {
+        let written = settings.ticks_to_bars_and_beats(tick).0;
+        let offset = tick - settings.bars_and_beats_to_ticks(written, 0, 0.0);
+        let starts = self.played_starts(settings);
+
+        self.get_played_bars(written as usize).into_iter().map(|p| starts[p] + offset).collect()
+    }
+
+    /// Copy the items of the written bars to every bar they are played in. Items keep
+    /// their length even if it reaches into the next bar.
+    pub fn unroll_timeline<T : PositionableMut + Clone>(&self, timeline : &Timeline<T>, settings : &SongSettings) -> Timeline<T> {
+        let mut items = Vec::new();
+        for item in timeline {
+            let pos = *item.get_position();
+            for on in self.written_to_played_ticks(pos.get_ticks_on(), settings) {
+                let mut item = item.clone();
+                item.set_position(match pos.get_ticks_off() {
+                    Some(off) => SongPosition::from(on, on + (off - pos.get_ticks_on())),
+                    None => SongPosition::new(on),
+                });
+                items.push(item);
+            }
+        }
+
+        Timeline::from_vec(items)
+    }
+
+    /// The start ticks of the played bars followed by the end of the last one.
+    fn played_starts(&self, settings : &SongSettings) -> Vec<u64> {
+        let mut starts = vec![0];
+        for written in &self.order {
+            let length = settings.bars_and_beats_to_ticks(*written as u64 + 1, 0, 0.0) - settings.bars_and_beats_to_ticks(*written as u64, 0, 0.0);
+            starts.push(starts[starts.len() - 1] + length);
+        }
+
+        starts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prelude::{Positionable, SongLyric, Syllabic};
+    use test_case::test_case;
+
+    fn bar(start_repeat : bool, end_repeat : bool, ending : Option<Vec<u64>>) -> RepeatMarks {
+        RepeatMarks { start_repeat, end_repeat, ending, ..RepeatMarks::default() }
+    }
+
+    #[test]
+    fn test_unroll_simple_repeat() {
+        let bars = [bar(false, false, None), bar(true, false, None), bar(false, true, None), bar(false, false, None)];
+        assert_eq!(unroll(&bars), vec![0, 1, 2, 1, 2, 3]);
+    }
+
+    #[test]
+    fn test_unroll_endings() {
+        let bars = [
+            bar(true, false, None),
+            bar(false, true, Some(vec![1])),
+            bar(false, false, Some(vec![2])),
+            bar(false, false, None),
+        ];
+        assert_eq!(unroll(&bars), vec![0, 1, 0, 2, 3]);
+    }
+
+    #[test]
+    fn test_unroll_three_endings() {
+        let bars = [
+            bar(true, false, None),
+            bar(false, true, Some(vec![1, 2])),
+            bar(false, false, Some(vec![3])),
+        ];
+        assert_eq!(unroll(&bars), vec![0, 1, 0, 1, 0, 2]);
+    }
+
+    fn plain() -> RepeatMarks {
+        RepeatMarks::default()
+    }
+
+    #[test]
+    fn test_unroll_da_capo_al_fine() {
+        let bars = [
+            plain(),
+            RepeatMarks { fine : true, ..plain() },
+            plain(),
+            RepeatMarks { jump : Some(Jump::DaCapo), ..plain() },
+        ];
+        assert_eq!(unroll(&bars), vec![0, 1, 2, 3, 0, 1]);
+    }
+
+    #[test]
+    fn test_unroll_dal_segno_al_coda() {
+        let bars = [
+            plain(),
+            RepeatMarks { segno : true, ..plain() },
+            RepeatMarks { to_coda : true, ..plain() },
+            RepeatMarks { jump : Some(Jump::DalSegno), ..plain() },
+            RepeatMarks { coda : true, ..plain() },
+            plain(),
+        ];
+        assert_eq!(unroll(&bars), vec![0, 1, 2, 3, 1, 2, 4, 5]);
+    }
+
+    #[test]
+    fn test_unroll_repeats_after_jump() {
+        let bars = [
+            bar(true, false, None),
+            bar(false, true, Some(vec![1])),
+            bar(false, false, Some(vec![2])),
+            bar(true, false, None),
+            RepeatMarks { end_repeat : true, jump : Some(Jump::DaCapo), ..plain() },
+        ];
+        assert_eq!(unroll(&bars), vec![0, 1, 0, 2, 3, 4, 3, 4, 0, 2, 3, 4]);
+    }
+
+    fn order() -> PlaybackOrder {
+        let bars = [bar(true, false, None), bar(false, true, Some(vec![1])), bar(false, false, Some(vec![2]))];
+        PlaybackOrder::new(&bars)
+    }
+
+    #[test_case(0, Some(0))]
+    #[test_case(2 * 3840 + 100, Some(100))]
+    #[test_case(3 * 3840 + 5, Some(2 * 3840 + 5))]
+    #[test_case(4 * 3840, None)]
+    fn test_played_to_written_tick(tick : u64, written : Option<u64>) {
+        assert_eq!(order().played_to_written_tick(tick, &SongSettings::default()), written);
+    }
+
+    #[test_case(100, vec![100, 2 * 3840 + 100])]
+    #[test_case(3840, vec![3840])]
+    #[test_case(2 * 3840, vec![3 * 3840])]
+    #[test_case(5 * 3840, vec![])]
+    fn test_written_to_played_ticks(tick : u64, played : Vec<u64>) {
+        assert_eq!(order().written_to_played_ticks(tick, &SongSettings::default()), played);
+    }
+
+    #[test]
+    fn test_unroll_timeline() {
+        let order = order();
+        assert_eq!(order.get_order(), &[0, 1, 0, 2]);
+        assert_eq!(order.get_played_bars(0), vec![0, 2]);
+        assert_eq!(order.get_written_bar(3), Some(2));
+
+        let written = Timeline::from_vec(vec![
+            SongLyric::new(0, "a", Syllabic::Single),
+            SongLyric::new(3840, "b", Syllabic::Single),
+            SongLyric::new(7680, "c", Syllabic::Single),
+        ]);
+        let played = order.unroll_timeline(&written, &SongSettings::default());
+        let texts : Vec<(u64, &str)> = played.iter().map(|l| (l.get_position().get_ticks_on(), l.get_text().as_str())).collect();
+        assert_eq!(texts, vec![(0, "a"), (3840, "b"), (7680, "a"), (11520, "c")]);
+    }
+}
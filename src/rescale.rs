@@ -0,0 +1,234 @@
+use crate::error::MusicalDataError;
+use crate::prelude::{Positionable, PositionableMut, Rational, Song, SongPosition, TempoMap, TimeSignatureMap, Timeline};
+
+/// How ticks that do not land exactly on the new resolution are rounded.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum RoundingPolicy {
+    /// Round to the nearest tick, halves up.
+    #[default]
+    Nearest,
+    Down,
+    Up,
+    /// Fail without changing the song.
+    Fail,
+}
+
+/// A tick that had to be rounded while rescaling.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RoundedTick {
+    /// The tick at the old PPQ.
+    pub original : u64,
+    /// The exact tick at the new PPQ.
+    pub exact : Rational,
+    pub rounded : u64,
+}
+
+/// The ticks rounded while rescaling.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RescaleReport {
+    /// Every tick that did not land exactly on the new resolution.
+    pub rounded : Vec<RoundedTick>,
+    /// Tempo and time signature changes that were rounded onto the tick of an earlier
+    /// change and replaced it, with the tick of the replaced change.
+    pub collisions : Vec<RoundedTick>,
+}
+
+impl Song {
+    /// Change the PPQ of the song and rescale every position to it: the chords, notes,
+    /// lyrics and markers as well as the tempo and time signature changes. Reports every
+    /// tick that did not land exactly on the new resolution and every tempo or time
+    /// signature change replaced by a later one rounded onto its tick. With
+    /// `RoundingPolicy::Fail` both are an error and the song stays unchanged.
+    pub fn rescale_ppq(&mut self, ppq : u64, policy : RoundingPolicy) -> Result<RescaleReport, MusicalDataError> {
+        if ppq == 0 {
+            return Err(MusicalDataError::InvalidPulsesPerQuarter(ppq));
+        }
+        let old_ppq = self.get_song_settings().get_pulses_per_quarter();
+        let scaler = Scaler { old_ppq, ppq, policy };
+        let settings = self.get_song_settings();
+        let tempo_ticks : Vec<u64> = settings.get_tempo_map().get_changes().iter().map(|c| c.get_tick()).collect();
+        let meter_ticks : Vec<u64> = settings.get_time_signature_map().get_changes().iter().map(|c| c.get_tick()).collect();
+        let collisions : Vec<RoundedTick> = scaler.collisions(&tempo_ticks).into_iter().chain(scaler.collisions(&meter_ticks)).collect();
+        if policy == RoundingPolicy::Fail {
+            if let Some(collision) = collisions.first() {
+                return Err(MusicalDataError::CollidingChanges(format!("the change at tick {} lands on tick {} at PPQ {}", collision.original, collision.rounded, ppq)));
+            }
+            if let Some(tick) = self.all_ticks().into_iter().find(|t| !scaler.exact(*t).is_integer()) {
+                return Err(MusicalDataError::InexactTicks(format!("tick {} at PPQ {}", tick, ppq)));
+            }
+        }
+
+        let mut rounded = Vec::new();
+        rescale_timeline(self.get_chord_timeline_mut(), &scaler, &mut rounded);
+        rescale_timeline(self.get_note_timeline_mut(), &scaler, &mut rounded);
+        rescale_timeline(self.get_lyric_timeline_mut(), &scaler, &mut rounded);
+        rescale_timeline(self.get_marker_timeline_mut(), &scaler, &mut rounded);
+
+        let settings = self.get_song_settings_mut();
+        let mut tempo_map = TempoMap::new();
+        for change in settings.get_tempo_map().get_changes() {
+            tempo_map.add_change_with_curve(scaler.scale(change.get_tick(), &mut rounded), change.get_tempo(), change.get_curve());
+        }
+        let mut time_signature_map = TimeSignatureMap::new();
+        for change in settings.get_time_signature_map().get_changes() {
+            time_signature_map.add_meter_change(scaler.scale(change.get_tick(), &mut rounded), change.get_meter().clone());
+        }
+        settings.set_tempo_map(tempo_map);
+        settings.set_time_signature_map(time_signature_map);
+        settings.set_pulses_per_quarter(ppq);
+
+        Ok(RescaleReport { rounded, collisions })
+    }
+
+    fn all_ticks(&self) -> Vec<u64> {
+        let positions = self.get_chords().iter().map(|c| c.get_position())
+            .chain(self.get_notes().iter().map(|n| n.get_position()))
+            .chain(self.get_lyrics().iter().map(|l| l.get_position()))
+            .chain(self.get_markers().iter().map(|m| m.get_position()));
+        let settings = self.get_song_settings();
+
+        positions.flat_map(|p| std::iter::once(p.get_ticks_on()).chain(p.get_ticks_off()))
+            .chain(settings.get_tempo_map().get_changes().iter().map(|c| c.get_tick()))
+            .chain(settings.get_time_signature_map().get_changes().iter().map(|c| c.get_tick()))
+            .collect()
+    }
+}
+
+struct Scaler {
+    old_ppq : u64,
+    ppq : u64,
+    policy : RoundingPolicy,
+}
+
+impl Scaler {
+    fn exact(&self, tick : u64) -> Rational {
+        Rational::from(tick) * Rational::from(self.ppq) / Rational::from(self.old_ppq.max(1))
+    }
+
+    fn round(&self, tick : u64) -> u64 {
+        let exact = self.exact(tick);
+        let rounded = match self.policy {
+            RoundingPolicy::Nearest | RoundingPolicy::Fail => exact.round(),
+            RoundingPolicy::Down => exact.floor(),
+            RoundingPolicy::Up => exact.ceil(),
+        };
+
+        rounded as u64
+    }
+
+    /// Scale a tick and record it if it had to be rounded.
+    fn scale(&self, tick : u64, rounded : &mut Vec<RoundedTick>) -> u64 {
+        let exact = self.exact(tick);
+        let result = self.round(tick);
+        if !exact.is_integer() {
+            rounded.push(RoundedTick { original : tick, exact, rounded : result });
+        }
+
+        result
+    }
+
+    /// The ordered change ticks that land on the same tick as the next change. Rounding
+    /// keeps the order, so only neighbours can collide.
+    fn collisions(&self, ticks : &[u64]) -> Vec<RoundedTick> {
+        ticks.windows(2)
+            .filter(|w| self.round(w[0]) == self.round(w[1]))
+            .map(|w| RoundedTick { original : w[0], exact : self.exact(w[0]), rounded : self.round(w[0]) })
+            .collect()
+    }
+}
+
+fn rescale_timeline<T : PositionableMut>(timeline : &mut Timeline<T>, scaler : &Scaler, rounded : &mut Vec<RoundedTick>) {
+    timeline.edit(|items| {
+        for item in items.iter_mut() {
+            let position = item.get_position();
+            let on = scaler.scale(position.get_ticks_on(), rounded);
+            let position = match position.get_ticks_off() {
+                Some(off) => SongPosition::from(on, scaler.scale(off, rounded)),
+                None => SongPosition::new(on),
+            };
+            item.set_position(position);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prelude::{SongChord, SongNote};
+    use test_case::test_case;
+
+    fn song() -> Song {
+        let mut song = Song::new();
+        song.get_song_settings_mut().set_pulses_per_quarter(96);
+        song.add_chord(SongChord::new(384, "C".parse().unwrap()));
+        song.add_note(SongNote::new(0, 96, 60));
+        song.add_note(SongNote::new(32, 65, 62));
+        song.get_song_settings_mut().get_tempo_map_mut().add_change(192, 90.0);
+        song.get_song_settings_mut().add_time_signature_change_at_bar(1, 3, 4);
+        song
+    }
+
+    #[test]
+    fn test_exact_rescale() {
+        let mut song = song();
+        assert_eq!(song.rescale_ppq(960, RoundingPolicy::Fail), Ok(RescaleReport::default()));
+
+        let settings = song.get_song_settings();
+        assert_eq!(settings.get_pulses_per_quarter(), 960);
+        assert_eq!(song.get_chords()[0].get_position().get_ticks_on(), 3840);
+        assert_eq!(*song.get_notes()[1].get_position(), SongPosition::from(320, 650));
+        assert_eq!(settings.get_tempo_map().get_changes()[0].get_tick(), 1920);
+        assert_eq!(settings.get_time_signature_at(3840), (3, 4));
+    }
+
+    #[test_case(RoundingPolicy::Nearest, 11, 22)]
+    #[test_case(RoundingPolicy::Down, 10, 21)]
+    #[test_case(RoundingPolicy::Up, 11, 22)]
+    fn test_rounding(policy : RoundingPolicy, on : u64, off : u64) {
+        let mut song = song();
+        let report = song.rescale_ppq(32, policy).unwrap();
+
+        // 32 and 65 ticks at 96 PPQ are 32/3 and 65/3 ticks at 32 PPQ.
+        assert_eq!(*song.get_notes()[1].get_position(), SongPosition::from(on, off));
+        assert!(report.collisions.is_empty());
+        assert_eq!(report.rounded, vec![
+            RoundedTick { original : 32, exact : Rational::new(32, 3), rounded : on },
+            RoundedTick { original : 65, exact : Rational::new(65, 3), rounded : off },
+        ]);
+    }
+
+    #[test]
+    fn test_fail_keeps_song() {
+        let mut song = song();
+        assert!(song.rescale_ppq(32, RoundingPolicy::Fail).is_err());
+        assert_eq!(song.get_song_settings().get_pulses_per_quarter(), 96);
+        assert_eq!(*song.get_notes()[1].get_position(), SongPosition::from(32, 65));
+    }
+
+    #[test]
+    fn test_zero_ppq() {
+        let mut song = song();
+        assert_eq!(song.rescale_ppq(0, RoundingPolicy::Nearest), Err(MusicalDataError::InvalidPulsesPerQuarter(0)));
+        assert_eq!(song.get_song_settings().get_pulses_per_quarter(), 96);
+    }
+
+    #[test]
+    fn test_colliding_changes() {
+        // 192 and 193 ticks at 96 PPQ both land on tick 2 at 1 PPQ.
+        let mut song = song();
+        song.get_song_settings_mut().get_tempo_map_mut().add_change(193, 100.0);
+        song.get_song_settings_mut().get_time_signature_map_mut().add_change(400, 6, 8);
+        assert_eq!(song.rescale_ppq(1, RoundingPolicy::Fail), Err(MusicalDataError::CollidingChanges(String::from("the change at tick 192 lands on tick 2 at PPQ 1"))));
+        assert_eq!(song.get_song_settings().get_pulses_per_quarter(), 96);
+
+        let report = song.rescale_ppq(1, RoundingPolicy::Nearest).unwrap();
+        assert_eq!(report.collisions, vec![
+            RoundedTick { original : 192, exact : Rational::from(2u64), rounded : 2 },
+            RoundedTick { original : 384, exact : Rational::from(4u64), rounded : 4 },
+        ]);
+        let settings = song.get_song_settings();
+        assert_eq!(settings.get_tempo_map().get_changes().len(), 1);
+        assert_eq!(settings.get_tempo_at(2), 100.0);
+        assert_eq!(settings.get_time_signature_at(4), (6, 8));
+    }
+}
@@ -0,0 +1,272 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::error::MusicalDataError;
+
+/// Subframes per frame.
+const SUBFRAMES : u64 = 100;
+
+/// The frame rates of SMPTE timecode. The numbers stored in `SongSettings::frame_type`
+/// are the codes of MIDI time code: 0 for 24, 1 for 25, 2 for 29.97 drop frame and 3 for 30 fps.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum FrameRate {
+    #[default]
+    Fps24,
+    Fps25,
+    Fps2997DropFrame,
+    Fps30,
+}
+
+impl FrameRate {
+    /// The real number of frames per second, 30000/1001 for drop frame.
+    pub fn get_frames_per_second(&self) -> f64 {
+        match self {
+            FrameRate::Fps24 => 24.0,
+            FrameRate::Fps25 => 25.0,
+            FrameRate::Fps2997DropFrame => 30000.0 / 1001.0,
+            FrameRate::Fps30 => 30.0,
+        }
+    }
+
+    /// The number of frames counted per timecode second.
+    pub fn get_nominal_frames(&self) -> u64 {
+        match self {
+            FrameRate::Fps24 => 24,
+            FrameRate::Fps25 => 25,
+            FrameRate::Fps2997DropFrame | FrameRate::Fps30 => 30,
+        }
+    }
+
+    pub fn is_drop_frame(&self) -> bool {
+        *self == FrameRate::Fps2997DropFrame
+    }
+}
+
+impl TryFrom<i64> for FrameRate {
+    type Error = MusicalDataError;
+
+    fn try_from(value : i64) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(FrameRate::Fps24),
+            1 => Ok(FrameRate::Fps25),
+            2 => Ok(FrameRate::Fps2997DropFrame),
+            3 => Ok(FrameRate::Fps30),
+            _ => Err(MusicalDataError::InvalidFrameRate(value)),
+        }
+    }
+}
+
+impl From<FrameRate> for i64 {
+    fn from(rate : FrameRate) -> Self {
+        match rate {
+            FrameRate::Fps24 => 0,
+            FrameRate::Fps25 => 1,
+            FrameRate::Fps2997DropFrame => 2,
+            FrameRate::Fps30 => 3,
+        }
+    }
+}
+
+/// A SMPTE timecode like "01:00:00:00" with subframes in hundredths of a frame.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Timecode {
+    hours : u64,
+    minutes : u64,
+    seconds : u64,
+    frames : u64,
+    subframes : u64,
+}
+
+impl Timecode {
+    pub fn new(hours : u64, minutes : u64, seconds : u64, frames : u64) -> Self {
+        Timecode { hours, minutes, seconds, frames, subframes : 0 }
+    }
+
+    pub fn get_hours(&self) -> u64 {
+        self.hours
+    }
+
+    pub fn get_minutes(&self) -> u64 {
+        self.minutes
+    }
+
+    pub fn get_seconds(&self) -> u64 {
+        self.seconds
+    }
+
+    pub fn get_frames(&self) -> u64 {
+        self.frames
+    }
+
+    pub fn get_subframes(&self) -> u64 {
+        self.subframes
+    }
+
+    pub fn set_subframes(&mut self, subframes : u64) {
+        self.subframes = subframes;
+    }
+
+    /// The number of frames since 00:00:00:00. Drop frame timecode skips the frame
+    /// numbers 0 and 1 at the start of every minute except every tenth minute.
+    pub fn to_frame_number(&self, rate : FrameRate) -> Result<u64, MusicalDataError> {
+        let invalid = || MusicalDataError::InvalidTimecode(self.to_string());
+        let nominal = rate.get_nominal_frames();
+        if self.minutes >= 60 || self.seconds >= 60 || self.frames >= nominal || self.subframes >= SUBFRAMES {
+            return Err(invalid());
+        }
+
+        let total_minutes = self.hours * 60 + self.minutes;
+        let frames = (total_minutes * 60 + self.seconds) * nominal + self.frames;
+        if !rate.is_drop_frame() {
+            return Ok(frames);
+        }
+        if self.seconds == 0 && self.frames < 2 && !self.minutes.is_multiple_of(10) {
+            return Err(invalid());
+        }
+
+        Ok(frames - 2 * (total_minutes - total_minutes / 10))
+    }
+
+    /// The timecode of a frame number counted from 00:00:00:00.
+    pub fn from_frame_number(frame : u64, rate : FrameRate) -> Self {
+        let mut frame = frame;
+        if rate.is_drop_frame() {
+            // 17982 frames per ten minutes and 1798 per minute after the first.
+            let tens = frame / 17982;
+            let rest = frame % 17982;
+            frame += 18 * tens;
+            if rest >= 2 {
+                frame += 2 * ((rest - 2) / 1798);
+            }
+        }
+
+        let nominal = rate.get_nominal_frames();
+        let seconds = frame / nominal;
+        Timecode::new(seconds / 3600, seconds / 60 % 60, seconds % 60, frame % nominal)
+    }
+
+    /// The frame number including the subframes as fraction.
+    pub(crate) fn to_frames(self, rate : FrameRate) -> Result<f64, MusicalDataError> {
+        Ok(self.to_frame_number(rate)? as f64 + self.subframes as f64 / SUBFRAMES as f64)
+    }
+
+    /// The timecode of a fractional frame number, rounded to the nearest subframe.
+    pub(crate) fn from_frames(frames : f64, rate : FrameRate) -> Self {
+        let subframes = (frames.max(0.0) * SUBFRAMES as f64).round() as u64;
+        let mut timecode = Timecode::from_frame_number(subframes / SUBFRAMES, rate);
+        timecode.subframes = subframes % SUBFRAMES;
+
+        timecode
+    }
+}
+
+/// Write the timecode as "HH:MM:SS:FF", followed by ".SS" if there are subframes.
+impl fmt::Display for Timecode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:02}:{:02}:{:02}:{:02}", self.hours, self.minutes, self.seconds, self.frames)?;
+        if self.subframes > 0 {
+            write!(f, ".{:02}", self.subframes)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Parse a timecode like "01:00:00:00", "00:01:00;02" (drop frame) or "00:00:01:12.50".
+impl FromStr for Timecode {
+    type Err = MusicalDataError;
+
+    fn from_str(s : &str) -> Result<Self, Self::Err> {
+        let invalid = || MusicalDataError::InvalidTimecode(String::from(s));
+        let (main, subframes) = match s.trim().split_once('.') {
+            Some((main, subframes)) => (main, Some(subframes)),
+            None => (s.trim(), None),
+        };
+
+        let parts : Vec<u64> = main.split([':', ';'])
+            .map(|p| if !p.is_empty() && p.chars().all(|c| c.is_ascii_digit()) { p.parse().ok() } else { None })
+            .collect::<Option<Vec<u64>>>()
+            .ok_or_else(invalid)?;
+        let [hours, minutes, seconds, frames] = parts[..] else {
+            return Err(invalid());
+        };
+
+        let mut timecode = Timecode::new(hours, minutes, seconds, frames);
+        if let Some(subframes) = subframes {
+            if subframes.is_empty() || subframes.len() > 2 || !subframes.chars().all(|c| c.is_ascii_digit()) {
+                return Err(invalid());
+            }
+            timecode.subframes = subframes.parse().map_err(|_| invalid())?;
+        }
+
+        Ok(timecode)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    #[test_case(0, 0, 0, 0, 0)]
+    #[test_case(1799, 0, 0, 59, 29)]
+    #[test_case(1800, 0, 1, 0, 2)]
+    #[test_case(17981, 0, 9, 59, 29)]
+    #[test_case(17982, 0, 10, 0, 0)]
+    #[test_case(17984, 0, 10, 0, 2)]
+    #[test_case(107892, 1, 0, 0, 0)]
+    fn test_drop_frame(frame : u64, hours : u64, minutes : u64, seconds : u64, frames : u64) {
+        let timecode = Timecode::new(hours, minutes, seconds, frames);
+        assert_eq!(Timecode::from_frame_number(frame, FrameRate::Fps2997DropFrame), timecode);
+        assert_eq!(timecode.to_frame_number(FrameRate::Fps2997DropFrame), Ok(frame));
+    }
+
+    #[test_case(FrameRate::Fps24, 86400, 1, 0, 0, 0)]
+    #[test_case(FrameRate::Fps25, 90061, 1, 0, 2, 11)]
+    #[test_case(FrameRate::Fps30, 1799, 0, 0, 59, 29)]
+    fn test_non_drop_frame(rate : FrameRate, frame : u64, hours : u64, minutes : u64, seconds : u64, frames : u64) {
+        let timecode = Timecode::new(hours, minutes, seconds, frames);
+        assert_eq!(Timecode::from_frame_number(frame, rate), timecode);
+        assert_eq!(timecode.to_frame_number(rate), Ok(frame));
+    }
+
+    #[test]
+    fn test_invalid_timecodes() {
+        assert!(Timecode::new(0, 1, 0, 0).to_frame_number(FrameRate::Fps2997DropFrame).is_err());
+        assert!(Timecode::new(0, 1, 0, 1).to_frame_number(FrameRate::Fps2997DropFrame).is_err());
+        assert!(Timecode::new(0, 0, 0, 25).to_frame_number(FrameRate::Fps25).is_err());
+        assert!(Timecode::new(0, 60, 0, 0).to_frame_number(FrameRate::Fps30).is_err());
+    }
+
+    #[test_case("01:00:00:00", Timecode::new(1, 0, 0, 0))]
+    #[test_case("00:01:00;02", Timecode::new(0, 1, 0, 2))]
+    #[test_case("00:00:01:12.50", { let mut t = Timecode::new(0, 0, 1, 12); t.set_subframes(50); t })]
+    fn test_parse(text : &str, timecode : Timecode) {
+        assert_eq!(text.parse::<Timecode>(), Ok(timecode));
+    }
+
+    #[test_case("01:00:00")]
+    #[test_case("01:00:00:00:00")]
+    #[test_case("01:00:00:0x")]
+    #[test_case("01:00:00:00.")]
+    #[test_case("01:00:00:00.100")]
+    fn test_parse_invalid(text : &str) {
+        assert_eq!(text.parse::<Timecode>(), Err(MusicalDataError::InvalidTimecode(String::from(text))));
+    }
+
+    #[test]
+    fn test_display() {
+        let mut timecode = Timecode::new(1, 2, 3, 4);
+        assert_eq!(timecode.to_string(), "01:02:03:04");
+        timecode.set_subframes(5);
+        assert_eq!(timecode.to_string(), "01:02:03:04.05");
+    }
+
+    #[test]
+    fn test_frame_rate_codes() {
+        for code in 0..4 {
+            assert_eq!(i64::from(FrameRate::try_from(code).unwrap()), code);
+        }
+        assert_eq!(FrameRate::try_from(4), Err(MusicalDataError::InvalidFrameRate(4)));
+    }
+}
@@ -1,5 +1,6 @@
 use core::fmt;
 
+use crate::prelude::{Marker, SongChord, SongLyric, SongNote, Timeline};
 use crate::song_meta::SongMeta;
 use crate::song_settings::SongSettings;
 
@@ -8,6 +9,10 @@ pub struct Song
 {
     song_meta : SongMeta,
     song_settings : SongSettings,
+    chords : Timeline<SongChord>,
+    notes : Timeline<SongNote>,
+    lyrics : Timeline<SongLyric>,
+    markers : Timeline<Marker>,
 }
 
 impl fmt::Display for Song {
@@ -21,6 +26,106 @@ impl Song {
         Song {
             song_meta : SongMeta::default(),
             song_settings : SongSettings::default(),
+            chords : Timeline::new(),
+            notes : Timeline::new(),
+            lyrics : Timeline::new(),
+            markers : Timeline::new(),
         }
     }
+
+    pub fn get_song_meta(&self) -> &SongMeta {
+        &self.song_meta
+    }
+
+    pub fn get_song_meta_mut(&mut self) -> &mut SongMeta {
+        &mut self.song_meta
+    }
+
+    pub fn set_song_meta(&mut self, song_meta : SongMeta) {
+        self.song_meta = song_meta;
+    }
+
+    pub fn get_song_settings(&self) -> &SongSettings {
+        &self.song_settings
+    }
+
+    pub fn get_song_settings_mut(&mut self) -> &mut SongSettings {
+        &mut self.song_settings
+    }
+
+    pub fn set_song_settings(&mut self, song_settings : SongSettings) {
+        self.song_settings = song_settings;
+    }
+
+    /// The chord track of this song, ordered by position.
+    pub fn get_chords(&self) -> &[SongChord] {
+        self.chords.get_items()
+    }
+
+    /// Insert a chord keeping the chord track ordered by position.
+    pub fn add_chord(&mut self, chord : SongChord) {
+        self.chords.insert(chord);
+    }
+
+    pub fn get_chord_timeline(&self) -> &Timeline<SongChord> {
+        &self.chords
+    }
+
+    pub fn get_chord_timeline_mut(&mut self) -> &mut Timeline<SongChord> {
+        &mut self.chords
+    }
+
+    /// The melody of this song, ordered by position.
+    pub fn get_notes(&self) -> &[SongNote] {
+        self.notes.get_items()
+    }
+
+    /// Insert a note keeping the melody ordered by position.
+    pub fn add_note(&mut self, note : SongNote) {
+        self.notes.insert(note);
+    }
+
+    pub fn get_note_timeline(&self) -> &Timeline<SongNote> {
+        &self.notes
+    }
+
+    pub fn get_note_timeline_mut(&mut self) -> &mut Timeline<SongNote> {
+        &mut self.notes
+    }
+
+    /// The lyrics of this song, ordered by position.
+    pub fn get_lyrics(&self) -> &[SongLyric] {
+        self.lyrics.get_items()
+    }
+
+    /// Insert a syllable keeping the lyrics ordered by position.
+    pub fn add_lyric(&mut self, lyric : SongLyric) {
+        self.lyrics.insert(lyric);
+    }
+
+    pub fn get_lyric_timeline(&self) -> &Timeline<SongLyric> {
+        &self.lyrics
+    }
+
+    pub fn get_lyric_timeline_mut(&mut self) -> &mut Timeline<SongLyric> {
+        &mut self.lyrics
+    }
+
+    /// The markers and regions of this song, ordered by position.
+    pub fn get_markers(&self) -> &[Marker] {
+        self.markers.get_items()
+    }
+
+    /// Insert a marker or region keeping the markers ordered by position.
+    pub fn add_marker(&mut self, marker : Marker) {
+        self.markers.insert(marker);
+    }
+
+    pub fn get_marker_timeline(&self) -> &Timeline<Marker> {
+        &self.markers
+    }
+
+    pub fn get_marker_timeline_mut(&mut self) -> &mut Timeline<Marker> {
+        &mut self.markers
+    }
 }
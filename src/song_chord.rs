@@ -1,15 +1,43 @@
 use std::fmt;
-use crate::prelude::{Positionable, SongPosition};
+use std::str::FromStr;
+use crate::prelude::{MusicalDataError, Positionable, PositionableMut, SongPosition};
 
-#[derive(Default, Debug, Clone, Copy)]
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum NoteMod {
+    DoubleFlat,
     Flat,
     #[default]
     Normal,
-    Sharp
+    Sharp,
+    DoubleSharp,
 }
 
-#[derive(Debug, Clone, Copy)]
+impl NoteMod {
+    /// Create a modifier from a chromatic alteration in semitones (-2 to 2).
+    pub fn from_alter(alter : i64) -> Option<Self> {
+        match alter {
+            -2 => Some(NoteMod::DoubleFlat),
+            -1 => Some(NoteMod::Flat),
+            0 => Some(NoteMod::Normal),
+            1 => Some(NoteMod::Sharp),
+            2 => Some(NoteMod::DoubleSharp),
+            _ => None,
+        }
+    }
+
+    /// The chromatic alteration in semitones.
+    pub fn get_alter(&self) -> i64 {
+        match self {
+            NoteMod::DoubleFlat => -2,
+            NoteMod::Flat => -1,
+            NoteMod::Normal => 0,
+            NoteMod::Sharp => 1,
+            NoteMod::DoubleSharp => 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum NoteName {
     C(NoteMod),
     D(NoteMod),
@@ -33,71 +61,196 @@ impl fmt::Display for NoteName {
 }
 
 impl NoteName {
+    /// Create a note name from a step letter ('A' to 'G') and a modifier.
+    pub fn from_step(step : char, m : NoteMod) -> Option<Self> {
+        match step.to_ascii_uppercase() {
+            'C' => Some(NoteName::C(m)),
+            'D' => Some(NoteName::D(m)),
+            'E' => Some(NoteName::E(m)),
+            'F' => Some(NoteName::F(m)),
+            'G' => Some(NoteName::G(m)),
+            'A' => Some(NoteName::A(m)),
+            'B' => Some(NoteName::B(m)),
+            _ => None,
+        }
+    }
+
+    /// The step letter of this note without its modifier.
+    pub fn get_step(&self) -> char {
+        match self {
+            NoteName::C(_) => 'C',
+            NoteName::D(_) => 'D',
+            NoteName::E(_) => 'E',
+            NoteName::F(_) => 'F',
+            NoteName::G(_) => 'G',
+            NoteName::A(_) => 'A',
+            NoteName::B(_) => 'B',
+        }
+    }
+
+    pub fn get_mod(&self) -> NoteMod {
+        match self {
+            NoteName::C(m) | NoteName::D(m) | NoteName::E(m) | NoteName::F(m) |
+            NoteName::G(m) | NoteName::A(m) | NoteName::B(m) => *m,
+        }
+    }
+
+    /// The midi pitch of this note in the given octave, middle C being C4 (60).
+    pub fn to_midi_pitch(&self, octave : i64) -> Option<u8> {
+        let natural : i64 = match self {
+            NoteName::C(_) => 0,
+            NoteName::D(_) => 2,
+            NoteName::E(_) => 4,
+            NoteName::F(_) => 5,
+            NoteName::G(_) => 7,
+            NoteName::A(_) => 9,
+            NoteName::B(_) => 11,
+        };
+
+        u8::try_from((octave + 1) * 12 + natural + self.get_mod().get_alter()).ok()
+            .filter(|p| *p < 128)
+    }
+
+    /// Spell a midi pitch using either sharps or flats. Returns the note name and its octave.
+    pub fn from_midi_pitch(pitch : u8, prefer_flats : bool) -> (NoteName, i64) {
+        const SHARPS : [(char, NoteMod); 12] = [
+            ('C', NoteMod::Normal), ('C', NoteMod::Sharp), ('D', NoteMod::Normal), ('D', NoteMod::Sharp),
+            ('E', NoteMod::Normal), ('F', NoteMod::Normal), ('F', NoteMod::Sharp), ('G', NoteMod::Normal),
+            ('G', NoteMod::Sharp), ('A', NoteMod::Normal), ('A', NoteMod::Sharp), ('B', NoteMod::Normal),
+        ];
+        const FLATS : [(char, NoteMod); 12] = [
+            ('C', NoteMod::Normal), ('D', NoteMod::Flat), ('D', NoteMod::Normal), ('E', NoteMod::Flat),
+            ('E', NoteMod::Normal), ('F', NoteMod::Normal), ('G', NoteMod::Flat), ('G', NoteMod::Normal),
+            ('A', NoteMod::Flat), ('A', NoteMod::Normal), ('B', NoteMod::Flat), ('B', NoteMod::Normal),
+        ];
+
+        let (step, m) = if prefer_flats { FLATS } else { SHARPS }[(pitch % 12) as usize];
+        let name = NoteName::from_step(step, m).unwrap_or_default();
+
+        (name, pitch as i64 / 12 - 1)
+    }
+
+    /// The pitch class of this note, 0 being C and 11 being B.
+    pub fn get_pitch_class(&self) -> u8 {
+        let natural : i64 = match self {
+            NoteName::C(_) => 0,
+            NoteName::D(_) => 2,
+            NoteName::E(_) => 4,
+            NoteName::F(_) => 5,
+            NoteName::G(_) => 7,
+            NoteName::A(_) => 9,
+            NoteName::B(_) => 11,
+        };
+
+        (natural + self.get_mod().get_alter()).rem_euclid(12) as u8
+    }
+
     pub fn to_string(&self) -> &str {
         match self {
             NoteName::C(m) => {
                 match m {
+                    NoteMod::DoubleFlat => "Cbb",
                     NoteMod::Flat => "Cb",
                     NoteMod::Normal => "C",
                     NoteMod::Sharp => "C#",
+                    NoteMod::DoubleSharp => "C##",
                 }
             },
             NoteName::D(m) => {
                 match m {
+                    NoteMod::DoubleFlat => "Dbb",
                     NoteMod::Flat => "Db",
                     NoteMod::Normal => "D",
                     NoteMod::Sharp => "D#",
+                    NoteMod::DoubleSharp => "D##",
                 }
             },
             NoteName::E(m) => {
                 match m {
+                    NoteMod::DoubleFlat => "Ebb",
                     NoteMod::Flat => "Eb",
                     NoteMod::Normal => "E",
                     NoteMod::Sharp => "E#",
+                    NoteMod::DoubleSharp => "E##",
                 }
             },
             NoteName::F(m) => {
                 match m {
+                    NoteMod::DoubleFlat => "Fbb",
                     NoteMod::Flat => "Fb",
                     NoteMod::Normal => "F",
                     NoteMod::Sharp => "F#",
+                    NoteMod::DoubleSharp => "F##",
                 }
             },
             NoteName::G(m) => {
                 match m {
+                    NoteMod::DoubleFlat => "Gbb",
                     NoteMod::Flat => "Gb",
                     NoteMod::Normal => "G",
                     NoteMod::Sharp => "G#",
+                    NoteMod::DoubleSharp => "G##",
                 }
             },
             NoteName::A(m) => {
                 match m {
+                    NoteMod::DoubleFlat => "Abb",
                     NoteMod::Flat => "Ab",
                     NoteMod::Normal => "A",
                     NoteMod::Sharp => "A#",
+                    NoteMod::DoubleSharp => "A##",
                 }
             },
             NoteName::B(m) => {
                 match m {
+                    NoteMod::DoubleFlat => "Bbb",
                     NoteMod::Flat => "Bb",
                     NoteMod::Normal => "B",
                     NoteMod::Sharp => "B#",
+                    NoteMod::DoubleSharp => "B##",
                 }
             },
         }
     }
 }
 
-#[derive(Debug, Default, Clone, Copy)]
+/// Parse a note name like "C", "F#", "Bb" or "Ebb".
+impl FromStr for NoteName {
+    type Err = MusicalDataError;
+
+    fn from_str(s : &str) -> Result<Self, Self::Err> {
+        let invalid = || MusicalDataError::InvalidNoteName(String::from(s));
+        let mut chars = s.chars();
+        let step = chars.next().ok_or_else(invalid)?;
+        let m = match chars.as_str() {
+            "" => NoteMod::Normal,
+            "b" => NoteMod::Flat,
+            "bb" => NoteMod::DoubleFlat,
+            "#" => NoteMod::Sharp,
+            "##" | "x" => NoteMod::DoubleSharp,
+            _ => return Err(invalid()),
+        };
+
+        NoteName::from_step(step, m).ok_or_else(invalid)
+    }
+}
+
+/// The triad a chord is built on. Extensions are stored as `ChordFuntion`s.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 pub enum ChordType {
     #[default]
     Major,
     Minor,
     Diminished,
-    Sus(u64)
+    Augmented,
+    Sus(u64),
+    /// Root and fifth only.
+    Power,
 }
 
-#[derive(Debug, Clone, Copy)]
+/// An interval of a chord relative to the major scale of its root. I.e. `Seventh(NoteMod::Flat)`
+/// is the minor seventh of a dominant chord while `Seventh(NoteMod::Normal)` is the major seventh.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ChordFuntion {
     Root(NoteMod),
     Second(NoteMod),
@@ -116,10 +269,52 @@ impl Default for ChordFuntion {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+impl ChordFuntion {
+    /// Create a chord function from a scale degree. Returns `None` for degrees that
+    /// are defined by the `ChordType` (the third) or do not exist.
+    pub fn from_degree(degree : u64, m : NoteMod) -> Option<Self> {
+        match degree {
+            1 => Some(ChordFuntion::Root(m)),
+            2 => Some(ChordFuntion::Second(m)),
+            4 => Some(ChordFuntion::Fourth(m)),
+            5 => Some(ChordFuntion::Fifth(m)),
+            6 => Some(ChordFuntion::Sixth(m)),
+            7 => Some(ChordFuntion::Seventh(m)),
+            9 => Some(ChordFuntion::Nineth(m)),
+            11 => Some(ChordFuntion::Eleventh(m)),
+            13 => Some(ChordFuntion::Thirteenth(m)),
+            _ => None,
+        }
+    }
+
+    pub fn get_degree(&self) -> u64 {
+        match self {
+            ChordFuntion::Root(_) => 1,
+            ChordFuntion::Second(_) => 2,
+            ChordFuntion::Fourth(_) => 4,
+            ChordFuntion::Fifth(_) => 5,
+            ChordFuntion::Sixth(_) => 6,
+            ChordFuntion::Seventh(_) => 7,
+            ChordFuntion::Nineth(_) => 9,
+            ChordFuntion::Eleventh(_) => 11,
+            ChordFuntion::Thirteenth(_) => 13,
+        }
+    }
+
+    pub fn get_mod(&self) -> NoteMod {
+        match self {
+            ChordFuntion::Root(m) | ChordFuntion::Second(m) | ChordFuntion::Fourth(m) |
+            ChordFuntion::Fifth(m) | ChordFuntion::Sixth(m) | ChordFuntion::Seventh(m) |
+            ChordFuntion::Nineth(m) | ChordFuntion::Eleventh(m) | ChordFuntion::Thirteenth(m) => *m,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 pub struct Chord {
     root : NoteName,
     base : Option<NoteName>,
+    chord_type : ChordType,
     interval_1 : Option<ChordFuntion>,
     interval_2 : Option<ChordFuntion>,
     interval_3 : Option<ChordFuntion>,
@@ -128,6 +323,299 @@ pub struct Chord {
     interval_6 : Option<ChordFuntion>,
 }
 
+impl Chord {
+    pub fn new(root : NoteName, chord_type : ChordType) -> Self {
+        Chord {
+            root,
+            chord_type,
+            ..Default::default()
+        }
+    }
+
+    pub fn get_root(&self) -> NoteName {
+        self.root
+    }
+
+    /// The bass note of a slash chord.
+    pub fn get_base(&self) -> Option<NoteName> {
+        self.base
+    }
+
+    pub fn get_chord_type(&self) -> ChordType {
+        self.chord_type
+    }
+
+    /// All intervals that have been added to the triad.
+    pub fn get_intervals(&self) -> Vec<ChordFuntion> {
+        [self.interval_1, self.interval_2, self.interval_3, self.interval_4, self.interval_5, self.interval_6]
+            .into_iter()
+            .flatten()
+            .collect()
+    }
+
+    pub fn has_interval(&self, interval : ChordFuntion) -> bool {
+        self.get_intervals().contains(&interval)
+    }
+
+    pub fn set_root(&mut self, root : NoteName) {
+        self.root = root;
+    }
+
+    pub fn set_base(&mut self, base : Option<NoteName>) {
+        self.base = base;
+    }
+
+    pub fn set_chord_type(&mut self, chord_type : ChordType) {
+        self.chord_type = chord_type;
+    }
+
+    /// Add an interval to the chord. An interval of the same degree is replaced.
+    /// Returns false if all interval slots are in use.
+    pub fn add_interval(&mut self, interval : ChordFuntion) -> bool {
+        self.remove_interval(interval.get_degree());
+        for slot in self.interval_slots() {
+            if slot.is_none() {
+                *slot = Some(interval);
+                return true;
+            }
+        }
+
+        false
+    }
+
+    /// Remove the interval of the given degree from the chord.
+    pub fn remove_interval(&mut self, degree : u64) {
+        let mut intervals = self.get_intervals();
+        intervals.retain(|i| i.get_degree() != degree);
+        for (i, slot) in self.interval_slots().into_iter().enumerate() {
+            *slot = intervals.get(i).copied();
+        }
+    }
+
+    fn interval_slots(&mut self) -> [&mut Option<ChordFuntion>; 6] {
+        [
+            &mut self.interval_1, &mut self.interval_2, &mut self.interval_3,
+            &mut self.interval_4, &mut self.interval_5, &mut self.interval_6,
+        ]
+    }
+}
+
+/// Write the chord as a chord symbol like "Am7", "G7/B", "Bbmaj9" or "F#m7b5".
+impl fmt::Display for Chord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let intervals = self.get_intervals();
+        let seventh = intervals.iter().find(|i| i.get_degree() == 7).map(|i| i.get_mod());
+        let mut covered : Vec<ChordFuntion> = Vec::new();
+
+        let quality = match (self.chord_type, seventh) {
+            (ChordType::Diminished, Some(NoteMod::Flat)) => "m7b5",
+            (ChordType::Diminished, Some(NoteMod::DoubleFlat)) => "dim7",
+            (ChordType::Diminished, _) => "dim",
+            (ChordType::Augmented, _) => "aug",
+            (ChordType::Minor, _) => "m",
+            (ChordType::Power, _) => "5",
+            (ChordType::Major, _) | (ChordType::Sus(_), _) => "",
+        };
+        if matches!(quality, "m7b5" | "dim7") {
+            covered.extend(seventh.and_then(|m| ChordFuntion::from_degree(7, m)));
+        }
+
+        let mut extension = String::new();
+        match seventh {
+            Some(m) if covered.is_empty() && (m == NoteMod::Flat || m == NoteMod::Normal) => {
+                covered.push(ChordFuntion::Seventh(m));
+                let mut number = 7;
+                for upper in [ChordFuntion::Nineth(NoteMod::Normal), ChordFuntion::Eleventh(NoteMod::Normal), ChordFuntion::Thirteenth(NoteMod::Normal)] {
+                    if !intervals.contains(&upper) {
+                        break;
+                    }
+                    covered.push(upper);
+                    number = upper.get_degree();
+                }
+                if m == NoteMod::Normal {
+                    extension.push_str("maj");
+                }
+                extension.push_str(&number.to_string());
+            },
+            _ => {
+                if intervals.contains(&ChordFuntion::Sixth(NoteMod::Normal)) {
+                    covered.push(ChordFuntion::Sixth(NoteMod::Normal));
+                    extension.push('6');
+                    if intervals.contains(&ChordFuntion::Nineth(NoteMod::Normal)) {
+                        covered.push(ChordFuntion::Nineth(NoteMod::Normal));
+                        extension.push('9');
+                    }
+                }
+            },
+        }
+
+        write!(f, "{}{}{}", self.root, quality, extension)?;
+        if let ChordType::Sus(n) = self.chord_type {
+            write!(f, "sus{}", n)?;
+        }
+
+        let mut rest : Vec<&ChordFuntion> = intervals.iter().filter(|i| !covered.contains(i)).collect();
+        rest.sort_by_key(|i| i.get_degree());
+        for interval in rest {
+            let prefix = match interval.get_mod() {
+                NoteMod::DoubleFlat => "bb",
+                NoteMod::Flat => "b",
+                NoteMod::Normal => "add",
+                NoteMod::Sharp => "#",
+                NoteMod::DoubleSharp => "##",
+            };
+            write!(f, "{}{}", prefix, interval.get_degree())?;
+        }
+
+        if let Some(base) = self.base {
+            write!(f, "/{}", base)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Parse a chord symbol like "Am7", "G7/B", "Bbmaj9", "F#m7b5", "C7sus4" or "D6/9".
+impl FromStr for Chord {
+    type Err = MusicalDataError;
+
+    fn from_str(s : &str) -> Result<Self, Self::Err> {
+        let invalid = || MusicalDataError::InvalidChordSymbol(String::from(s));
+        let symbol = s.trim();
+
+        let (main, base) = match symbol.rsplit_once('/') {
+            Some((main, base)) if base.parse::<NoteName>().is_ok() => (main, base.parse::<NoteName>().ok()),
+            _ => (symbol, None),
+        };
+
+        let root_length = 1 + main.chars().skip(1).take(2).take_while(|c| *c == '#' || *c == 'b').count();
+        if main.len() < root_length {
+            return Err(invalid());
+        }
+        let root : NoteName = main[..root_length].parse().map_err(|_| invalid())?;
+        let mut chord = Chord::new(root, ChordType::Major);
+        chord.set_base(base);
+
+        const QUALITIES : [(&str, ChordType, Option<NoteMod>); 19] = [
+            ("m7b5", ChordType::Diminished, Some(NoteMod::Flat)),
+            ("m7(b5)", ChordType::Diminished, Some(NoteMod::Flat)),
+            ("ø7", ChordType::Diminished, Some(NoteMod::Flat)),
+            ("ø", ChordType::Diminished, Some(NoteMod::Flat)),
+            ("dim7", ChordType::Diminished, Some(NoteMod::DoubleFlat)),
+            ("o7", ChordType::Diminished, Some(NoteMod::DoubleFlat)),
+            ("°7", ChordType::Diminished, Some(NoteMod::DoubleFlat)),
+            ("dim", ChordType::Diminished, None),
+            ("o", ChordType::Diminished, None),
+            ("°", ChordType::Diminished, None),
+            ("aug", ChordType::Augmented, None),
+            ("+", ChordType::Augmented, None),
+            ("min", ChordType::Minor, None),
+            ("mi", ChordType::Minor, None),
+            ("-", ChordType::Minor, None),
+            // "maj" and "M" must not be taken for minor.
+            ("maj", ChordType::Major, None),
+            ("M", ChordType::Major, None),
+            ("m", ChordType::Minor, None),
+            ("5", ChordType::Power, None),
+        ];
+
+        let mut rest = &main[root_length..];
+        if let Some((prefix, chord_type, seventh)) = QUALITIES.iter().find(|(p, _, _)| rest.starts_with(p)) {
+            // "maj" and "M" only mark the major seventh, they are handled below.
+            if *prefix != "maj" && *prefix != "M" {
+                chord.set_chord_type(*chord_type);
+                if let Some(m) = seventh {
+                    chord.add_interval(ChordFuntion::Seventh(*m));
+                }
+                rest = &rest[prefix.len()..];
+            }
+        }
+        if chord.chord_type == ChordType::Power && !rest.is_empty() {
+            return Err(invalid());
+        }
+
+        let mut major_seventh = false;
+        while !rest.is_empty() {
+            let token_start = rest.len();
+            rest = rest.trim_start_matches(['(', ')', ',', ' ']);
+            if let Some(p) = ["maj", "Maj", "M", "Δ", "^"].iter().find(|p| rest.starts_with(**p)) {
+                major_seventh = true;
+                rest = &rest[p.len()..];
+                // "Cmaj" is a major triad while a triangle alone stands for the major seventh.
+                if (*p == "Δ" || *p == "^") && !rest.starts_with(|c : char| c.is_ascii_digit()) {
+                    chord.add_interval(ChordFuntion::Seventh(NoteMod::Normal));
+                }
+                continue;
+            }
+            if let Some(r) = rest.strip_prefix("sus") {
+                let digits = r.chars().take_while(|c| c.is_ascii_digit()).count();
+                let n = if digits == 0 { 4 } else { r[..digits].parse().map_err(|_| invalid())? };
+                chord.set_chord_type(ChordType::Sus(n));
+                rest = &r[digits..];
+                continue;
+            }
+
+            let (m, r) = if let Some(r) = rest.strip_prefix("add") {
+                (None, r)
+            } else if let Some(r) = rest.strip_prefix("bb") {
+                (Some(NoteMod::DoubleFlat), r)
+            } else if let Some(r) = rest.strip_prefix('b') {
+                (Some(NoteMod::Flat), r)
+            } else if let Some(r) = rest.strip_prefix("##") {
+                (Some(NoteMod::DoubleSharp), r)
+            } else if let Some(r) = rest.strip_prefix('#') {
+                (Some(NoteMod::Sharp), r)
+            } else {
+                (None, rest)
+            };
+            let added = rest.starts_with("add");
+            let digits = r.chars().take_while(|c| c.is_ascii_digit()).count();
+            if digits == 0 {
+                if rest.len() == token_start {
+                    return Err(invalid());
+                }
+                continue;
+            }
+            let number : u64 = r[..digits].parse().map_err(|_| invalid())?;
+            rest = &r[digits..];
+
+            if added || m.is_some() {
+                let interval = ChordFuntion::from_degree(number, m.unwrap_or_default()).ok_or_else(invalid)?;
+                chord.add_interval(interval);
+                continue;
+            }
+
+            // A plain number is an extension which includes all lower extensions.
+            let seventh = if major_seventh { NoteMod::Normal } else { NoteMod::Flat };
+            let extensions : &[ChordFuntion] = match number {
+                6 => &[ChordFuntion::Sixth(NoteMod::Normal)],
+                69 => &[ChordFuntion::Sixth(NoteMod::Normal), ChordFuntion::Nineth(NoteMod::Normal)],
+                7 => &[ChordFuntion::Seventh(seventh)],
+                9 => &[ChordFuntion::Seventh(seventh), ChordFuntion::Nineth(NoteMod::Normal)],
+                11 => &[ChordFuntion::Seventh(seventh), ChordFuntion::Nineth(NoteMod::Normal), ChordFuntion::Eleventh(NoteMod::Normal)],
+                13 => &[ChordFuntion::Seventh(seventh), ChordFuntion::Nineth(NoteMod::Normal), ChordFuntion::Eleventh(NoteMod::Normal), ChordFuntion::Thirteenth(NoteMod::Normal)],
+                _ => return Err(invalid()),
+            };
+            for extension in extensions {
+                // Do not overwrite the seventh of a diminished or half diminished chord.
+                if extension.get_degree() != 7 || !chord.get_intervals().iter().any(|i| i.get_degree() == 7) {
+                    chord.add_interval(*extension);
+                }
+            }
+            // "6/9" is written with a slash.
+            if number == 6 {
+                if let Some(r) = rest.strip_prefix("/9") {
+                    chord.add_interval(ChordFuntion::Nineth(NoteMod::Normal));
+                    rest = r;
+                }
+            }
+        }
+
+        Ok(chord)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct SongChord {
     pos : SongPosition,
     chord : Chord,
@@ -139,6 +627,12 @@ impl Positionable for SongChord {
     }
 }
 
+impl PositionableMut for SongChord {
+    fn set_position(&mut self, position : SongPosition) {
+        self.pos = position;
+    }
+}
+
 impl SongChord {
     pub fn new(tick : u64, chord : Chord) -> Self {
         SongChord {
@@ -146,4 +640,78 @@ impl SongChord {
             chord
         }
     }
-}
\ No newline at end of file
+
+    pub fn get_chord(&self) -> &Chord {
+        &self.chord
+    }
+
+    pub fn get_chord_mut(&mut self) -> &mut Chord {
+        &mut self.chord
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    #[test_case("C")]
+    #[test_case("Am")]
+    #[test_case("G7")]
+    #[test_case("Cmaj7")]
+    #[test_case("Ebmaj9")]
+    #[test_case("Dm7")]
+    #[test_case("Bm7b5")]
+    #[test_case("Bdim7")]
+    #[test_case("Cdim")]
+    #[test_case("Caug")]
+    #[test_case("Gsus4")]
+    #[test_case("G7sus4")]
+    #[test_case("C6")]
+    #[test_case("C69")]
+    #[test_case("Am6")]
+    #[test_case("Ammaj7")]
+    #[test_case("F#m7/C#")]
+    #[test_case("Bb13")]
+    #[test_case("C7b9")]
+    #[test_case("C7#5")]
+    #[test_case("Cadd9")]
+    #[test_case("C5")]
+    fn test_chord_symbol_round_trip(symbol : &str) {
+        let chord : Chord = symbol.parse().unwrap();
+        assert_eq!(chord.to_string(), symbol);
+    }
+
+    #[test_case("Cmin7", "Cm7")]
+    #[test_case("C-7", "Cm7")]
+    #[test_case("CΔ", "Cmaj7" ; "triangle")]
+    #[test_case("CM7", "Cmaj7")]
+    #[test_case("Cmaj", "C")]
+    #[test_case("Cø", "Cm7b5")]
+    #[test_case("Co7", "Cdim7")]
+    #[test_case("C+", "Caug")]
+    #[test_case("D6/9", "D69")]
+    #[test_case("C7(b9,#11)", "C7b9#11")]
+    #[test_case("Csus", "Csus4")]
+    fn test_chord_symbol_aliases(symbol : &str, normalized : &str) {
+        let chord : Chord = symbol.parse().unwrap();
+        assert_eq!(chord.to_string(), normalized);
+    }
+
+    #[test_case("H7")]
+    #[test_case("Cxyz")]
+    #[test_case("C5b9")]
+    #[test_case("")]
+    fn test_invalid_chord_symbol(symbol : &str) {
+        assert!(symbol.parse::<Chord>().is_err());
+    }
+
+    #[test]
+    fn test_chord_symbol_content() {
+        let chord : Chord = "G7/B".parse().unwrap();
+        assert_eq!(chord.get_root(), NoteName::G(NoteMod::Normal));
+        assert_eq!(chord.get_base(), Some(NoteName::B(NoteMod::Normal)));
+        assert_eq!(chord.get_chord_type(), ChordType::Major);
+        assert_eq!(chord.get_intervals(), vec![ChordFuntion::Seventh(NoteMod::Flat)]);
+    }
+}
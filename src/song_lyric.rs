@@ -0,0 +1,49 @@
+use crate::prelude::{Positionable, PositionableMut, SongPosition};
+
+/// Describes where a syllable sits within its word.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Syllabic {
+    #[default]
+    Single,
+    Begin,
+    Middle,
+    End,
+}
+
+/// A single syllable of the lyrics, sung at the note starting at the same position.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SongLyric {
+    pos : SongPosition,
+    text : String,
+    syllabic : Syllabic,
+}
+
+impl Positionable for SongLyric {
+    fn get_position(&self) -> &SongPosition {
+        &self.pos
+    }
+}
+
+impl PositionableMut for SongLyric {
+    fn set_position(&mut self, position : SongPosition) {
+        self.pos = position;
+    }
+}
+
+impl SongLyric {
+    pub fn new(tick : u64, text : &str, syllabic : Syllabic) -> Self {
+        SongLyric {
+            pos : SongPosition::new(tick),
+            text : String::from(text),
+            syllabic,
+        }
+    }
+
+    pub fn get_text(&self) -> &String {
+        &self.text
+    }
+
+    pub fn get_syllabic(&self) -> Syllabic {
+        self.syllabic
+    }
+}
@@ -21,7 +21,7 @@ impl fmt::Display for SongMeta {
         write!(f, "Title: '{}' Album: '{}' Year: '{}' Comments: '{}' Songwriter: '{}' Composer: '{}' Arranger: '{}' Copyright: '{}' Artist: '{}' Page: '{}' Genre: '{}'", 
             self.title.clone().unwrap_or(String::from("-")), 
             self.album.clone().unwrap_or(String::from("-")),
-            self.year.clone().unwrap_or(1970),
+            self.year.unwrap_or(1970),
             self.comment.clone().unwrap_or(String::from("-")),
             self.songwriter.clone().unwrap_or(String::from("-")),
             self.composer.clone().unwrap_or(String::from("-")),
@@ -0,0 +1,71 @@
+use crate::prelude::{NoteName, Positionable, PositionableMut, SongPosition};
+
+/// A pitched note event of a melody or any other part.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SongNote {
+    pos : SongPosition,
+    pitch : u8,
+    velocity : u8,
+}
+
+impl Positionable for SongNote {
+    fn get_position(&self) -> &SongPosition {
+        &self.pos
+    }
+}
+
+impl PositionableMut for SongNote {
+    fn set_position(&mut self, position : SongPosition) {
+        self.pos = position;
+    }
+}
+
+impl SongNote {
+    /// Create a note from its on and off ticks and its midi pitch.
+    pub fn new(ticks_on : u64, ticks_off : u64, pitch : u8) -> Self {
+        SongNote {
+            pos : SongPosition::from(ticks_on, ticks_off),
+            pitch,
+            velocity : 100,
+        }
+    }
+
+    pub fn get_pitch(&self) -> u8 {
+        self.pitch
+    }
+
+    pub fn get_velocity(&self) -> u8 {
+        self.velocity
+    }
+
+    /// Spell the pitch of this note. Returns the note name and its octave.
+    pub fn get_note_name(&self, prefer_flats : bool) -> (NoteName, i64) {
+        NoteName::from_midi_pitch(self.pitch, prefer_flats)
+    }
+
+    pub fn set_pitch(&mut self, pitch : u8) {
+        self.pitch = pitch;
+    }
+
+    pub fn set_velocity(&mut self, velocity : u8) {
+        self.velocity = velocity;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prelude::NoteMod;
+    use test_case::test_case;
+
+    #[test_case(60, false, NoteName::C(NoteMod::Normal), 4)]
+    #[test_case(61, false, NoteName::C(NoteMod::Sharp), 4)]
+    #[test_case(61, true, NoteName::D(NoteMod::Flat), 4)]
+    #[test_case(69, false, NoteName::A(NoteMod::Normal), 4)]
+    #[test_case(47, true, NoteName::B(NoteMod::Normal), 2)]
+    fn test_get_note_name(pitch : u8, prefer_flats : bool, name : NoteName, octave : i64) {
+        let note = SongNote::new(0, 960, pitch);
+        assert_eq!(note.get_note_name(prefer_flats), (name, octave));
+        assert_eq!(name.to_midi_pitch(octave), Some(pitch));
+    }
+}
@@ -1,7 +1,13 @@
-use crate::prelude::SongSettings;
+use std::ops::{Add, Sub};
+
+use crate::prelude::{MusicalDataError, Rational, SongSettings, TickOffset, Timecode, WholeNotes};
 
 /// Stores a song position in midi ticks and provides methods to translate ticks into
 /// hours, minutes and seconds and milliseconds or bar, beat, note and click etc.
+///
+/// Positions are ordered by their onset and then by their offset, a position without
+/// an offset comes first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct SongPosition {
     ticks_on : u64,
     ticks_off : Option<u64>,
@@ -11,6 +17,11 @@ pub trait Positionable {
     fn get_position(&self) -> &SongPosition;
 }
 
+/// Positioned items that can be moved, e.g. by quantizing or rescaling.
+pub trait PositionableMut : Positionable {
+    fn set_position(&mut self, position : SongPosition);
+}
+
 impl SongPosition {
     pub fn new(ticks : u64) -> Self {
         SongPosition{
@@ -19,6 +30,8 @@ impl SongPosition {
         }
     }
 
+    /// Create a position from an onset and an offset. A reversed position is kept as it
+    /// is, see `checked_from` to reject it.
     pub fn from(ticks_on : u64, ticks_off : u64) -> Self {
         SongPosition {
             ticks_on,
@@ -26,12 +39,237 @@ impl SongPosition {
         }
     }
 
+    /// Like `from` but fails if the position ends before it starts, instead of
+    /// underflowing later on.
+    pub fn checked_from(ticks_on : u64, ticks_off : u64) -> Result<Self, MusicalDataError> {
+        if ticks_off < ticks_on {
+            return Err(MusicalDataError::ReversedPosition(ticks_on, ticks_off));
+        }
+
+        Ok(SongPosition::from(ticks_on, ticks_off))
+    }
+
+    /// Create a position from seconds since the start of the song, rounded to the
+    /// nearest tick.
+    pub fn from_seconds(seconds : f64, settings : &SongSettings) -> Self {
+        SongPosition::new(settings.seconds_to_ticks(seconds).round() as u64)
+    }
+
+    /// Create a position from an exact time in whole notes. Fails if the PPQ of the
+    /// settings can not represent the time without rounding.
+    pub fn from_whole_notes(on : WholeNotes, settings : &SongSettings) -> Result<Self, MusicalDataError> {
+        let ticks = on.to_ticks(settings.get_pulses_per_quarter())
+            .ok_or_else(|| MusicalDataError::InexactTicks(on.to_string()))?;
+
+        Ok(SongPosition::new(ticks))
+    }
+
+    pub fn get_ticks_on(&self) -> u64 {
+        self.ticks_on
+    }
+
+    pub fn get_ticks_off(&self) -> Option<u64> {
+        self.ticks_off
+    }
+
+    /// The length in ticks, zero without an offset or for a reversed position.
     pub fn get_length(&self) -> u64 {
-        if self.ticks_off.is_none() {
-            return 0;
+        self.ticks_off.map_or(0, |off| off.saturating_sub(self.ticks_on))
+    }
+
+    /// The offset or the onset for a position without an offset.
+    pub fn get_ticks_end(&self) -> u64 {
+        self.ticks_off.unwrap_or(self.ticks_on)
+    }
+
+    /// A position without an offset or with zero length only marks its onset.
+    pub fn is_point(&self) -> bool {
+        self.get_ticks_end() == self.ticks_on
+    }
+
+    /// Whether the tick lies within the position. The offset itself is not part of the
+    /// position, a point only contains its onset.
+    pub fn contains(&self, tick : u64) -> bool {
+        if self.is_point() {
+            return tick == self.ticks_on;
+        }
+
+        self.ticks_on <= tick && tick < self.get_ticks_end()
+    }
+
+    /// Whether both positions share at least one tick. Positions that only touch, like
+    /// a note ending where the next starts, do not overlap.
+    pub fn overlaps(&self, other : &SongPosition) -> bool {
+        match (self.is_point(), other.is_point()) {
+            (true, true) => self.ticks_on == other.ticks_on,
+            (true, false) => other.contains(self.ticks_on),
+            (false, true) => self.contains(other.ticks_on),
+            (false, false) => self.ticks_on.max(other.ticks_on) < self.get_ticks_end().min(other.get_ticks_end()),
+        }
+    }
+
+    /// The part both positions share, `None` if they do not overlap.
+    pub fn intersection(&self, other : &SongPosition) -> Option<SongPosition> {
+        if !self.overlaps(other) {
+            return None;
+        }
+
+        let on = self.ticks_on.max(other.ticks_on);
+        if self.is_point() || other.is_point() {
+            return Some(SongPosition::new(on));
+        }
+
+        Some(SongPosition::from(on, self.get_ticks_end().min(other.get_ticks_end())))
+    }
+
+    /// The smallest position covering both, including the gap between them.
+    pub fn union(&self, other : &SongPosition) -> SongPosition {
+        let on = self.ticks_on.min(other.ticks_on);
+        if self.ticks_off.is_none() && other.ticks_off.is_none() && self.ticks_on == other.ticks_on {
+            return SongPosition::new(on);
+        }
+
+        SongPosition::from(on, self.get_ticks_end().max(other.get_ticks_end()))
+    }
+
+    /// The empty range between two positions, `None` if they overlap or touch.
+    pub fn gap(&self, other : &SongPosition) -> Option<SongPosition> {
+        let (first, second) = if self.ticks_on <= other.ticks_on { (self, other) } else { (other, self) };
+        if first.get_ticks_end() >= second.ticks_on {
+            return None;
+        }
+
+        Some(SongPosition::from(first.get_ticks_end(), second.ticks_on))
+    }
+
+    /// Move the position by an offset, `None` if it would start before the song.
+    pub fn checked_shift(&self, offset : TickOffset) -> Option<SongPosition> {
+        let shift = |ticks : u64| ticks.checked_add_signed(offset.get_ticks());
+
+        Some(SongPosition {
+            ticks_on : shift(self.ticks_on)?,
+            ticks_off : match self.ticks_off {
+                Some(off) => Some(shift(off)?),
+                None => None,
+            },
+        })
+    }
+
+    /// Create a position from a bar, a beat within the bar and a fraction of the beat,
+    /// all counted from zero.
+    pub fn from_bars_and_beats(bar : u64, beat : u64, fraction : f64, settings : &SongSettings) -> Self {
+        SongPosition::new(settings.bars_and_beats_to_ticks(bar, beat, fraction))
+    }
+
+    /// The start of the position in whole notes from the start of the song.
+    pub fn get_whole_notes_on(&self, settings : &SongSettings) -> WholeNotes {
+        WholeNotes::from_ticks(self.ticks_on, settings.get_pulses_per_quarter())
+    }
+
+    pub fn get_whole_notes_off(&self, settings : &SongSettings) -> Option<WholeNotes> {
+        self.ticks_off.map(|off| WholeNotes::from_ticks(off, settings.get_pulses_per_quarter()))
+    }
+
+    /// The exact length in whole notes, e.g. 1/12 for an eighth triplet.
+    pub fn get_length_in_whole_notes(&self, settings : &SongSettings) -> WholeNotes {
+        WholeNotes::from_ticks(self.get_length(), settings.get_pulses_per_quarter())
+    }
+
+    /// The start of the position in seconds from the start of the song.
+    pub fn get_seconds_on(&self, settings : &SongSettings) -> f64 {
+        settings.ticks_to_seconds(self.ticks_on)
+    }
+
+    pub fn get_seconds_off(&self, settings : &SongSettings) -> Option<f64> {
+        self.ticks_off.map(|off| settings.ticks_to_seconds(off))
+    }
+
+    /// The start of the position as hours, minutes, seconds and milliseconds.
+    pub fn get_as_time_on(&self, settings : &SongSettings) -> (u64, u64, u64, u64) {
+        split_time(self.get_seconds_on(settings))
+    }
+
+    pub fn get_as_time_off(&self, settings : &SongSettings) -> Option<(u64, u64, u64, u64)> {
+        self.get_seconds_off(settings).map(split_time)
+    }
+
+    /// Format the start of the position like "01:23.450", with the hours in front
+    /// ("1:01:23.450") for positions after the first hour.
+    pub fn format_time_on(&self, settings : &SongSettings) -> String {
+        format_time(self.get_as_time_on(settings))
+    }
+
+    pub fn format_time_off(&self, settings : &SongSettings) -> Option<String> {
+        self.get_as_time_off(settings).map(format_time)
+    }
+
+    /// Parse a time like "01:23.450", "1:01:23.450" or "83.45" and create a position
+    /// at the nearest tick.
+    pub fn parse_time(s : &str, settings : &SongSettings) -> Result<Self, MusicalDataError> {
+        let invalid = || MusicalDataError::InvalidTime(String::from(s));
+        let parts : Vec<&str> = s.trim().split(':').collect();
+        if parts.len() > 3 || parts.iter().any(|p| p.is_empty()) {
+            return Err(invalid());
+        }
+
+        let (whole, fraction) = parts[parts.len() - 1].split_once('.').unwrap_or((parts[parts.len() - 1], "0"));
+        let digits = |p : &str| !p.is_empty() && p.chars().all(|c| c.is_ascii_digit());
+        if !digits(whole) || !digits(fraction) || !parts[..parts.len() - 1].iter().all(|p| digits(p)) {
+            return Err(invalid());
+        }
+
+        let mut seconds = 0.0;
+        for (i, part) in parts[..parts.len() - 1].iter().enumerate() {
+            let value : f64 = part.parse().map_err(|_| invalid())?;
+            // Minutes after hours must be below 60.
+            if i > 0 && value >= 60.0 {
+                return Err(invalid());
+            }
+            seconds = seconds * 60.0 + value * 60.0;
         }
+        let whole : f64 = whole.parse().map_err(|_| invalid())?;
+        if parts.len() > 1 && whole >= 60.0 {
+            return Err(invalid());
+        }
+        let fraction : f64 = format!("0.{}", fraction).parse().map_err(|_| invalid())?;
+
+        Ok(SongPosition::from_seconds(seconds + whole + fraction, settings))
+    }
+
+    /// The start of the position as the nearest sample index at the sample rate of the settings.
+    pub fn get_sample_on(&self, settings : &SongSettings) -> u64 {
+        settings.ticks_to_samples(self.ticks_on)
+    }
+
+    pub fn get_sample_off(&self, settings : &SongSettings) -> Option<u64> {
+        self.ticks_off.map(|off| settings.ticks_to_samples(off))
+    }
 
-        return self.ticks_off.unwrap() - self.ticks_on;
+    /// Create a position from a sample index at the nearest tick.
+    pub fn from_sample(sample : u64, settings : &SongSettings) -> Self {
+        SongPosition::new(settings.samples_to_ticks(sample))
+    }
+
+    /// The start of the position as SMPTE timecode at the frame rate of the settings,
+    /// counted from the SMPTE offset of the song.
+    pub fn get_as_timecode_on(&self, settings : &SongSettings) -> Timecode {
+        seconds_to_timecode(self.get_seconds_on(settings), settings)
+    }
+
+    pub fn get_as_timecode_off(&self, settings : &SongSettings) -> Option<Timecode> {
+        self.get_seconds_off(settings).map(|seconds| seconds_to_timecode(seconds, settings))
+    }
+
+    /// Create a position from a SMPTE timecode at the nearest tick. Fails for timecodes
+    /// that do not exist at the frame rate or lie before the SMPTE offset of the song.
+    pub fn from_timecode(timecode : &Timecode, settings : &SongSettings) -> Result<Self, MusicalDataError> {
+        let rate = settings.get_frame_rate();
+        let frames = timecode.to_frames(rate)? - settings.get_smpte_offset().to_frames(rate)?;
+        if frames < 0.0 {
+            return Err(MusicalDataError::InvalidTimecode(timecode.to_string()));
+        }
+
+        Ok(SongPosition::from_seconds(frames / rate.get_frames_per_second(), settings))
     }
 
     pub fn get_as_bars_and_beats_on(&self, settings : &SongSettings) -> (u64, u64, f64) {
@@ -46,29 +284,83 @@ impl SongPosition {
         None
     }
 
+    /// Like `get_as_bars_and_beats_on` but with the exact fraction of the beat, e.g. 1/3
+    /// for the second note of an eighth triplet.
+    pub fn get_as_bars_and_beats_exact_on(&self, settings : &SongSettings) -> (u64, u64, Rational) {
+        settings.ticks_to_bars_and_beats_exact(self.ticks_on)
+    }
+
+    pub fn get_as_bars_and_beats_exact_off(&self, settings : &SongSettings) -> Option<(u64, u64, Rational)> {
+        self.ticks_off.map(|off| settings.ticks_to_bars_and_beats_exact(off))
+    }
+
     fn get_as_bars_and_beats(&self, ticks : u64, settings : &SongSettings) -> (u64, u64, f64) {
-        let beats =  ticks / settings.get_pulses_per_beat();
-        let bar  : u64 = beats / settings.get_time_signature_numerator();
-        let beat : u64 = beats % settings.get_time_signature_numerator();
+        settings.ticks_to_bars_and_beats(ticks)
+    }
+}
 
-        let bar_to_ticks = bar * settings.get_time_signature_numerator() * settings.get_pulses_per_beat();
-        let mut note : f64 = ticks as f64 - (bar_to_ticks + beat * settings.get_pulses_per_beat()) as f64;
-        note = note / settings.get_pulses_per_beat() as f64;
+/// Move a position by an offset. Panics if it would start before the song, use
+/// `checked_shift` to avoid that.
+impl Add<TickOffset> for SongPosition {
+    type Output = SongPosition;
 
-        (bar, beat, note)
+    fn add(self, offset : TickOffset) -> SongPosition {
+        self.checked_shift(offset).expect("position moved before the start of the song")
+    }
+}
+
+impl Sub<TickOffset> for SongPosition {
+    type Output = SongPosition;
+
+    fn sub(self, offset : TickOffset) -> SongPosition {
+        self + -offset
+    }
+}
+
+/// The offset between the onsets of two positions.
+impl Sub for SongPosition {
+    type Output = TickOffset;
+
+    fn sub(self, other : SongPosition) -> TickOffset {
+        TickOffset::new(self.ticks_on as i64 - other.ticks_on as i64)
+    }
+}
+
+/// Split seconds into hours, minutes, seconds and milliseconds, rounded to the nearest millisecond.
+fn split_time(seconds : f64) -> (u64, u64, u64, u64) {
+    let millis = (seconds.max(0.0) * 1000.0).round() as u64;
+
+    (millis / 3_600_000, millis / 60_000 % 60, millis / 1000 % 60, millis % 1000)
+}
+
+fn seconds_to_timecode(seconds : f64, settings : &SongSettings) -> Timecode {
+    let rate = settings.get_frame_rate();
+    // An invalid offset is ignored.
+    let offset = settings.get_smpte_offset().to_frames(rate).unwrap_or_default();
+
+    Timecode::from_frames(offset + seconds * rate.get_frames_per_second(), rate)
+}
+
+fn format_time((hours, minutes, seconds, millis) : (u64, u64, u64, u64)) -> String {
+    if hours > 0 {
+        format!("{}:{:02}:{:02}.{:03}", hours, minutes, seconds, millis)
+    } else {
+        format!("{:02}:{:02}.{:03}", minutes, seconds, millis)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::prelude::SongSettings;
+    use crate::prelude::{FrameRate, SongSettings};
     use test_case::test_case;
     use super::*;
 
     #[test_case(960, 4, 4, 0, 1, 0.0)]
     #[test_case(480, 4, 4, 0, 0, 0.5)]
     #[test_case(720, 4, 4, 0, 0, 0.75)]
-    #[test_case(960, 6, 8, 0, 2, 0.0)]
+    #[test_case(720, 6, 8, 0, 0, 0.5)]
+    #[test_case(1440 + 720, 6, 8, 0, 1, 0.5)]
+    #[test_case(2880 + 1440, 12, 8, 0, 3, 0.0)]
     #[test_case(960, 1, 2, 0, 0, 0.5)]
     #[test_case(2880, 3, 4, 1, 0, 0.0)]
     #[test_case(3360, 3, 4, 1, 0, 0.5)]
@@ -90,6 +382,48 @@ mod tests {
         assert_eq!(note, note2);
     }
 
+    #[test_case(3840, 1, 0, 0.0)]
+    #[test_case(3840 + 480 * 5, 1, 5, 0.0)]
+    #[test_case(3840 + 3360, 2, 0, 0.0)]
+    #[test_case(3840 + 3360 + 1440, 2, 1, 0.5)]
+    fn test_bars_and_beats_with_meter_changes(ticks : u64, bar : u64, beat : u64, note : f64) {
+        let mut song_settings = SongSettings::default();
+        song_settings.add_time_signature_change_at_bar(1, 7, 8);
+        song_settings.add_time_signature_change_at_bar(2, 4, 4);
+
+        let pos = SongPosition::new(ticks);
+        assert_eq!(pos.get_as_bars_and_beats_on(&song_settings), (bar, beat, note));
+        assert_eq!(SongPosition::from_bars_and_beats(bar, beat, note, &song_settings).get_ticks_on(), ticks);
+    }
+
+    #[test_case(960, 320, 4, 4, 0, 0, (1, 3))]
+    #[test_case(96, 3 * 96 + 64, 4, 4, 0, 3, (2, 3))]
+    #[test_case(15, 60 + 10, 7, 8, 1, 2, (1, 3))]
+    #[test_case(15, 105 + 10, 7, 8, 2, 1, (1, 3))]
+    fn test_exact_bars_and_beats(ppq : u64, ticks : u64, numerator : u64, denominator : u64, bar : u64, beat : u64, (n, d) : (i128, i128)) {
+        let mut song_settings = SongSettings::default();
+        song_settings.set_pulses_per_quarter(ppq);
+        song_settings.set_time_signature_numerator(numerator);
+        song_settings.set_time_signature_denominator(denominator);
+        let pos = SongPosition::new(ticks);
+
+        assert_eq!(pos.get_as_bars_and_beats_exact_on(&song_settings), (bar, beat, Rational::new(n, d)));
+        let whole_notes = pos.get_whole_notes_on(&song_settings);
+        assert_eq!(SongPosition::from_whole_notes(whole_notes, &song_settings).unwrap().get_ticks_on(), ticks);
+    }
+
+    #[test]
+    fn test_whole_notes() {
+        let song_settings = SongSettings::default();
+        let pos = SongPosition::from(960, 960 + 320);
+        assert_eq!(pos.get_length_in_whole_notes(&song_settings), WholeNotes::new(1, 12));
+        assert_eq!(pos.get_whole_notes_off(&song_settings), Some(WholeNotes::new(1, 3)));
+
+        let mut coarse = SongSettings::default();
+        coarse.set_pulses_per_quarter(100);
+        assert_eq!(SongPosition::from_whole_notes(WholeNotes::new(1, 12), &coarse).err(), Some(MusicalDataError::InexactTicks(String::from("1/12"))));
+    }
+
     #[test_case(960, 1200, 4, 4, 0, 1, 0.25)]
     #[test_case(  0, 1200, 4, 4, 0, 1, 0.25)]
     fn test_ppq_to_bars_and_beats_off(on : u64, off : u64, numerator : u64, denomiator : u64,  bar : u64, beat : u64, note : f64) {
@@ -107,6 +441,87 @@ mod tests {
         }
     }
 
+    #[test_case(0, 0.0)]
+    #[test_case(1920, 1.0)]
+    #[test_case(3840, 2.0)]
+    #[test_case(5760, 2.5)]
+    #[test_case(7680, 3.0)]
+    fn test_seconds_with_tempo_changes(ticks : u64, seconds : f64) {
+        let mut song_settings = SongSettings::default();
+        song_settings.get_tempo_map_mut().add_change(3840, 240.0);
+
+        let pos = SongPosition::from(0, ticks);
+        assert_eq!(pos.get_seconds_off(&song_settings), Some(seconds));
+        assert_eq!(SongPosition::from_seconds(seconds, &song_settings).get_ticks_on(), ticks);
+    }
+
+    #[test_case(0, (0, 0, 0, 0), "00:00.000")]
+    #[test_case(960, (0, 0, 0, 500), "00:00.500")]
+    #[test_case(96_000 + 960 * 3 / 2, (0, 0, 50, 750), "00:50.750")]
+    #[test_case(960 * 2 * 83 + 864, (0, 1, 23, 450), "01:23.450")]
+    #[test_case(960 * 2 * 3723, (1, 2, 3, 0), "1:02:03.000")]
+    fn test_time(ticks : u64, time : (u64, u64, u64, u64), text : &str) {
+        let song_settings = SongSettings::default();
+        let pos = SongPosition::new(ticks);
+
+        assert_eq!(pos.get_as_time_on(&song_settings), time);
+        assert_eq!(pos.format_time_on(&song_settings), text);
+        assert_eq!(SongPosition::parse_time(text, &song_settings).unwrap().get_ticks_on(), ticks);
+    }
+
+    #[test]
+    fn test_time_with_tempo_changes() {
+        let mut song_settings = SongSettings::default();
+        song_settings.get_tempo_map_mut().add_change(3840, 60.0);
+        let pos = SongPosition::from(0, 3840 + 960);
+
+        assert_eq!(pos.format_time_off(&song_settings), Some(String::from("00:03.000")));
+        assert_eq!(SongPosition::parse_time("3", &song_settings).unwrap().get_ticks_on(), 4800);
+        assert_eq!(SongPosition::parse_time("0:2.5", &song_settings).unwrap().get_ticks_on(), 3840 + 480);
+    }
+
+    #[test_case("")]
+    #[test_case("1:2:3:4")]
+    #[test_case("01:60.000")]
+    #[test_case("1:60:00")]
+    #[test_case("a:01")]
+    #[test_case("01:-1")]
+    #[test_case("01:02.")]
+    fn test_invalid_time(text : &str) {
+        assert_eq!(SongPosition::parse_time(text, &SongSettings::default()).err(), Some(MusicalDataError::InvalidTime(String::from(text))));
+    }
+
+    #[test_case(0, 0, "01:00:00:00")]
+    #[test_case(0, 1920, "01:00:01:00")]
+    #[test_case(1, 1920, "01:00:01:00")]
+    #[test_case(1, 960, "01:00:00:12.50")]
+    #[test_case(3, 1920 * 61, "01:01:01:00")]
+    fn test_timecode(frame_type : i64, ticks : u64, timecode : &str) {
+        let mut song_settings = SongSettings::default();
+        song_settings.set_frame_type(frame_type);
+        song_settings.set_smpte_offset(Timecode::new(1, 0, 0, 0));
+        let pos = SongPosition::new(ticks);
+
+        assert_eq!(pos.get_as_timecode_on(&song_settings).to_string(), timecode);
+        assert_eq!(SongPosition::from_timecode(&timecode.parse().unwrap(), &song_settings).unwrap().get_ticks_on(), ticks);
+    }
+
+    #[test]
+    fn test_drop_frame_timecode() {
+        let mut song_settings = SongSettings::default();
+        song_settings.set_frame_rate(FrameRate::Fps2997DropFrame);
+        // One minute of timecode is 1798 real frames at 29.97 fps.
+        let seconds = 1800.0 * 1001.0 / 30000.0;
+        let pos = SongPosition::from_seconds(seconds, &song_settings);
+
+        assert_eq!(pos.get_as_timecode_on(&song_settings), Timecode::new(0, 1, 0, 2));
+        assert_eq!(SongPosition::from_timecode(&Timecode::new(0, 1, 0, 2), &song_settings).unwrap().get_ticks_on(), pos.get_ticks_on());
+        assert!(SongPosition::from_timecode(&Timecode::new(0, 1, 0, 0), &song_settings).is_err());
+
+        song_settings.set_smpte_offset(Timecode::new(0, 10, 0, 0));
+        assert!(SongPosition::from_timecode(&Timecode::new(0, 9, 0, 2), &song_settings).is_err());
+    }
+
     #[test_case(0, None, 0)]
     #[test_case(960, None, 0)]
     #[test_case(960, Some(961), 1)]
@@ -122,14 +537,62 @@ mod tests {
         assert_eq!(subject.get_length(), length);
     }
 
-    #[test_case(960, Some(959), -1)]
-    #[should_panic]
-    fn test_invalid_matrices_multiplication(on : u64, off : Option<u64>, length : i64) {
-        let subject = SongPosition {
-            ticks_on : on,
-            ticks_off : off
-        };
+    #[test]
+    fn test_checked_from() {
+        assert_eq!(SongPosition::checked_from(960, 959), Err(MusicalDataError::ReversedPosition(960, 959)));
+        assert_eq!(SongPosition::checked_from(960, 960), Ok(SongPosition::from(960, 960)));
+    }
+
+    #[test]
+    fn test_ordering() {
+        let mut positions = vec![SongPosition::from(960, 1920), SongPosition::new(960), SongPosition::from(0, 3840), SongPosition::from(960, 1440)];
+        positions.sort();
+
+        assert_eq!(positions, vec![SongPosition::from(0, 3840), SongPosition::new(960), SongPosition::from(960, 1440), SongPosition::from(960, 1920)]);
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let pos = SongPosition::from(960, 1920);
+
+        assert_eq!(pos + TickOffset::new(480), SongPosition::from(1440, 2400));
+        assert_eq!(pos - TickOffset::new(960), SongPosition::from(0, 960));
+        assert_eq!(SongPosition::new(480) - pos, TickOffset::new(-480));
+        assert_eq!(pos.checked_shift(TickOffset::new(-961)), None);
+    }
+
+    #[test_case((0, Some(960)), (480, Some(1440)), true, Some(SongPosition::from(480, 960)))]
+    #[test_case((0, Some(960)), (960, Some(1440)), false, None)]
+    #[test_case((0, Some(960)), (480, None), true, Some(SongPosition::new(480)))]
+    #[test_case((0, Some(960)), (960, None), false, None)]
+    #[test_case((480, None), (480, Some(480)), true, Some(SongPosition::new(480)))]
+    #[test_case((0, Some(3840)), (960, Some(1920)), true, Some(SongPosition::from(960, 1920)))]
+    fn test_overlap_and_intersection(a : (u64, Option<u64>), b : (u64, Option<u64>), overlaps : bool, intersection : Option<SongPosition>) {
+        let a = SongPosition { ticks_on : a.0, ticks_off : a.1 };
+        let b = SongPosition { ticks_on : b.0, ticks_off : b.1 };
+
+        assert_eq!(a.overlaps(&b), overlaps);
+        assert_eq!(b.overlaps(&a), overlaps);
+        assert_eq!(a.intersection(&b), intersection);
+        assert_eq!(b.intersection(&a), intersection);
+    }
+
+    #[test]
+    fn test_union_and_gap() {
+        let a = SongPosition::from(0, 960);
+        let b = SongPosition::from(1920, 2400);
+
+        assert_eq!(a.union(&b), SongPosition::from(0, 2400));
+        assert_eq!(b.gap(&a), Some(SongPosition::from(960, 1920)));
+        assert_eq!(a.gap(&SongPosition::new(960)), None);
+        assert_eq!(SongPosition::new(480).union(&SongPosition::new(480)), SongPosition::new(480));
+        assert_eq!(SongPosition::new(480).union(&a), a);
+    }
 
-        assert_eq!(subject.get_length(), length.try_into().unwrap());
+    #[test_case(960, 959)]
+    #[test_case(960, 0)]
+    fn test_reversed_length(on : u64, off : u64) {
+        assert_eq!(SongPosition::from(on, off).get_length(), 0);
+        assert!(SongPosition::checked_from(on, off).is_err());
     }
 }
\ No newline at end of file
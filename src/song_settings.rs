@@ -1,14 +1,23 @@
 use core::fmt;
 
+use crate::prelude::{FrameRate, Meter, Positionable, Rational, TempoMap, TimeSignatureMap, Timecode};
+use crate::tempo_map::{self, SampleSegment};
+use crate::time_signature_map::{self, MeterSegment};
+
 #[derive(Clone)]
 pub struct SongSettings
 {
     ppq : u64,          // Pulses per Quarter
     sample_rate : i64,
     frame_type : i64,
+    smpte_offset : Timecode,
     tempo : f64,
+    tempo_map : TempoMap,
     time_signature_numerator : u64,
     time_signature_denominator : u64,
+    /// The beat grouping of the time signature, empty for the usual grouping.
+    beat_grouping : Vec<u64>,
+    time_signature_map : TimeSignatureMap,
     key_signature : String,
     track_count : i32,
     length : f64,
@@ -22,9 +31,13 @@ impl Default for SongSettings {
             ppq: 960, 
             sample_rate: 44100, 
             frame_type: Default::default(), 
+            smpte_offset: Timecode::default(),
             tempo: 120.0, 
+            tempo_map: TempoMap::default(),
             time_signature_numerator: 4, 
             time_signature_denominator: 4, 
+            beat_grouping: Vec::new(),
+            time_signature_map: TimeSignatureMap::default(),
             key_signature: String::from("C Major"), 
             track_count: Default::default(), 
             length: Default::default(), 
@@ -58,19 +71,17 @@ impl SongSettings {
         self.ppq
     }
 
-    /// Get the number of pulses per beat. We need this in order to calculate 
-    /// the nummber of ticks per beat if the denominator of the time signature 
-    /// in not 4. But rather 8, 16 or 2 and 1 etc.
+    /// Get the number of pulses of the first beat of the time signature, i.e. of a quarter
+    /// in 4/4 and of a dotted quarter in 6/8. Rounded down if the PPQ can not represent
+    /// the beat, e.g. for the thirds of 4/3.
     pub fn get_pulses_per_beat(&self) -> u64 {
-        if self.get_time_signature_denominator() != 1 &&
-           self.get_time_signature_denominator() % 2 != 0 {
-            panic!("Invalid time signature settings!");
-        }
-
-        let divisor : f64 = self.get_time_signature_denominator() as f64 / 4.0;
-        // println!("Divisor: {} {}/{}.", divisor, self.get_time_signature_numerator(), self.get_time_signature_denominator());
+        let meter = self.get_meter();
+        (meter.get_unit_length(self.ppq) * Rational::from(meter.get_beat_length(0))).floor() as u64
+    }
 
-        (self.ppq as f64 / divisor) as u64
+    /// Get the number of pulses of a bar of the time signature, rounded down.
+    pub fn get_pulses_per_bar(&self) -> u64 {
+        self.get_meter().get_bar_length(self.ppq).floor() as u64
     }
 
     pub fn get_sample_rate(&self) -> i64 {
@@ -81,10 +92,45 @@ impl SongSettings {
         self.frame_type
     }
 
+    /// Get the frame type as frame rate. Unknown frame types fall back to 24 fps.
+    pub fn get_frame_rate(&self) -> FrameRate {
+        FrameRate::try_from(self.frame_type).unwrap_or_default()
+    }
+
+    /// Get the timecode at the start of the song.
+    pub fn get_smpte_offset(&self) -> Timecode {
+        self.smpte_offset
+    }
+
     pub fn get_tempo(&self) -> f64 {
         self.tempo
     }
 
+    pub fn get_tempo_map(&self) -> &TempoMap {
+        &self.tempo_map
+    }
+
+    pub fn get_tempo_map_mut(&mut self) -> &mut TempoMap {
+        &mut self.tempo_map
+    }
+
+    /// Get the tempo at a tick. The tempo from `get_tempo` applies until the
+    /// first change of the tempo map.
+    pub fn get_tempo_at(&self, tick : u64) -> f64 {
+        self.tempo_map.get_tempo_at(tick, self.tempo)
+    }
+
+    /// Convert a tick to seconds from the start of the song, following all tempo changes.
+    pub fn ticks_to_seconds(&self, ticks : u64) -> f64 {
+        self.tempo_map.ticks_to_seconds(ticks, self.ppq, self.tempo)
+    }
+
+    /// Convert seconds from the start of the song to a possibly fractional tick,
+    /// following all tempo changes.
+    pub fn seconds_to_ticks(&self, seconds : f64) -> f64 {
+        self.tempo_map.seconds_to_ticks(seconds, self.ppq, self.tempo)
+    }
+
     pub fn get_time_signature_numerator(&self) -> u64 {
         self.time_signature_numerator
     }
@@ -93,6 +139,113 @@ impl SongSettings {
         self.time_signature_denominator
     }
 
+    /// Get the time signature with its beat grouping. A grouping that does not add up to
+    /// the numerator falls back to the usual grouping.
+    pub fn get_meter(&self) -> Meter {
+        if !self.beat_grouping.is_empty() && self.beat_grouping.iter().sum::<u64>() == self.time_signature_numerator {
+            return Meter::with_groups(self.beat_grouping.clone(), self.time_signature_denominator);
+        }
+
+        Meter::new(self.time_signature_numerator, self.time_signature_denominator)
+    }
+
+    /// Convert a tick to the exact sample position at the sample rate, following all
+    /// tempo changes. Tempos are taken with three decimal places.
+    pub fn ticks_to_samples_exact(&self, ticks : u64) -> Rational {
+        tempo_map::ticks_to_samples(&self.sample_segments(), ticks)
+    }
+
+    /// Convert a tick to the nearest sample index.
+    pub fn ticks_to_samples(&self, ticks : u64) -> u64 {
+        self.ticks_to_samples_exact(ticks).round().max(0) as u64
+    }
+
+    /// Convert a sample position to the exact tick, following all tempo changes.
+    pub fn samples_to_ticks_exact(&self, samples : Rational) -> Rational {
+        tempo_map::samples_to_ticks(&self.sample_segments(), samples)
+    }
+
+    /// Convert a sample index to the nearest tick.
+    pub fn samples_to_ticks(&self, sample : u64) -> u64 {
+        self.samples_to_ticks_exact(Rational::from(sample)).round().max(0) as u64
+    }
+
+    /// List the events that start in the audio block `[start_sample, start_sample + length)`
+    /// together with their offset in samples from the start of the block. The events must be
+    /// sorted by their start like the chords, notes and lyrics of a `Song`.
+    pub fn events_in_block<'a, T : Positionable>(&self, events : &'a [T], start_sample : u64, length : u64) -> Vec<(&'a T, u64)> {
+        let segments = self.sample_segments();
+        let sample_of = |event : &T| tempo_map::ticks_to_samples(&segments, event.get_position().get_ticks_on()).round().max(0) as u64;
+
+        let first = events.partition_point(|e| sample_of(e) < start_sample);
+        events[first..].iter()
+            .map(|e| (e, sample_of(e)))
+            .take_while(|(_, sample)| *sample < start_sample + length)
+            .map(|(e, sample)| (e, sample - start_sample))
+            .collect()
+    }
+
+    fn sample_segments(&self) -> Vec<SampleSegment> {
+        self.tempo_map.sample_segments(self.ppq, self.tempo, self.sample_rate.max(0) as u64)
+    }
+
+    pub fn get_time_signature_map(&self) -> &TimeSignatureMap {
+        &self.time_signature_map
+    }
+
+    pub fn get_time_signature_map_mut(&mut self) -> &mut TimeSignatureMap {
+        &mut self.time_signature_map
+    }
+
+    /// Get the time signature at a tick as numerator and denominator. The time signature
+    /// from the numerator and denominator getters applies until the first change.
+    pub fn get_time_signature_at(&self, tick : u64) -> (u64, u64) {
+        let changes = self.time_signature_map.get_changes();
+        match changes.partition_point(|c| c.get_tick() <= tick) {
+            0 => (self.time_signature_numerator, self.time_signature_denominator),
+            index => (changes[index - 1].get_numerator(), changes[index - 1].get_denominator()),
+        }
+    }
+
+    /// Get the meter at a tick, see `get_time_signature_at`.
+    pub fn get_meter_at(&self, tick : u64) -> Meter {
+        let changes = self.time_signature_map.get_changes();
+        match changes.partition_point(|c| c.get_tick() <= tick) {
+            0 => self.get_meter(),
+            index => changes[index - 1].get_meter().clone(),
+        }
+    }
+
+    /// Convert a tick to the bar, the beat within the bar and the fraction of the beat,
+    /// all counted from zero and following all time signature changes. Beats follow the
+    /// beat grouping, so 6/8 has two dotted quarter beats.
+    pub fn ticks_to_bars_and_beats(&self, ticks : u64) -> (u64, u64, f64) {
+        let (bar, beat, fraction) = self.ticks_to_bars_and_beats_exact(ticks);
+        (bar, beat, fraction.to_f64())
+    }
+
+    /// Convert a tick to the bar, the beat within the bar and the exact fraction of the beat.
+    pub fn ticks_to_bars_and_beats_exact(&self, ticks : u64) -> (u64, u64, Rational) {
+        time_signature_map::ticks_to_bars_and_beats(&self.meter_segments(), Rational::from(ticks))
+    }
+
+    /// Convert a bar, a beat within the bar and a fraction of the beat to the nearest tick.
+    pub fn bars_and_beats_to_ticks(&self, bar : u64, beat : u64, fraction : f64) -> u64 {
+        // Fractions of a beat are taken with the precision of a tick.
+        let fraction = Rational::new((fraction * self.ppq as f64 * 4.0).round() as i128, self.ppq.max(1) as i128 * 4);
+        self.bars_and_beats_to_ticks_exact(bar, beat, fraction).round().max(0) as u64
+    }
+
+    /// Convert a bar, a beat within the bar and an exact fraction of the beat to a
+    /// possibly fractional tick.
+    pub fn bars_and_beats_to_ticks_exact(&self, bar : u64, beat : u64, fraction : Rational) -> Rational {
+        time_signature_map::bars_and_beats_to_ticks(&self.meter_segments(), bar, beat, fraction)
+    }
+
+    pub(crate) fn meter_segments(&self) -> Vec<MeterSegment> {
+        self.time_signature_map.segments(self.ppq, &self.get_meter())
+    }
+
     pub fn get_key_signature(&self) -> &String {
         &self.key_signature
     }
@@ -114,6 +267,8 @@ impl SongSettings {
     }
 
     // Setters
+    /// Set the PPQ without touching any position. Use `Song::rescale_ppq` to keep the
+    /// positions of a song at their musical time.
     pub fn set_pulses_per_quarter(&mut self, value : u64) {
         self.ppq = value
     }
@@ -126,10 +281,22 @@ impl SongSettings {
         self.frame_type = value;
     }
 
+    pub fn set_frame_rate(&mut self, value: FrameRate) {
+        self.frame_type = value.into();
+    }
+
+    pub fn set_smpte_offset(&mut self, value: Timecode) {
+        self.smpte_offset = value;
+    }
+
     pub fn set_tempo(&mut self, value: f64) {
         self.tempo = value;
     }
 
+    pub fn set_tempo_map(&mut self, value: TempoMap) {
+        self.tempo_map = value;
+    }
+
     pub fn set_time_signature_numerator(&mut self, value: u64) {
         self.time_signature_numerator = value;
     }
@@ -138,6 +305,30 @@ impl SongSettings {
         self.time_signature_denominator = value;
     }
 
+    /// Set the time signature together with its beat grouping.
+    pub fn set_meter(&mut self, value: Meter) {
+        self.time_signature_numerator = value.get_numerator();
+        self.time_signature_denominator = value.get_denominator();
+        self.beat_grouping = value.get_groups().to_vec();
+    }
+
+    pub fn set_time_signature_map(&mut self, value: TimeSignatureMap) {
+        self.time_signature_map = value;
+    }
+
+    /// Add a time signature change at the start of a bar. The bar is counted with the
+    /// time signatures before it.
+    pub fn add_time_signature_change_at_bar(&mut self, bar : u64, numerator : u64, denominator : u64) {
+        let tick = self.bars_and_beats_to_ticks(bar, 0, 0.0);
+        self.time_signature_map.add_change(tick, numerator, denominator);
+    }
+
+    /// Add a change to a meter with its own beat grouping at the start of a bar.
+    pub fn add_meter_change_at_bar(&mut self, bar : u64, meter : Meter) {
+        let tick = self.bars_and_beats_to_ticks(bar, 0, 0.0);
+        self.time_signature_map.add_meter_change(tick, meter);
+    }
+
     pub fn set_key_signature(&mut self, value: String) {
         self.key_signature = value;
     }
@@ -162,6 +353,76 @@ impl SongSettings {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::prelude::SongNote;
+
+    #[test]
+    fn test_samples_without_drift() {
+        let mut song_settings = SongSettings::default();
+        song_settings.set_sample_rate(48000);
+        song_settings.set_tempo(90.0);
+
+        // A tick lasts exactly 100/3 samples, ten minutes are 864000 ticks.
+        assert_eq!(song_settings.ticks_to_samples_exact(1), Rational::new(100, 3));
+        assert_eq!(song_settings.ticks_to_samples(864_000), 28_800_000);
+        assert_eq!(song_settings.samples_to_ticks(28_800_000), 864_000);
+        assert_eq!(song_settings.ticks_to_samples(864_001), 28_800_033);
+
+        song_settings.get_tempo_map_mut().add_change(864_000, 120.0);
+        assert_eq!(song_settings.ticks_to_samples(864_000 + 960 * 120), 28_800_000 + 48000 * 60);
+    }
+
+    #[test]
+    fn test_samples_of_zero_settings() {
+        let mut song_settings = SongSettings::default();
+        song_settings.set_pulses_per_quarter(0);
+        song_settings.set_tempo(0.0);
+        assert!(song_settings.ticks_to_samples(960) > 0);
+        assert_eq!(song_settings.samples_to_ticks(song_settings.ticks_to_samples(960)), 960);
+    }
+
+    #[test]
+    fn test_events_in_block() {
+        let song_settings = SongSettings::default();
+        let notes = [SongNote::new(0, 960, 60), SongNote::new(960, 1920, 62), SongNote::new(1920, 2880, 64)];
+
+        let block = song_settings.events_in_block(&notes, 22000, 100);
+        assert_eq!(block.len(), 1);
+        assert_eq!(block[0].0.get_pitch(), 62);
+        assert_eq!(block[0].1, 50);
+
+        assert_eq!(song_settings.events_in_block(&notes, 0, 22050).len(), 1);
+        assert_eq!(song_settings.events_in_block(&notes, 0, 44101).len(), 3);
+        assert!(song_settings.events_in_block(&notes, 50000, 1024).is_empty());
+    }
+
+    #[test]
+    fn test_compound_and_additive_meters() {
+        let mut song_settings = SongSettings::default();
+        song_settings.set_time_signature_numerator(6);
+        song_settings.set_time_signature_denominator(8);
+        assert_eq!(song_settings.get_pulses_per_beat(), 1440);
+        assert_eq!(song_settings.get_pulses_per_bar(), 2880);
+        assert_eq!(song_settings.ticks_to_bars_and_beats(1440 + 480), (0, 1, 1.0 / 3.0));
+
+        song_settings.set_meter("2+2+3/8".parse().unwrap());
+        assert_eq!(song_settings.get_time_signature_numerator(), 7);
+        assert_eq!(song_settings.ticks_to_bars_and_beats_exact(2400), (0, 2, Rational::new(1, 3)));
+        assert_eq!(song_settings.bars_and_beats_to_ticks(1, 2, 0.0), 3360 + 1920);
+
+        // Changing the numerator drops the grouping that no longer fits.
+        song_settings.set_time_signature_numerator(9);
+        assert_eq!(song_settings.get_meter(), Meter::new(9, 8));
+        song_settings.add_meter_change_at_bar(1, Meter::with_groups(vec![3, 2], 8));
+        assert_eq!(song_settings.get_meter_at(4320).get_groups(), &[3, 2]);
+    }
+
+    #[test]
+    fn test_odd_denominator() {
+        let mut song_settings = SongSettings::default();
+        song_settings.set_time_signature_denominator(3);
+        assert_eq!(song_settings.get_pulses_per_beat(), 1280);
+        assert_eq!(song_settings.ticks_to_bars_and_beats(1280 * 5), (1, 1, 0.0));
+    }
 
     #[test]
     fn test_ppq_getter_setter() {
@@ -0,0 +1,499 @@
+use crate::prelude::Rational;
+
+/// Tempos are converted to fractions with this many decimal places.
+const TEMPO_PRECISION : u32 = 3;
+
+/// The slowest tempo, the smallest step at `TEMPO_PRECISION`. Tempos of zero or below
+/// would stop the song.
+const MIN_TEMPO : f64 = 0.001;
+
+/// Sample positions within tempo ramps are taken with this many decimal places.
+const RAMP_PRECISION : u32 = 6;
+
+/// The start of every tempo segment is kept in this fraction of a sample. Summing exact
+/// fractions over many tempo changes would grow the denominators until they overflow,
+/// rounding to a millionth of a sample keeps them bounded without audible drift.
+const SAMPLE_DENOMINATOR : i128 = 1_000_000;
+
+/// How the tempo moves from a tempo change to the next one.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum TempoCurve {
+    /// The tempo stays constant until the next change.
+    #[default]
+    Step,
+    /// The tempo changes by the same amount every tick.
+    Linear,
+    /// The tempo changes by the same factor every tick, which sounds even for large changes.
+    Exponential,
+}
+
+/// A tempo change in quarter notes per minute that takes effect at a tick.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TempoChange {
+    tick : u64,
+    tempo : f64,
+    curve : TempoCurve,
+}
+
+impl TempoChange {
+    pub fn new(tick : u64, tempo : f64) -> Self {
+        TempoChange { tick, tempo, curve : TempoCurve::Step }
+    }
+
+    /// Create a change that ramps to the tempo of the next change along a curve.
+    pub fn with_curve(tick : u64, tempo : f64, curve : TempoCurve) -> Self {
+        TempoChange { tick, tempo, curve }
+    }
+
+    pub fn get_tick(&self) -> u64 {
+        self.tick
+    }
+
+    pub fn get_tempo(&self) -> f64 {
+        self.tempo
+    }
+
+    pub fn get_curve(&self) -> TempoCurve {
+        self.curve
+    }
+}
+
+/// The tempo changes of a song ordered by tick. Before the first change the song
+/// plays at the tempo of the `SongSettings`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TempoMap {
+    changes : Vec<TempoChange>,
+}
+
+impl TempoMap {
+    pub fn new() -> Self {
+        TempoMap::default()
+    }
+
+    pub fn get_changes(&self) -> &[TempoChange] {
+        &self.changes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Add a tempo change. A change at the same tick is replaced. Tempos below 0.001
+    /// are raised to it.
+    pub fn add_change(&mut self, tick : u64, tempo : f64) {
+        self.add_change_with_curve(tick, tempo, TempoCurve::Step);
+    }
+
+    /// Add a tempo change that ramps to the tempo of the next change along a curve.
+    /// A change at the same tick is replaced. Tempos below 0.001 are raised to it.
+    pub fn add_change_with_curve(&mut self, tick : u64, tempo : f64, curve : TempoCurve) {
+        let change = TempoChange::with_curve(tick, tempo.max(MIN_TEMPO), curve);
+        let index = self.changes.partition_point(|c| c.tick < tick);
+        match self.changes.get_mut(index) {
+            Some(c) if c.tick == tick => *c = change,
+            _ => self.changes.insert(index, change),
+        }
+    }
+
+    /// Add an accelerando or ritardando from `start_tempo` at `start_tick` to `end_tempo`
+    /// at `end_tick`, where the tempo stays until the next change.
+    pub fn add_ramp(&mut self, start_tick : u64, start_tempo : f64, end_tick : u64, end_tempo : f64, curve : TempoCurve) {
+        self.add_change_with_curve(start_tick, start_tempo, curve);
+        self.add_change(end_tick, end_tempo);
+    }
+
+    /// Remove the tempo change at a tick and return it.
+    pub fn remove_change(&mut self, tick : u64) -> Option<TempoChange> {
+        let index = self.changes.iter().position(|c| c.tick == tick)?;
+        Some(self.changes.remove(index))
+    }
+
+    /// The tempo at a tick. A change applies from its own tick on, within a ramp the
+    /// tempo follows its curve.
+    pub fn get_tempo_at(&self, tick : u64, initial_tempo : f64) -> f64 {
+        let segments = self.segments(1, initial_tempo);
+        let segment = segments[segments.partition_point(|s| s.start_tick <= tick).max(1) - 1];
+
+        segment.tempo_after((tick - segment.start_tick) as f64)
+    }
+
+    /// Replace all ramps by a tempo change every `resolution` ticks, for formats without
+    /// tempo curves. Every step gets the tempo that keeps its duration, so the song keeps
+    /// its length. The exporters pass the PPQ, which gives a step on every quarter note.
+    pub fn to_steps(&self, resolution : u64) -> TempoMap {
+        let resolution = resolution.max(1);
+        let mut map = TempoMap::new();
+        // The initial tempo is a step and does not matter for the changes.
+        for segment in self.segments(1, 0.0).iter().filter(|s| s.change) {
+            let Some((length, _)) = segment.ramp() else {
+                map.add_change(segment.start_tick, segment.tempo);
+                continue;
+            };
+
+            let mut offset = 0;
+            while (offset as f64) < length {
+                let next = (offset + resolution).min(length as u64);
+                let seconds = segment.seconds_after(next as f64, 1) - segment.seconds_after(offset as f64, 1);
+                let tempo = Rational::from_decimal((next - offset) as f64 * 60.0 / seconds, TEMPO_PRECISION).to_f64();
+                map.add_change(segment.start_tick + offset, tempo);
+                offset = next;
+            }
+        }
+
+        map
+    }
+
+    /// The sections from one tempo change to the next.
+    fn segments(&self, ppq : u64, initial_tempo : f64) -> Vec<TimeSegment> {
+        let mut segments = vec![TimeSegment {
+            start_tick : 0,
+            start_seconds : 0.0,
+            tempo : initial_tempo,
+            curve : TempoCurve::Step,
+            end : None,
+            change : false,
+        }];
+        for (i, change) in self.changes.iter().enumerate() {
+            let last = segments[segments.len() - 1];
+            let start_seconds = if change.tick == last.start_tick {
+                segments.pop();
+                last.start_seconds
+            } else {
+                last.start_seconds + last.seconds_after((change.tick - last.start_tick) as f64, ppq)
+            };
+            segments.push(TimeSegment {
+                start_tick : change.tick,
+                start_seconds,
+                tempo : change.tempo,
+                curve : change.curve,
+                end : self.changes.get(i + 1).map(|next| (next.tick, next.tempo)),
+                change : true,
+            });
+        }
+
+        segments
+    }
+
+    /// Convert a tick to seconds from the start of the song.
+    pub(crate) fn ticks_to_seconds(&self, ticks : u64, ppq : u64, initial_tempo : f64) -> f64 {
+        let segments = self.segments(ppq, initial_tempo);
+        let segment = segments[segments.partition_point(|s| s.start_tick <= ticks) - 1];
+
+        segment.start_seconds + segment.seconds_after((ticks - segment.start_tick) as f64, ppq)
+    }
+
+    /// Convert seconds from the start of the song to a possibly fractional tick.
+    pub(crate) fn seconds_to_ticks(&self, seconds : f64, ppq : u64, initial_tempo : f64) -> f64 {
+        let seconds = seconds.max(0.0);
+        let segments = self.segments(ppq, initial_tempo);
+        let segment = segments[segments.partition_point(|s| s.start_seconds <= seconds).max(1) - 1];
+
+        segment.start_tick as f64 + segment.ticks_after(seconds - segment.start_seconds, ppq)
+    }
+}
+
+/// A section of the song from a tempo change to the next one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct TimeSegment {
+    start_tick : u64,
+    start_seconds : f64,
+    tempo : f64,
+    curve : TempoCurve,
+    /// The tick and tempo of the next change.
+    end : Option<(u64, f64)>,
+    /// False for the initial tempo of the song.
+    change : bool,
+}
+
+impl TimeSegment {
+    /// The length in ticks and the end tempo if the tempo changes within the segment.
+    fn ramp(&self) -> Option<(f64, f64)> {
+        let (end_tick, end_tempo) = self.end?;
+        let length = (end_tick - self.start_tick) as f64;
+        if self.curve == TempoCurve::Step || end_tempo == self.tempo || self.tempo <= 0.0 || end_tempo <= 0.0 || length == 0.0 {
+            return None;
+        }
+
+        Some((length, end_tempo))
+    }
+
+    fn tempo_after(&self, ticks : f64) -> f64 {
+        match self.ramp() {
+            None => self.tempo,
+            Some((length, end_tempo)) if self.curve == TempoCurve::Linear => self.tempo + (end_tempo - self.tempo) * ticks / length,
+            Some((length, end_tempo)) => self.tempo * (end_tempo / self.tempo).powf(ticks / length),
+        }
+    }
+
+    /// The seconds from the start of the segment to a tick offset, integrated over the ramp.
+    fn seconds_after(&self, ticks : f64, ppq : u64) -> f64 {
+        let ppq = ppq as f64;
+        match self.ramp() {
+            None => 60.0 * ticks / (self.tempo * ppq),
+            Some((length, end_tempo)) if self.curve == TempoCurve::Linear => {
+                let slope = (end_tempo - self.tempo) / length;
+                60.0 / (ppq * slope) * ((self.tempo + slope * ticks) / self.tempo).ln()
+            },
+            Some((length, end_tempo)) => {
+                let rate = (end_tempo / self.tempo).ln() / length;
+                60.0 / (ppq * self.tempo * rate) * (1.0 - (-rate * ticks).exp())
+            },
+        }
+    }
+
+    /// The tick offset reached after some seconds from the start of the segment.
+    fn ticks_after(&self, seconds : f64, ppq : u64) -> f64 {
+        let ppq = ppq as f64;
+        match self.ramp() {
+            None => seconds * self.tempo * ppq / 60.0,
+            Some((length, end_tempo)) if self.curve == TempoCurve::Linear => {
+                let slope = (end_tempo - self.tempo) / length;
+                self.tempo / slope * ((seconds * ppq * slope / 60.0).exp() - 1.0)
+            },
+            Some((length, end_tempo)) => {
+                let rate = (end_tempo / self.tempo).ln() / length;
+                -(1.0 - seconds * ppq * self.tempo * rate / 60.0).ln() / rate
+            },
+        }
+    }
+}
+
+/// A section with a constant tempo or a tempo ramp for exact sample calculations.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct SampleSegment {
+    pub start_tick : u64,
+    pub start_sample : Rational,
+    pub samples_per_tick : Rational,
+    /// The tempo ramp with the PPQ and sample rate. Samples within ramps are integrated
+    /// in floating point and taken with `RAMP_PRECISION` decimal places.
+    pub ramp : Option<(TimeSegment, u64, u64)>,
+}
+
+impl SampleSegment {
+    fn samples_after(&self, ticks : u64) -> Rational {
+        match self.ramp {
+            Some((segment, ppq, sample_rate)) => Rational::from_decimal(segment.seconds_after(ticks as f64, ppq) * sample_rate as f64, RAMP_PRECISION),
+            None => self.samples_per_tick * Rational::from(ticks),
+        }
+    }
+}
+
+impl TempoMap {
+    /// The sections between tempo changes, with their start in exact samples.
+    pub(crate) fn sample_segments(&self, ppq : u64, initial_tempo : f64, sample_rate : u64) -> Vec<SampleSegment> {
+        // Settings of zero would divide by zero, they are taken as the smallest valid value.
+        let (ppq, initial_tempo, sample_rate) = (ppq.max(1), initial_tempo.max(MIN_TEMPO), sample_rate.max(1));
+        let samples_per_tick = |tempo : f64| {
+            Rational::from(60 * sample_rate) / (Rational::from_decimal(tempo, TEMPO_PRECISION) * Rational::from(ppq))
+        };
+
+        let mut segments : Vec<SampleSegment> = Vec::new();
+        for segment in self.segments(ppq, initial_tempo) {
+            let start_sample = match segments.last() {
+                Some(last) => (last.start_sample + last.samples_after(segment.start_tick - last.start_tick)).limit_denominator(SAMPLE_DENOMINATOR),
+                None => Rational::zero(),
+            };
+            segments.push(SampleSegment {
+                start_tick : segment.start_tick,
+                start_sample,
+                samples_per_tick : samples_per_tick(segment.tempo),
+                ramp : segment.ramp().map(|_| (segment, ppq, sample_rate)),
+            });
+        }
+
+        segments
+    }
+}
+
+/// Convert a tick to an exact sample position.
+pub(crate) fn ticks_to_samples(segments : &[SampleSegment], ticks : u64) -> Rational {
+    let segment = segments[segments.partition_point(|s| s.start_tick <= ticks).max(1) - 1];
+
+    segment.start_sample + segment.samples_after(ticks - segment.start_tick)
+}
+
+/// Convert a sample position to an exact tick.
+pub(crate) fn samples_to_ticks(segments : &[SampleSegment], samples : Rational) -> Rational {
+    let samples = samples.max(Rational::zero());
+    let segment = segments[segments.partition_point(|s| s.start_sample <= samples).max(1) - 1];
+    match segment.ramp {
+        Some((ramp, ppq, sample_rate)) => {
+            let seconds = (samples - segment.start_sample).to_f64() / sample_rate as f64;
+            Rational::from(segment.start_tick) + Rational::from_decimal(ramp.ticks_after(seconds, ppq), RAMP_PRECISION)
+        },
+        None => Rational::from(segment.start_tick) + (samples - segment.start_sample) / segment.samples_per_tick,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    fn map() -> TempoMap {
+        // 120 bpm for one bar, 60 bpm for one bar, then 240 bpm.
+        let mut map = TempoMap::new();
+        map.add_change(3840, 60.0);
+        map.add_change(7680, 240.0);
+        map
+    }
+
+    #[test]
+    fn test_add_and_remove_changes() {
+        let mut map = map();
+        map.add_change(0, 100.0);
+        map.add_change(3840, 90.0);
+        assert_eq!(map.get_changes(), &[TempoChange::new(0, 100.0), TempoChange::new(3840, 90.0), TempoChange::new(7680, 240.0)]);
+
+        assert_eq!(map.remove_change(0), Some(TempoChange::new(0, 100.0)));
+        assert_eq!(map.remove_change(1), None);
+        assert_eq!(map.get_changes().len(), 2);
+    }
+
+    #[test_case(0, 120.0, 44100)]
+    #[test_case(960, 0.0, 44100)]
+    #[test_case(960, -120.0, 44100)]
+    #[test_case(960, 120.0, 0)]
+    fn test_sample_segments_of_invalid_settings(ppq : u64, tempo : f64, sample_rate : u64) {
+        let segments = map().sample_segments(ppq, tempo, sample_rate);
+        assert!(segments.iter().all(|s| s.samples_per_tick > Rational::zero()));
+        assert_eq!(samples_to_ticks(&segments, ticks_to_samples(&segments, 1920)), Rational::from(1920u64));
+    }
+
+    #[test]
+    fn test_tempo_at_least_minimum() {
+        let mut map = TempoMap::new();
+        map.add_change(960, 0.0);
+        map.add_change(1920, -20.0);
+        assert!(map.get_changes().iter().all(|c| c.get_tempo() == MIN_TEMPO));
+        assert!(map.sample_segments(960, 120.0, 44100).iter().all(|s| s.samples_per_tick > Rational::zero()));
+    }
+
+    #[test_case(0, 120.0)]
+    #[test_case(3839, 120.0)]
+    #[test_case(3840, 60.0)]
+    #[test_case(7680, 240.0)]
+    #[test_case(100000, 240.0)]
+    fn test_get_tempo_at(tick : u64, tempo : f64) {
+        assert_eq!(map().get_tempo_at(tick, 120.0), tempo);
+    }
+
+    #[test_case(0, 0.0)]
+    #[test_case(960, 0.5)]
+    #[test_case(3840, 2.0)]
+    #[test_case(4800, 3.0)]
+    #[test_case(7680, 6.0)]
+    #[test_case(8640, 6.25)]
+    fn test_ticks_to_seconds(ticks : u64, seconds : f64) {
+        let map = map();
+        assert_eq!(map.ticks_to_seconds(ticks, 960, 120.0), seconds);
+        assert_eq!(map.seconds_to_ticks(seconds, 960, 120.0), ticks as f64);
+    }
+
+    #[test]
+    fn test_change_at_the_start() {
+        let mut map = map();
+        map.add_change(0, 60.0);
+        assert_eq!(map.ticks_to_seconds(3840, 960, 120.0), 4.0);
+        assert_eq!(map.seconds_to_ticks(4.0, 960, 120.0), 3840.0);
+        assert_eq!(map.seconds_to_ticks(-1.0, 960, 120.0), 0.0);
+    }
+
+    #[test]
+    fn test_sample_segments() {
+        let segments = map().sample_segments(960, 120.0, 44100);
+        assert_eq!(segments.len(), 3);
+        assert_eq!(segments[1].start_sample, Rational::from(88200u64));
+        assert_eq!(segments[2].start_sample, Rational::from(6 * 44100u64));
+        assert_eq!(ticks_to_samples(&segments, 960), Rational::from(22050u64));
+        assert_eq!(samples_to_ticks(&segments, Rational::from(88200u64)), Rational::from(3840u64));
+        assert_eq!(samples_to_ticks(&segments, Rational::from(6 * 44100u64 + 11025)), Rational::from(8640u64));
+    }
+
+    fn ramp(curve : TempoCurve) -> TempoMap {
+        // One bar from 60 to 120 bpm, then 120 bpm.
+        let mut map = TempoMap::new();
+        map.add_ramp(0, 60.0, 3840, 120.0, curve);
+        map
+    }
+
+    #[test_case(TempoCurve::Linear, 1920, 90.0)]
+    #[test_case(TempoCurve::Exponential, 1920, 60.0 * std::f64::consts::SQRT_2)]
+    #[test_case(TempoCurve::Linear, 3840, 120.0)]
+    #[test_case(TempoCurve::Step, 1920, 60.0)]
+    fn test_tempo_in_ramp(curve : TempoCurve, tick : u64, tempo : f64) {
+        assert!((ramp(curve).get_tempo_at(tick, 100.0) - tempo).abs() < 1e-9);
+    }
+
+    // Linear: 4 ln 2 seconds, exponential: 2 / ln 2 seconds for the ramp.
+    #[test_case(TempoCurve::Linear, 3840, 4.0 * std::f64::consts::LN_2)]
+    #[test_case(TempoCurve::Linear, 3840 + 960, 4.0 * std::f64::consts::LN_2 + 0.5)]
+    #[test_case(TempoCurve::Exponential, 3840, 2.0 / std::f64::consts::LN_2)]
+    #[test_case(TempoCurve::Step, 3840, 4.0)]
+    fn test_seconds_in_ramp(curve : TempoCurve, ticks : u64, seconds : f64) {
+        let map = ramp(curve);
+        assert!((map.ticks_to_seconds(ticks, 960, 100.0) - seconds).abs() < 1e-9);
+        assert!((map.seconds_to_ticks(seconds, 960, 100.0) - ticks as f64).abs() < 1e-6);
+        assert!((map.seconds_to_ticks(map.ticks_to_seconds(1000, 960, 100.0), 960, 100.0) - 1000.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_samples_in_ramp() {
+        let segments = ramp(TempoCurve::Linear).sample_segments(960, 100.0, 48000);
+        let end = Rational::from_decimal(4.0 * std::f64::consts::LN_2 * 48000.0, RAMP_PRECISION);
+        assert_eq!(ticks_to_samples(&segments, 3840), end);
+        assert_eq!(ticks_to_samples(&segments, 3840 + 960), end + Rational::from(24000u64));
+        assert_eq!(samples_to_ticks(&segments, end + Rational::from(24000u64)), Rational::from(3840u64 + 960));
+        assert_eq!(samples_to_ticks(&segments, ticks_to_samples(&segments, 1000)).round(), 1000);
+    }
+
+    #[test]
+    fn test_many_irregular_changes() {
+        let mut map = TempoMap::new();
+        for i in 0..150u64 {
+            map.add_change(i * 977, 60.0 + ((i * 37) % 97) as f64 + (i % 7) as f64 * 0.123);
+        }
+        let segments = map.sample_segments(960, 100.0, 44100);
+        let mut last = Rational::zero();
+        for ticks in (0..150 * 977).step_by(4801) {
+            let samples = ticks_to_samples(&segments, ticks);
+            let expected = map.ticks_to_seconds(ticks, 960, 100.0) * 44100.0;
+            assert!((samples.to_f64() - expected).abs() < 1e-3);
+            assert!(samples >= last);
+            assert_eq!(samples_to_ticks(&segments, samples).round(), ticks as i128);
+            last = samples;
+        }
+    }
+
+    #[test]
+    fn test_samples_of_steps() {
+        let mut map = TempoMap::new();
+        map.add_ramp(0, 73.3, 3 * 3840, 141.7, TempoCurve::Exponential);
+        let steps = map.to_steps(960);
+        let segments = steps.sample_segments(960, 100.0, 48000);
+        for ticks in [0, 1000, 5000, 3 * 3840, 4 * 3840 + 17] {
+            let expected = steps.ticks_to_seconds(ticks, 960, 100.0) * 48000.0;
+            assert!((ticks_to_samples(&segments, ticks).to_f64() - expected).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn test_to_steps() {
+        let map = ramp(TempoCurve::Linear);
+        let steps = map.to_steps(960);
+        assert_eq!(steps.get_changes().len(), 5);
+        assert!(steps.get_changes().iter().all(|c| c.get_curve() == TempoCurve::Step));
+        assert_eq!(steps.get_changes()[4], TempoChange::new(3840, 120.0));
+        // The steps keep the length of the ramp.
+        assert!((steps.ticks_to_seconds(3840, 960, 100.0) - map.ticks_to_seconds(3840, 960, 100.0)).abs() < 1e-4);
+        assert_eq!(map.to_steps(960).to_steps(1), steps);
+    }
+
+    #[test]
+    fn test_empty_map() {
+        let map = TempoMap::new();
+        assert!(map.is_empty());
+        assert_eq!(map.ticks_to_seconds(1920, 480, 60.0), 4.0);
+        assert_eq!(map.seconds_to_ticks(1.0, 480, 60.0), 480.0);
+    }
+}
@@ -0,0 +1,84 @@
+use std::fmt;
+use std::ops::{Add, Neg, Sub};
+
+/// A signed distance in ticks between two song positions, e.g. to shift a position
+/// forward or backward.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TickOffset {
+    ticks : i64,
+}
+
+impl TickOffset {
+    pub fn new(ticks : i64) -> Self {
+        TickOffset { ticks }
+    }
+
+    pub fn get_ticks(&self) -> i64 {
+        self.ticks
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.ticks < 0
+    }
+
+    /// The distance without its direction.
+    pub fn abs(&self) -> u64 {
+        self.ticks.unsigned_abs()
+    }
+}
+
+impl From<i64> for TickOffset {
+    fn from(ticks : i64) -> Self {
+        TickOffset::new(ticks)
+    }
+}
+
+impl Add for TickOffset {
+    type Output = TickOffset;
+
+    fn add(self, other : TickOffset) -> TickOffset {
+        TickOffset { ticks : self.ticks + other.ticks }
+    }
+}
+
+impl Sub for TickOffset {
+    type Output = TickOffset;
+
+    fn sub(self, other : TickOffset) -> TickOffset {
+        TickOffset { ticks : self.ticks - other.ticks }
+    }
+}
+
+impl Neg for TickOffset {
+    type Output = TickOffset;
+
+    fn neg(self) -> TickOffset {
+        TickOffset { ticks : -self.ticks }
+    }
+}
+
+/// Write the offset with its sign like "+480" or "-120".
+impl fmt::Display for TickOffset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:+}", self.ticks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_arithmetic() {
+        let a = TickOffset::new(480);
+        let b = TickOffset::from(-960);
+
+        assert_eq!(a + b, TickOffset::new(-480));
+        assert_eq!(a - b, TickOffset::new(1440));
+        assert_eq!(-b, TickOffset::new(960));
+        assert_eq!((a + b).abs(), 480);
+        assert!(b.is_negative());
+        assert_eq!(b.to_string(), "-960");
+        assert_eq!(a.to_string(), "+480");
+    }
+}
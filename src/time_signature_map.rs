@@ -0,0 +1,244 @@
+use crate::prelude::{Meter, Rational};
+
+/// A change of the time signature that takes effect at a tick. The bar in progress
+/// ends at the change, so a change always starts a new bar.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimeSignatureChange {
+    tick : u64,
+    meter : Meter,
+}
+
+impl TimeSignatureChange {
+    /// Create a change to a time signature with its usual beat grouping.
+    pub fn new(tick : u64, numerator : u64, denominator : u64) -> Self {
+        TimeSignatureChange { tick, meter : Meter::new(numerator, denominator) }
+    }
+
+    pub fn from_meter(tick : u64, meter : Meter) -> Self {
+        TimeSignatureChange { tick, meter }
+    }
+
+    pub fn get_tick(&self) -> u64 {
+        self.tick
+    }
+
+    pub fn get_numerator(&self) -> u64 {
+        self.meter.get_numerator()
+    }
+
+    pub fn get_denominator(&self) -> u64 {
+        self.meter.get_denominator()
+    }
+
+    pub fn get_meter(&self) -> &Meter {
+        &self.meter
+    }
+}
+
+/// A section of a song with a constant time signature.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct MeterSegment {
+    pub start_tick : u64,
+    pub start_bar : u64,
+    pub meter : Meter,
+    /// The exact length of a note of the denominator in ticks, which is fractional for
+    /// e.g. 7/8 at an odd PPQ.
+    pub unit_length : Rational,
+}
+
+impl MeterSegment {
+    pub fn bar_length(&self) -> Rational {
+        self.unit_length * Rational::from(self.meter.get_numerator())
+    }
+
+    /// The start of a beat in ticks from the start of the bar.
+    pub fn beat_offset(&self, beat : u64) -> Rational {
+        self.unit_length * Rational::from(self.meter.get_beat_offset(beat))
+    }
+
+    pub fn beat_length(&self, beat : u64) -> Rational {
+        self.unit_length * Rational::from(self.meter.get_beat_length(beat))
+    }
+}
+
+/// The time signature changes of a song ordered by tick. Before the first change the
+/// song uses the time signature of the `SongSettings`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TimeSignatureMap {
+    changes : Vec<TimeSignatureChange>,
+}
+
+impl TimeSignatureMap {
+    pub fn new() -> Self {
+        TimeSignatureMap::default()
+    }
+
+    pub fn get_changes(&self) -> &[TimeSignatureChange] {
+        &self.changes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Add a time signature change at a tick. A change at the same tick is replaced.
+    pub fn add_change(&mut self, tick : u64, numerator : u64, denominator : u64) {
+        self.add_meter_change(tick, Meter::new(numerator, denominator));
+    }
+
+    /// Add a change to a meter with its own beat grouping, e.g. 2+2+3/8.
+    pub fn add_meter_change(&mut self, tick : u64, meter : Meter) {
+        let change = TimeSignatureChange::from_meter(tick, meter);
+        let index = self.changes.partition_point(|c| c.tick < tick);
+        match self.changes.get_mut(index) {
+            Some(c) if c.tick == tick => *c = change,
+            _ => self.changes.insert(index, change),
+        }
+    }
+
+    /// Remove the time signature change at a tick and return it.
+    pub fn remove_change(&mut self, tick : u64) -> Option<TimeSignatureChange> {
+        let index = self.changes.iter().position(|c| c.tick == tick)?;
+        Some(self.changes.remove(index))
+    }
+
+    /// The sections with a constant time signature. A bar cut short by a change
+    /// still counts as a bar.
+    pub(crate) fn segments(&self, ppq : u64, initial : &Meter) -> Vec<MeterSegment> {
+        let segment = |start_tick, start_bar, meter : &Meter| MeterSegment {
+            start_tick,
+            start_bar,
+            meter : meter.clone(),
+            unit_length : meter.get_unit_length(ppq),
+        };
+
+        let mut segments = vec![segment(0, 0, initial)];
+        for change in &self.changes {
+            let last = segments.pop().unwrap();
+            if change.tick == last.start_tick {
+                segments.push(segment(last.start_tick, last.start_bar, &change.meter));
+            } else {
+                let bars = bar_count(Rational::from(change.tick - last.start_tick), last.bar_length());
+                let next = segment(change.tick, last.start_bar + bars, &change.meter);
+                segments.push(last);
+                segments.push(next);
+            }
+        }
+
+        segments
+    }
+}
+
+/// The number of bars needed for a length, a started bar counts as bar.
+fn bar_count(length : Rational, bar_length : Rational) -> u64 {
+    if bar_length == Rational::zero() {
+        return 0;
+    }
+
+    (length / bar_length).ceil() as u64
+}
+
+/// Convert a tick to the bar, the beat within the bar and the exact fraction of the beat.
+/// Beats follow the beat grouping of the meter, e.g. 6/8 has two beats.
+pub(crate) fn ticks_to_bars_and_beats(segments : &[MeterSegment], ticks : Rational) -> (u64, u64, Rational) {
+    let index = segments.partition_point(|s| Rational::from(s.start_tick) <= ticks).max(1) - 1;
+    let segment = &segments[index];
+    let offset = ticks - Rational::from(segment.start_tick);
+    if segment.unit_length == Rational::zero() || segment.meter.get_numerator() == 0 {
+        return (segment.start_bar, 0, Rational::zero());
+    }
+
+    let bars = (offset / segment.bar_length()).floor();
+    let in_bar = offset - segment.bar_length() * Rational::from(bars);
+    let unit = (in_bar / segment.unit_length).floor() as u64;
+    let (beat, _) = segment.meter.get_beat_of_unit(unit);
+    let fraction = (in_bar - segment.beat_offset(beat)) / segment.beat_length(beat);
+
+    (segment.start_bar + bars as u64, beat, fraction)
+}
+
+/// Convert a bar, a beat within the bar and a fraction of the beat to an exact tick.
+pub(crate) fn bars_and_beats_to_ticks(segments : &[MeterSegment], bar : u64, beat : u64, fraction : Rational) -> Rational {
+    let index = segments.partition_point(|s| s.start_bar <= bar).max(1) - 1;
+    let segment = &segments[index];
+
+    Rational::from(segment.start_tick)
+        + segment.bar_length() * Rational::from(bar - segment.start_bar)
+        + segment.beat_offset(beat)
+        + segment.beat_length(beat) * fraction
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    fn segments() -> Vec<MeterSegment> {
+        // Two bars of 4/4, one bar of 7/8 and 4/4 again.
+        let mut map = TimeSignatureMap::new();
+        map.add_change(7680, 7, 8);
+        map.add_change(7680 + 3360, 4, 4);
+        map.segments(960, &Meter::new(4, 4))
+    }
+
+    #[test]
+    fn test_add_and_remove_changes() {
+        let mut map = TimeSignatureMap::new();
+        map.add_change(3840, 3, 4);
+        map.add_change(0, 6, 8);
+        map.add_change(3840, 5, 4);
+        assert_eq!(map.get_changes(), &[TimeSignatureChange::new(0, 6, 8), TimeSignatureChange::new(3840, 5, 4)]);
+        assert_eq!(map.remove_change(0), Some(TimeSignatureChange::new(0, 6, 8)));
+        assert_eq!(map.remove_change(0), None);
+    }
+
+    #[test_case(0, 0, 0, 0.0)]
+    #[test_case(3840 + 480, 1, 0, 0.5)]
+    #[test_case(7680, 2, 0, 0.0)]
+    #[test_case(7680 + 480 * 6 + 240, 2, 6, 0.5)]
+    #[test_case(11040, 3, 0, 0.0)]
+    #[test_case(11040 + 3840 + 960, 4, 1, 0.0)]
+    fn test_meter_changes(ticks : u64, bar : u64, beat : u64, fraction : f64) {
+        let segments = segments();
+        let fraction = Rational::from_decimal(fraction, 3);
+        assert_eq!(ticks_to_bars_and_beats(&segments, Rational::from(ticks)), (bar, beat, fraction));
+        assert_eq!(bars_and_beats_to_ticks(&segments, bar, beat, fraction), Rational::from(ticks));
+    }
+
+    #[test]
+    fn test_change_inside_a_bar() {
+        // A change after one and a half bars of 4/4 cuts the second bar short.
+        let mut map = TimeSignatureMap::new();
+        map.add_change(5760, 3, 4);
+        let segments = map.segments(960, &Meter::new(4, 4));
+        assert_eq!(ticks_to_bars_and_beats(&segments, Rational::from(4800u64)), (1, 1, Rational::zero()));
+        assert_eq!(ticks_to_bars_and_beats(&segments, Rational::from(5760u64)), (2, 0, Rational::zero()));
+        assert_eq!(bars_and_beats_to_ticks(&segments, 3, 0, Rational::zero()), Rational::from(5760u64 + 2880));
+    }
+
+    #[test_case(1440 + 480, 0, 1, (1, 3))]
+    #[test_case(2880 + 960 + 480, 1, 1, (1, 2))]
+    #[test_case(2880 + 3360 + 1920 + 720, 2, 2, (1, 2))]
+    fn test_compound_and_additive_meters(ticks : u64, bar : u64, beat : u64, (n, d) : (i128, i128)) {
+        // A bar of 6/8, a bar of 2+2+3/8 and 2+2+3/8 again.
+        let mut map = TimeSignatureMap::new();
+        map.add_meter_change(2880, Meter::with_groups(vec![2, 2, 3], 8));
+        let segments = map.segments(960, &Meter::new(6, 8));
+        let fraction = Rational::new(n, d);
+
+        assert_eq!(ticks_to_bars_and_beats(&segments, Rational::from(ticks)), (bar, beat, fraction));
+        assert_eq!(bars_and_beats_to_ticks(&segments, bar, beat, fraction), Rational::from(ticks));
+    }
+
+    #[test]
+    fn test_odd_ppq() {
+        // At a PPQ of 15 an eighth is 7.5 ticks, two bars of 7/8 are 105 ticks.
+        let mut map = TimeSignatureMap::new();
+        map.add_change(105, 4, 4);
+        let segments = map.segments(15, &Meter::new(7, 8));
+        assert_eq!(segments[1].start_bar, 2);
+        assert_eq!(ticks_to_bars_and_beats(&segments, Rational::from(60u64)), (1, 1, Rational::zero()));
+        assert_eq!(ticks_to_bars_and_beats(&segments, Rational::from(10u64)), (0, 1, Rational::new(1, 3)));
+        assert_eq!(bars_and_beats_to_ticks(&segments, 0, 3, Rational::zero()), Rational::new(45, 2));
+    }
+}
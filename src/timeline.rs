@@ -0,0 +1,301 @@
+use std::sync::OnceLock;
+
+use crate::prelude::{Positionable, SongPosition};
+
+/// A collection of positioned items kept ordered by position, with queries by tick
+/// and range.
+///
+/// The items are kept in a sorted vector, so inserting one shifts the items after it.
+/// Build a timeline with `from_vec` or `extend` to add many items at once. Overlap
+/// queries use a segment tree holding the maximum end tick of every range of items,
+/// built on the first query after a change. Only subtrees ending after the start of the
+/// queried range are visited, so a query takes O(log n) per item found, also next to
+/// long items.
+#[derive(Debug, Clone)]
+pub struct Timeline<T : Positionable> {
+    items : Vec<T>,
+    max_ends : OnceLock<Vec<u64>>,
+}
+
+impl<T : Positionable + PartialEq> PartialEq for Timeline<T> {
+    fn eq(&self, other : &Self) -> bool {
+        self.items == other.items
+    }
+}
+
+impl<T : Positionable> Default for Timeline<T> {
+    fn default() -> Self {
+        Timeline::new()
+    }
+}
+
+impl<T : Positionable> Timeline<T> {
+    pub fn new() -> Self {
+        Timeline {
+            items : Vec::new(),
+            max_ends : OnceLock::new(),
+        }
+    }
+
+    /// Create a timeline from unordered items. Items at equal positions keep their order.
+    pub fn from_vec(items : Vec<T>) -> Self {
+        let mut timeline = Timeline { items, max_ends : OnceLock::new() };
+        timeline.sort();
+
+        timeline
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// All items ordered by position.
+    pub fn get_items(&self) -> &[T] {
+        &self.items
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, T> {
+        self.items.iter()
+    }
+
+    pub fn into_vec(self) -> Vec<T> {
+        self.items
+    }
+
+    /// Insert an item after all items at the same position and return its index.
+    pub fn insert(&mut self, item : T) -> usize {
+        let position = *item.get_position();
+        let index = self.items.partition_point(|i| *i.get_position() <= position);
+        self.items.insert(index, item);
+        self.max_ends.take();
+
+        index
+    }
+
+    pub fn remove(&mut self, index : usize) -> T {
+        let item = self.items.remove(index);
+        self.max_ends.take();
+
+        item
+    }
+
+    /// Keep only the items the predicate returns true for.
+    pub fn retain<F : FnMut(&T) -> bool>(&mut self, f : F) {
+        self.items.retain(f);
+        self.max_ends.take();
+    }
+
+    /// Insert many items at once, which is faster than inserting them one by one.
+    pub fn extend<I : IntoIterator<Item = T>>(&mut self, items : I) {
+        self.items.extend(items);
+        self.sort();
+    }
+
+    /// Change the items in place, e.g. to move all of them. The timeline is ordered
+    /// again afterwards.
+    pub fn edit<F : FnOnce(&mut [T])>(&mut self, f : F) {
+        f(&mut self.items);
+        self.sort();
+    }
+
+    /// All items that contain the tick, see `SongPosition::contains`.
+    pub fn get_at(&self, tick : u64) -> Vec<&T> {
+        self.get_overlapping(&SongPosition::new(tick))
+    }
+
+    /// The items starting at or after `start` and before `end`.
+    pub fn get_in_range(&self, start : u64, end : u64) -> &[T] {
+        let from = self.items.partition_point(|i| i.get_position().get_ticks_on() < start);
+        let to = self.items.partition_point(|i| i.get_position().get_ticks_on() < end).max(from);
+
+        &self.items[from..to]
+    }
+
+    /// All items sharing at least one tick with the range, see `SongPosition::overlaps`.
+    pub fn get_overlapping(&self, range : &SongPosition) -> Vec<&T> {
+        let start = range.get_ticks_on();
+        let end = exclusive_end(range);
+        let to = self.items.partition_point(|i| i.get_position().get_ticks_on() < end);
+        let max_ends = self.max_ends.get_or_init(|| build_max_ends(&self.items));
+        let leaves = max_ends.len() / 2;
+
+        // Walk the tree from the left, skipping subtrees that end before the range
+        // or start after it.
+        let mut found = Vec::new();
+        let mut stack = vec![(1, 0, leaves)];
+        while let Some((node, first, last)) = stack.pop() {
+            if first >= to || max_ends[node] <= start {
+                continue;
+            }
+            if last - first == 1 {
+                if self.items[first].get_position().overlaps(range) {
+                    found.push(&self.items[first]);
+                }
+                continue;
+            }
+            let middle = (first + last) / 2;
+            stack.push((2 * node + 1, middle, last));
+            stack.push((2 * node, first, middle));
+        }
+
+        found
+    }
+
+    /// The first item starting after the tick.
+    pub fn get_next(&self, tick : u64) -> Option<&T> {
+        let index = self.items.partition_point(|i| i.get_position().get_ticks_on() <= tick);
+        self.items.get(index)
+    }
+
+    /// The last item starting before the tick.
+    pub fn get_previous(&self, tick : u64) -> Option<&T> {
+        let index = self.items.partition_point(|i| i.get_position().get_ticks_on() < tick);
+        index.checked_sub(1).map(|i| &self.items[i])
+    }
+
+    fn sort(&mut self) {
+        self.items.sort_by_key(|i| *i.get_position());
+        self.max_ends.take();
+    }
+}
+
+/// The segment tree of the end ticks: node 1 is the root, the children of node n are
+/// 2n and 2n + 1 and the leaves hold the exclusive ends of the items.
+fn build_max_ends<T : Positionable>(items : &[T]) -> Vec<u64> {
+    let leaves = items.len().next_power_of_two();
+    let mut max_ends = vec![0; 2 * leaves];
+    for (index, item) in items.iter().enumerate() {
+        max_ends[leaves + index] = exclusive_end(item.get_position());
+    }
+    for node in (1..leaves).rev() {
+        max_ends[node] = max_ends[2 * node].max(max_ends[2 * node + 1]);
+    }
+
+    max_ends
+}
+
+impl<'a, T : Positionable> IntoIterator for &'a Timeline<T> {
+    type Item = &'a T;
+    type IntoIter = std::slice::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.items.iter()
+    }
+}
+
+impl<T : Positionable> FromIterator<T> for Timeline<T> {
+    fn from_iter<I : IntoIterator<Item = T>>(iter : I) -> Self {
+        Timeline::from_vec(iter.into_iter().collect())
+    }
+}
+
+/// The first tick after the position, a point covers its onset tick.
+fn exclusive_end(position : &SongPosition) -> u64 {
+    if position.is_point() {
+        return position.get_ticks_on() + 1;
+    }
+
+    position.get_ticks_end()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prelude::SongNote;
+    use test_case::test_case;
+
+    fn timeline() -> Timeline<SongNote> {
+        Timeline::from_vec(vec![
+            SongNote::new(960, 1920, 62),
+            SongNote::new(0, 3840, 48),
+            SongNote::new(0, 960, 60),
+            SongNote::new(1920, 2880, 64),
+            SongNote::new(2880, 2880, 65),
+        ])
+    }
+
+    fn pitches<'a>(notes : impl IntoIterator<Item = &'a SongNote>) -> Vec<u8> {
+        notes.into_iter().map(|n| n.get_pitch()).collect()
+    }
+
+    #[test]
+    fn test_ordering() {
+        let mut timeline = timeline();
+        assert_eq!(pitches(&timeline), vec![60, 48, 62, 64, 65]);
+
+        assert_eq!(timeline.insert(SongNote::new(960, 1920, 67)), 3);
+        assert_eq!(timeline.remove(2).get_pitch(), 62);
+        assert_eq!(pitches(&timeline), vec![60, 48, 67, 64, 65]);
+    }
+
+    #[test_case(0, vec![60, 48])]
+    #[test_case(960, vec![48, 62])]
+    #[test_case(2880, vec![48, 65])]
+    #[test_case(3840, vec![])]
+    fn test_get_at(tick : u64, expected : Vec<u8>) {
+        assert_eq!(pitches(timeline().get_at(tick)), expected);
+    }
+
+    #[test_case(480, 1440, vec![60, 48, 62])]
+    #[test_case(1920, 2880, vec![48, 64])]
+    #[test_case(3000, 4000, vec![48])]
+    #[test_case(4000, 5000, vec![])]
+    fn test_get_overlapping(on : u64, off : u64, expected : Vec<u8>) {
+        assert_eq!(pitches(timeline().get_overlapping(&SongPosition::from(on, off))), expected);
+    }
+
+    #[test]
+    fn test_range_and_neighbours() {
+        let timeline = timeline();
+
+        assert_eq!(pitches(timeline.get_in_range(960, 2880)), vec![62, 64]);
+        assert_eq!(pitches(timeline.get_in_range(2000, 1000)), vec![]);
+        assert_eq!(timeline.get_next(960).map(|n| n.get_pitch()), Some(64));
+        assert_eq!(timeline.get_next(2880).map(|n| n.get_pitch()), None);
+        assert_eq!(timeline.get_previous(960).map(|n| n.get_pitch()), Some(48));
+        assert_eq!(timeline.get_previous(0).map(|n| n.get_pitch()), None);
+    }
+
+    #[test]
+    fn test_bulk_edits() {
+        let mut timeline = timeline();
+        timeline.edit(|notes| notes.iter_mut().for_each(|n| n.set_pitch(n.get_pitch() + 12)));
+        timeline.retain(|n| n.get_pitch() != 72);
+        timeline.extend(vec![SongNote::new(5000, 6000, 50), SongNote::new(100, 200, 51)]);
+
+        assert_eq!(pitches(&timeline), vec![60, 51, 74, 76, 77, 50]);
+        assert_eq!(pitches(timeline.get_at(150)), vec![60, 51]);
+        assert_eq!(pitches(timeline.get_at(4000)), vec![]);
+    }
+
+    #[test]
+    fn test_many_items() {
+        let timeline : Timeline<SongNote> = (0..100_000u64).rev()
+            .map(|i| SongNote::new(i * 10, i * 10 + 25, (i % 128) as u8))
+            .collect();
+
+        assert_eq!(timeline.len(), 100_000);
+        assert_eq!(timeline.get_at(500_004).len(), 3);
+        assert_eq!(timeline.get_in_range(0, 1000).len(), 100);
+    }
+
+    #[test]
+    fn test_long_item_and_reverse_inserts() {
+        // A note over the whole song must not make every query scan all items before it.
+        let mut timeline = Timeline::new();
+        timeline.insert(SongNote::new(0, 10_000_000, 36));
+        for i in (1..20_000u64).rev() {
+            timeline.insert(SongNote::new(i * 10, i * 10 + 5, 60));
+        }
+        assert_eq!(timeline.len(), 20_000);
+        assert_eq!(timeline.get_items()[1].get_position().get_ticks_on(), 10);
+
+        for i in 0..100_000u64 {
+            assert_eq!(timeline.get_at(i % 20_000 * 10 + 2).len(), 1 + (i % 20_000 > 0) as usize);
+        }
+    }
+}
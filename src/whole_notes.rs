@@ -0,0 +1,123 @@
+use std::fmt;
+use std::ops::{Add, Mul, Sub};
+
+use crate::prelude::Rational;
+
+/// An exact musical time or length in whole notes, e.g. 1/4 for a quarter or 1/12 for
+/// an eighth triplet. Unlike ticks it does not depend on the PPQ of a song.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct WholeNotes {
+    value : Rational,
+}
+
+impl WholeNotes {
+    /// Create a length of `numerator / denominator` whole notes. Panics if the denominator is zero.
+    pub fn new(numerator : i128, denominator : i128) -> Self {
+        WholeNotes { value : Rational::new(numerator, denominator) }
+    }
+
+    pub fn from_rational(value : Rational) -> Self {
+        WholeNotes { value }
+    }
+
+    /// Convert ticks at a PPQ, which is always exact.
+    pub fn from_ticks(ticks : u64, ppq : u64) -> Self {
+        WholeNotes::new(ticks as i128, ppq as i128 * 4)
+    }
+
+    pub fn get_rational(&self) -> Rational {
+        self.value
+    }
+
+    pub fn get_numerator(&self) -> i128 {
+        self.value.get_numerator()
+    }
+
+    pub fn get_denominator(&self) -> i128 {
+        self.value.get_denominator()
+    }
+
+    /// The exact length in ticks, possibly fractional.
+    pub fn to_ticks_exact(&self, ppq : u64) -> Rational {
+        self.value * Rational::from(ppq * 4)
+    }
+
+    /// Convert to ticks at a PPQ. Returns `None` if the PPQ can not represent the
+    /// length exactly, e.g. an eighth triplet at a PPQ of 96 is fine but not at 100.
+    pub fn to_ticks(&self, ppq : u64) -> Option<u64> {
+        let ticks = self.to_ticks_exact(ppq);
+        if !ticks.is_integer() {
+            return None;
+        }
+
+        u64::try_from(ticks.get_numerator()).ok()
+    }
+
+    /// Convert to the nearest tick at a PPQ, negative lengths become zero.
+    pub fn to_ticks_rounded(&self, ppq : u64) -> u64 {
+        self.to_ticks_exact(ppq).round().max(0) as u64
+    }
+}
+
+impl Add for WholeNotes {
+    type Output = WholeNotes;
+
+    fn add(self, other : WholeNotes) -> WholeNotes {
+        WholeNotes { value : self.value + other.value }
+    }
+}
+
+impl Sub for WholeNotes {
+    type Output = WholeNotes;
+
+    fn sub(self, other : WholeNotes) -> WholeNotes {
+        WholeNotes { value : self.value - other.value }
+    }
+}
+
+impl Mul<Rational> for WholeNotes {
+    type Output = WholeNotes;
+
+    fn mul(self, factor : Rational) -> WholeNotes {
+        WholeNotes { value : self.value * factor }
+    }
+}
+
+/// Write the length as fraction of a whole note like "3/8".
+impl fmt::Display for WholeNotes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    #[test_case(1, 4, 960, Some(960))]
+    #[test_case(1, 12, 960, Some(320))]
+    #[test_case(1, 12, 100, None)]
+    #[test_case(3, 16, 96, Some(72))]
+    #[test_case(1, 128, 15, None)]
+    #[test_case(-1, 4, 960, None)]
+    fn test_to_ticks(n : i128, d : i128, ppq : u64, ticks : Option<u64>) {
+        assert_eq!(WholeNotes::new(n, d).to_ticks(ppq), ticks);
+    }
+
+    #[test]
+    fn test_from_ticks() {
+        assert_eq!(WholeNotes::from_ticks(320, 960), WholeNotes::new(1, 12));
+        assert_eq!(WholeNotes::from_ticks(15, 15), WholeNotes::new(1, 4));
+        assert_eq!(WholeNotes::new(1, 128).to_ticks_rounded(15), 0);
+        assert_eq!(WholeNotes::new(1, 12).to_ticks_rounded(100), 33);
+    }
+
+    #[test]
+    fn test_triplets_add_up() {
+        let triplet = WholeNotes::new(1, 12);
+        assert_eq!(triplet + triplet + triplet, WholeNotes::new(1, 4));
+        assert_eq!(WholeNotes::new(1, 2) - triplet * Rational::from(3u64), WholeNotes::new(1, 4));
+        assert_eq!(triplet.to_string(), "1/12");
+    }
+}
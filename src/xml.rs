@@ -0,0 +1,414 @@
+use crate::error::MusicalDataError;
+
+/// A very small xml tree. It only supports what we need to read and write
+/// MusicXML: elements, attributes and text. Comments, processing instructions,
+/// the doctype and whitespace only text are skipped while parsing.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum XmlNode {
+    Element(XmlElement),
+    Text(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub(crate) struct XmlElement {
+    pub name : String,
+    pub attributes : Vec<(String, String)>,
+    pub children : Vec<XmlNode>,
+}
+
+impl XmlElement {
+    pub fn new(name : &str) -> Self {
+        XmlElement {
+            name : String::from(name),
+            ..Default::default()
+        }
+    }
+
+    /// Create an element that only contains text.
+    pub fn with_text(name : &str, text : &str) -> Self {
+        let mut element = XmlElement::new(name);
+        element.children.push(XmlNode::Text(String::from(text)));
+        element
+    }
+
+    pub fn set_attribute(&mut self, name : &str, value : &str) -> &mut Self {
+        self.attributes.push((String::from(name), String::from(value)));
+        self
+    }
+
+    pub fn push(&mut self, child : XmlElement) -> &mut Self {
+        self.children.push(XmlNode::Element(child));
+        self
+    }
+
+    pub fn push_text(&mut self, name : &str, text : &str) -> &mut Self {
+        self.push(XmlElement::with_text(name, text))
+    }
+
+    pub fn attribute(&self, name : &str) -> Option<&str> {
+        self.attributes.iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Iterate over all child elements, skipping text nodes.
+    pub fn elements(&self) -> impl Iterator<Item = &XmlElement> {
+        self.children.iter().filter_map(|c| match c {
+            XmlNode::Element(e) => Some(e),
+            XmlNode::Text(_) => None,
+        })
+    }
+
+    pub fn children_named<'a>(&'a self, name : &'a str) -> impl Iterator<Item = &'a XmlElement> {
+        self.elements().filter(move |e| e.name == name)
+    }
+
+    pub fn child(&self, name : &str) -> Option<&XmlElement> {
+        self.elements().find(|e| e.name == name)
+    }
+
+    pub fn has_child(&self, name : &str) -> bool {
+        self.child(name).is_some()
+    }
+
+    /// The concatenated and trimmed text content of this element.
+    pub fn text(&self) -> String {
+        let mut result = String::new();
+        for c in &self.children {
+            if let XmlNode::Text(t) = c {
+                result.push_str(t);
+            }
+        }
+
+        String::from(result.trim())
+    }
+
+    pub fn child_text(&self, name : &str) -> Option<String> {
+        self.child(name).map(|c| c.text())
+    }
+}
+
+impl XmlElement {
+    fn write(&self, out : &mut String, indent : usize) {
+        out.push_str(&"  ".repeat(indent));
+        out.push('<');
+        out.push_str(&self.name);
+        for (name, value) in &self.attributes {
+            out.push_str(&format!(" {}=\"{}\"", name, escape(value)));
+        }
+
+        if self.children.is_empty() {
+            out.push_str("/>\n");
+            return;
+        }
+
+        out.push('>');
+        if let [XmlNode::Text(text)] = self.children.as_slice() {
+            out.push_str(&escape(text));
+        } else {
+            out.push('\n');
+            for child in &self.children {
+                match child {
+                    XmlNode::Element(e) => e.write(out, indent + 1),
+                    XmlNode::Text(t) => {
+                        out.push_str(&"  ".repeat(indent + 1));
+                        out.push_str(&escape(t));
+                        out.push('\n');
+                    },
+                }
+            }
+            out.push_str(&"  ".repeat(indent));
+        }
+        out.push_str(&format!("</{}>\n", self.name));
+    }
+}
+
+/// Serialize a document with an xml declaration and an optional doctype.
+pub(crate) fn write_document(root : &XmlElement, doctype : Option<&str>) -> String {
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"no\"?>\n");
+    if let Some(doctype) = doctype {
+        out.push_str(doctype);
+        out.push('\n');
+    }
+    root.write(&mut out, 0);
+
+    out
+}
+
+fn escape(s : &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Parse an xml document and return its root element.
+pub(crate) fn parse(input : &str) -> Result<XmlElement, MusicalDataError> {
+    let mut parser = Parser { input, pos : 0 };
+    parser.skip_prolog()?;
+    let root = parser.parse_element()?;
+    parser.skip_misc()?;
+    if parser.pos < input.len() {
+        return Err(parser.error("unexpected content after the root element"));
+    }
+
+    Ok(root)
+}
+
+struct Parser<'a> {
+    input : &'a str,
+    pos : usize,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.input[self.pos..]
+    }
+
+    fn error(&self, msg : &str) -> MusicalDataError {
+        let line = self.input[..self.pos].matches('\n').count() + 1;
+        MusicalDataError::InvalidXml(format!("{} (line {})", msg, line))
+    }
+
+    fn skip_whitespace(&mut self) {
+        let trimmed = self.rest().trim_start();
+        self.pos = self.input.len() - trimmed.len();
+    }
+
+    fn skip_until(&mut self, end : &str) -> Result<(), MusicalDataError> {
+        match self.rest().find(end) {
+            Some(i) => {
+                self.pos += i + end.len();
+                Ok(())
+            },
+            None => Err(self.error(&format!("missing '{}'", end))),
+        }
+    }
+
+    /// Skip comments, processing instructions and whitespace.
+    fn skip_misc(&mut self) -> Result<(), MusicalDataError> {
+        loop {
+            self.skip_whitespace();
+            if self.rest().starts_with("<?") {
+                self.skip_until("?>")?;
+            } else if self.rest().starts_with("<!--") {
+                self.skip_until("-->")?;
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    fn skip_prolog(&mut self) -> Result<(), MusicalDataError> {
+        // Skip a byte order mark if present.
+        if self.rest().starts_with('\u{feff}') {
+            self.pos += '\u{feff}'.len_utf8();
+        }
+
+        loop {
+            self.skip_misc()?;
+            if self.rest().starts_with("<!DOCTYPE") {
+                self.skip_doctype()?;
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    fn skip_doctype(&mut self) -> Result<(), MusicalDataError> {
+        let mut depth = 0;
+        for (i, c) in self.rest().char_indices() {
+            match c {
+                '[' => depth += 1,
+                ']' => depth -= 1,
+                '>' if depth == 0 => {
+                    self.pos += i + 1;
+                    return Ok(());
+                },
+                _ => {},
+            }
+        }
+
+        Err(self.error("unterminated doctype"))
+    }
+
+    fn parse_name(&mut self) -> Result<String, MusicalDataError> {
+        let end = self.rest()
+            .find(|c : char| c.is_whitespace() || c == '>' || c == '/' || c == '=')
+            .unwrap_or(self.rest().len());
+        if end == 0 {
+            return Err(self.error("expected a name"));
+        }
+
+        let name = String::from(&self.rest()[..end]);
+        self.pos += end;
+        Ok(name)
+    }
+
+    fn expect(&mut self, s : &str) -> Result<(), MusicalDataError> {
+        if !self.rest().starts_with(s) {
+            return Err(self.error(&format!("expected '{}'", s)));
+        }
+
+        self.pos += s.len();
+        Ok(())
+    }
+
+    fn parse_element(&mut self) -> Result<XmlElement, MusicalDataError> {
+        self.expect("<")?;
+        let mut element = XmlElement::new(&self.parse_name()?);
+
+        loop {
+            self.skip_whitespace();
+            if self.rest().starts_with("/>") {
+                self.pos += 2;
+                return Ok(element);
+            }
+
+            if self.rest().starts_with('>') {
+                self.pos += 1;
+                break;
+            }
+
+            let name = self.parse_name()?;
+            self.skip_whitespace();
+            self.expect("=")?;
+            self.skip_whitespace();
+            let quote = match self.rest().chars().next() {
+                Some(q) if q == '"' || q == '\'' => q,
+                _ => return Err(self.error("expected a quoted attribute value")),
+            };
+            self.pos += 1;
+            let end = match self.rest().find(quote) {
+                Some(end) => end,
+                None => return Err(self.error("unterminated attribute value")),
+            };
+            let value = unescape(&self.rest()[..end]);
+            self.pos += end + 1;
+            element.attributes.push((name, value));
+        }
+
+        loop {
+            if self.rest().is_empty() {
+                return Err(self.error(&format!("unterminated element '{}'", element.name)));
+            }
+
+            if self.rest().starts_with("</") {
+                self.pos += 2;
+                let name = self.parse_name()?;
+                if name != element.name {
+                    return Err(self.error(&format!("expected '</{}>' but found '</{}>'", element.name, name)));
+                }
+                self.skip_whitespace();
+                self.expect(">")?;
+                return Ok(element);
+            } else if self.rest().starts_with("<!--") {
+                self.skip_until("-->")?;
+            } else if self.rest().starts_with("<?") {
+                self.skip_until("?>")?;
+            } else if self.rest().starts_with("<![CDATA[") {
+                self.pos += "<![CDATA[".len();
+                let end = match self.rest().find("]]>") {
+                    Some(end) => end,
+                    None => return Err(self.error("unterminated CDATA section")),
+                };
+                element.children.push(XmlNode::Text(String::from(&self.rest()[..end])));
+                self.pos += end + 3;
+            } else if self.rest().starts_with('<') {
+                let child = self.parse_element()?;
+                element.children.push(XmlNode::Element(child));
+            } else {
+                // Whitespace between elements is only indentation.
+                let end = self.rest().find('<').unwrap_or(self.rest().len());
+                let text = &self.rest()[..end];
+                if !text.trim().is_empty() {
+                    element.children.push(XmlNode::Text(unescape(text)));
+                }
+                self.pos += end;
+            }
+        }
+    }
+}
+
+/// Replace the predefined entities and character references.
+fn unescape(s : &str) -> String {
+    if !s.contains('&') {
+        return String::from(s);
+    }
+
+    let mut result = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find('&') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = match rest.find(';') {
+            Some(end) => end,
+            None => break,
+        };
+
+        let entity = &rest[1..end];
+        let replacement = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ if entity.starts_with("#x") => u32::from_str_radix(&entity[2..], 16).ok().and_then(char::from_u32),
+            _ if entity.starts_with('#') => entity[1..].parse::<u32>().ok().and_then(char::from_u32),
+            _ => None,
+        };
+
+        match replacement {
+            Some(c) => {
+                result.push(c);
+                rest = &rest[end + 1..];
+            },
+            None => {
+                result.push('&');
+                rest = &rest[1..];
+            },
+        }
+    }
+    result.push_str(rest);
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_nested_elements() {
+        let doc = parse("<?xml version=\"1.0\"?>\n<!DOCTYPE a PUBLIC \"x\" \"y\">\n<a x='1'><b>text</b><!-- c --><c/></a>").unwrap();
+        assert_eq!(doc.name, "a");
+        assert_eq!(doc.attribute("x"), Some("1"));
+        assert_eq!(doc.child_text("b"), Some(String::from("text")));
+        assert!(doc.has_child("c"));
+        assert_eq!(doc.elements().count(), 2);
+    }
+
+    #[test]
+    fn test_parse_entities() {
+        let doc = parse("<a t=\"&quot;x&quot;\">A &amp; B &#65;&#x42;</a>").unwrap();
+        assert_eq!(doc.attribute("t"), Some("\"x\""));
+        assert_eq!(doc.text(), "A & B AB");
+    }
+
+    #[test]
+    fn test_write_and_parse() {
+        let mut root = XmlElement::new("a");
+        root.set_attribute("x", "\"1\"");
+        root.push_text("b", "A & B");
+        root.push(XmlElement::new("c"));
+
+        let doc = write_document(&root, None);
+        assert_eq!(parse(&doc).unwrap(), root);
+    }
+
+    #[test]
+    fn test_mismatched_tags() {
+        assert!(parse("<a><b></a></b>").is_err());
+        assert!(parse("<a>").is_err());
+    }
+}